@@ -0,0 +1,120 @@
+use clap::{Parser, Subcommand};
+
+/// Talks to Quectel BLE devices to take them off the vendor cloud
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Bluetooth adapter to use, by name (e.g. `hci1`) or address. Defaults to the
+    /// system default adapter
+    #[arg(long, global = true)]
+    pub adapter: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List advertising Quectel devices
+    Scan {
+        /// How long to listen for advertisements, in seconds
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Send wifi credentials and the MQTT endpoint to the first device found
+    Pair {
+        #[arg(long)]
+        ssid: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long)]
+        mqtt_url: String,
+        /// Set the device clock right after the login
+        #[arg(long)]
+        sync_time: bool,
+    },
+    /// Print the firmware and component versions of the first device found
+    Read,
+    /// Stream decoded frames from the first device found until interrupted
+    Monitor,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["ble"].iter().chain(args))
+    }
+
+    #[test]
+    fn scan_defaults_to_ten_seconds() {
+        let cli = parse(&["scan"]).unwrap();
+
+        assert!(matches!(cli.command, Command::Scan { timeout: 10 }));
+        assert_eq!(cli.adapter, None);
+    }
+
+    #[test]
+    fn scan_takes_a_timeout() {
+        let cli = parse(&["scan", "--timeout", "3"]).unwrap();
+
+        assert!(matches!(cli.command, Command::Scan { timeout: 3 }));
+    }
+
+    #[test]
+    fn pair_reads_the_credentials() {
+        let cli = parse(&[
+            "pair",
+            "--ssid",
+            "home",
+            "--password",
+            "secret",
+            "--mqtt-url",
+            "mqtt://10.0.0.2:1883",
+        ])
+        .unwrap();
+
+        let Command::Pair {
+            ssid,
+            password,
+            mqtt_url,
+            sync_time,
+        } = cli.command
+        else {
+            panic!("not pair: {:?}", cli.command);
+        };
+        assert_eq!(ssid, "home");
+        assert_eq!(password, "secret");
+        assert_eq!(mqtt_url, "mqtt://10.0.0.2:1883");
+        assert!(!sync_time);
+    }
+
+    #[test]
+    fn pair_needs_every_credential() {
+        assert!(parse(&["pair", "--ssid", "home", "--password", "secret"]).is_err());
+    }
+
+    #[test]
+    fn read_and_monitor_take_no_arguments() {
+        let read = parse(&["read"]).unwrap();
+        let monitor = parse(&["monitor"]).unwrap();
+
+        assert!(matches!(read.command, Command::Read));
+        assert!(matches!(monitor.command, Command::Monitor));
+        assert!(parse(&["read", "--timeout", "3"]).is_err());
+    }
+
+    #[test]
+    fn adapter_is_accepted_after_the_subcommand() {
+        let cli = parse(&["monitor", "--adapter", "hci1"]).unwrap();
+
+        assert_eq!(cli.adapter.as_deref(), Some("hci1"));
+    }
+
+    #[test]
+    fn a_subcommand_is_required() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["unpair"]).is_err());
+    }
+}
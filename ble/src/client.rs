@@ -0,0 +1,160 @@
+use bluer::{Adapter, AdapterEvent, Address, DiscoveryFilter, Result};
+use futures::{Stream, StreamExt};
+
+use unquec_model::{
+    commands::TtlvCommandModel, connection::SessionOptions, quec_ble_device::QuecBLEDevice,
+    ttlv::decode::DecodeResult,
+};
+
+use crate::{connection::QuecConnection, link::Link, quec_device_at};
+
+/// Reusable entry point for talking to Quectel devices: discover them, connect to one,
+/// then exchange commands as decoded frames. Encoding, decoding and the retried writes go
+/// through the `QuecConnection`
+pub struct BleClient {
+    adapter: Adapter,
+    session_options: SessionOptions,
+    connection: Option<QuecConnection>,
+}
+
+impl BleClient {
+    pub fn new(adapter: Adapter) -> Self {
+        Self {
+            adapter,
+            session_options: SessionOptions::default(),
+            connection: None,
+        }
+    }
+
+    /// What connections opened from now on do on their own after the login
+    pub fn with_session_options(mut self, options: SessionOptions) -> Self {
+        self.session_options = options;
+        self
+    }
+
+    pub fn adapter(&self) -> &Adapter {
+        &self.adapter
+    }
+
+    /// Quectel devices as they advertise, with name and address filled in. Discovery runs
+    /// until the stream is dropped; other advertisers are skipped
+    pub async fn discover(
+        adapter: &Adapter,
+        filter: DiscoveryFilter,
+    ) -> Result<impl Stream<Item = QuecBLEDevice> + use<>> {
+        println!(
+            "Discovering on Bluetooth adapter {} with address {}\n",
+            adapter.name(),
+            adapter.address().await?
+        );
+
+        adapter.set_discovery_filter(filter).await?;
+        let events = adapter.discover_devices().await?;
+
+        let adapter = adapter.clone();
+        Ok(events.filter_map(move |evt| {
+            let adapter = adapter.clone();
+            async move {
+                let AdapterEvent::DeviceAdded(addr) = evt else {
+                    return None;
+                };
+
+                match quec_device_at(&adapter, addr).await {
+                    Ok(found) => found.map(|(_, quec_device)| quec_device),
+                    Err(err) => {
+                        println!("Skipping device {}: {}", addr, err);
+                        None
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Connects to a device found by `discover`, closing any previous connection first
+    pub async fn connect(&mut self, quec_device: &QuecBLEDevice) -> Result<&mut QuecConnection> {
+        if let Some(connection) = self.connection.take() {
+            connection.close().await?;
+        }
+
+        let addr: Address = quec_device.mac.parse().map_err(|_| bluer::Error {
+            kind: bluer::ErrorKind::InvalidArguments,
+            message: format!("Invalid device address {:?}", quec_device.mac),
+        })?;
+        let device = self.adapter.device(addr)?;
+        device.set_blocked(false).await?;
+        device.set_trusted(true).await?;
+
+        let connection = QuecConnection::open(
+            &self.adapter,
+            device,
+            quec_device.clone(),
+            self.session_options,
+        )
+        .await?;
+        Ok(self.connection.insert(connection))
+    }
+
+    pub fn connection(&self) -> Option<&QuecConnection> {
+        self.connection.as_ref()
+    }
+
+    pub fn connection_mut(&mut self) -> Option<&mut QuecConnection> {
+        self.connection.as_mut()
+    }
+
+    fn connected(&mut self) -> Result<&mut QuecConnection> {
+        self.connection.as_mut().ok_or_else(|| bluer::Error {
+            kind: bluer::ErrorKind::Failed,
+            message: "Not connected, call connect first".to_string(),
+        })
+    }
+
+    /// Next decoded frame from the connected device, `None` once notifications end
+    pub async fn recv(&mut self) -> Result<Option<DecodeResult>> {
+        Ok(self.connected()?.recv().await)
+    }
+
+    /// Encodes `model` with the next packet id and writes it, retrying failed writes
+    pub async fn send(&mut self, model: &TtlvCommandModel) -> Result<()> {
+        self.connected()?.send_command(model).await
+    }
+
+    /// Like `send`, but keeps `model.packet_id`, for acks the device matches by packet id
+    pub async fn reply(&mut self, model: &TtlvCommandModel) -> Result<()> {
+        self.connected()?.send_reply(model).await
+    }
+
+    /// Disconnects the current device, if any
+    pub async fn disconnect(&mut self) -> Result<()> {
+        match self.connection.take() {
+            Some(connection) => connection.close().await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn close(mut self) -> Result<()> {
+        self.disconnect().await
+    }
+}
+
+impl Link for BleClient {
+    async fn send(&mut self, model: &TtlvCommandModel) -> Result<()> {
+        BleClient::send(self, model).await
+    }
+
+    async fn reply(&mut self, model: &TtlvCommandModel) -> Result<()> {
+        BleClient::reply(self, model).await
+    }
+
+    async fn recv(&mut self) -> Result<Option<DecodeResult>> {
+        BleClient::recv(self).await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        BleClient::disconnect(self).await
+    }
+
+    fn session_options(&self) -> SessionOptions {
+        self.session_options
+    }
+}
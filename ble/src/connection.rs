@@ -0,0 +1,487 @@
+use std::time::Duration;
+
+use bluer::{Adapter, Device, Result, gatt::remote::Characteristic};
+use futures::{StreamExt, stream::BoxStream};
+use tokio::time::sleep;
+
+use unquec_model::{
+    commands::{Command, IotCmd, TtlvCommandModel},
+    connection::{
+        CommandLog, CommandLogEntry, ConnectionGuard, ConnectionState, Direction, PacketTracker,
+        SessionOptions, WrongState,
+    },
+    device_info::DeviceInfo,
+    quec_ble_device::QuecBLEDevice,
+    ttlv::{decode::DecodeResult, encode::EncodeTools},
+};
+
+use crate::{
+    CHARACTERISTIC_UUID, CONNECT_TIMEOUT, DATA_CHARACTERISTIC_UUID, MIN_WRITE_INTERVAL,
+    WriteOptions, connect_to_device, encode_error, find_our_characteristic,
+    gatt::GattCharacteristic, link::Link, reader::FrameReader, throttle::WriteThrottle, wifi,
+    write_to_characteristic_with,
+};
+
+/// How long `refresh_device_info` waits for the device info response
+const DEVICE_INFO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connected Quectel device and its notification subscription, with the identity
+/// decoded from its advertisement.
+///
+/// Call `close().await` when done: `Drop` cannot wait for the disconnect, so a connection
+/// dropped unclosed only logs a warning and disconnects from a detached task
+pub struct QuecConnection {
+    device: Device,
+    characteristic: Characteristic,
+    data_characteristic: Option<Characteristic>,
+    device_info: QuecBLEDevice,
+    reported_info: Option<DeviceInfo>,
+    encode_tools: EncodeTools,
+    command_log: CommandLog,
+    packet_tracker: PacketTracker,
+    guard: ConnectionGuard,
+    session_options: SessionOptions,
+    throttle: WriteThrottle,
+    reader: FrameReader<BoxStream<'static, Vec<u8>>>,
+    closed: bool,
+}
+
+impl QuecConnection {
+    /// Connects to `device`, looks up the protocol characteristic and subscribes to its
+    /// notifications, enabling them through the CCCD if BlueZ refuses. The file/OTA data
+    /// characteristic is looked up too, if the module has one.
+    ///
+    /// The subscription is made before any command can be sent, and notifications that
+    /// arrive before the first `recv` stay queued on it. `session_options` tells the login
+    /// flow what to send once the device accepted it
+    pub async fn open(
+        adapter: &Adapter,
+        device: Device,
+        device_info: QuecBLEDevice,
+        session_options: SessionOptions,
+    ) -> Result<Self> {
+        match connect_to_device(&device, CONNECT_TIMEOUT).await {
+            Ok(()) => println!("Device connected"),
+            Err(err) => {
+                println!("Device connection failed: {}", &err);
+                return Err(err);
+            }
+        }
+
+        let characteristic =
+            match find_our_characteristic(&device, CHARACTERISTIC_UUID, CONNECT_TIMEOUT).await {
+                Ok(Some(char)) => char,
+                Ok(None) => {
+                    println!("    Not found!");
+                    return Err(bluer::Error {
+                        kind: bluer::ErrorKind::NotFound,
+                        message: "Characteristic not found".to_string(),
+                    });
+                }
+                Err(err) => {
+                    println!("    Device failed: {}", &err);
+                    let _ = adapter.remove_device(device.address()).await;
+                    return Err(err);
+                }
+            };
+
+        let data_characteristic =
+            match find_our_characteristic(&device, DATA_CHARACTERISTIC_UUID, CONNECT_TIMEOUT).await
+            {
+                Ok(char) => char,
+                Err(err) => {
+                    println!("    Data characteristic lookup failed: {}", &err);
+                    None
+                }
+            };
+
+        device.set_trusted(true).await?;
+
+        sleep(Duration::from_secs(1)).await;
+
+        println!("Trying notify...");
+        let notifications = subscribe(&characteristic).await?;
+
+        Ok(Self {
+            device,
+            characteristic,
+            data_characteristic,
+            device_info,
+            reported_info: None,
+            encode_tools: EncodeTools::new(),
+            command_log: CommandLog::default(),
+            packet_tracker: PacketTracker::new(),
+            guard: ConnectionGuard::new(),
+            session_options,
+            throttle: WriteThrottle::new(MIN_WRITE_INTERVAL),
+            reader: FrameReader::new(notifications),
+            closed: false,
+        })
+    }
+
+    /// Next decoded frame from the device, `None` once notifications end. Decoded
+    /// commands advance the session state and are noted for `recent_commands`
+    pub async fn recv(&mut self) -> Option<DecodeResult> {
+        let result = self.reader.next().await;
+        if let Some(DecodeResult::Success(model)) = &result {
+            self.log_received(model);
+        }
+        result
+    }
+
+    /// Stop forwarding notifications, e.g. during a bulk write, without unsubscribing
+    pub fn pause_notifications(&mut self) {
+        self.reader.pause();
+    }
+
+    /// Forward notifications again. Whatever arrived while paused is dropped
+    pub fn resume_notifications(&mut self) {
+        self.reader.resume();
+    }
+
+    /// Connects again after the link dropped and subscribes anew. Partial frames from
+    /// before the drop are discarded, the device resends them in full. The device forgets
+    /// the login with the link, so the session state starts over at `Connected` and
+    /// requests still awaiting a response are dropped
+    pub async fn reconnect(&mut self) -> Result<()> {
+        connect_to_device(&self.device, CONNECT_TIMEOUT).await?;
+        let notifications = subscribe(&self.characteristic).await?;
+        self.reader.resubscribe(notifications);
+        self.guard.reset();
+        self.packet_tracker.clear();
+        Ok(())
+    }
+
+    /// Encodes `model` with the next packet id and writes it to the characteristic its
+    /// command is routed to
+    pub async fn send_command(&mut self, model: &TtlvCommandModel) -> Result<()> {
+        self.send_command_with(model, WriteOptions::default()).await
+    }
+
+    /// Like `send_command`, with the GATT write type and authorization given by `options`,
+    /// for characteristics that only accept reliable or authorized writes
+    pub async fn send_command_with(
+        &mut self,
+        model: &TtlvCommandModel,
+        options: WriteOptions,
+    ) -> Result<()> {
+        self.guard.on_send(model.cmd).map_err(wrong_state_error)?;
+        let frame = self
+            .encode_tools
+            .start_encode(model)
+            .map_err(encode_error)?;
+        self.write(model.cmd, frame.get_cmd_data(), options).await?;
+        self.command_log
+            .record(Direction::Sent, model.cmd, frame.get_packet_id() as i32);
+        self.packet_tracker.record(&frame);
+        Ok(())
+    }
+
+    /// Like `send_command`, but keeps `model.packet_id` instead of assigning the next one,
+    /// for acks the device matches to its own frame
+    pub async fn send_reply(&mut self, model: &TtlvCommandModel) -> Result<()> {
+        let frame = self
+            .encode_tools
+            .start_encode_with_packet_id(model, true)
+            .map_err(encode_error)?;
+        self.write(model.cmd, frame.get_cmd_data(), WriteOptions::default())
+            .await?;
+        self.command_log
+            .record(Direction::Sent, model.cmd, frame.get_packet_id() as i32);
+        Ok(())
+    }
+
+    /// The command sent with `packet_id` that a decoded response answers, if it is still
+    /// outstanding
+    pub fn take_request(&mut self, packet_id: i32) -> Option<Command> {
+        self.packet_tracker.take(packet_id)
+    }
+
+    /// Notes a response decoded outside the connection, for `recent_commands` and the
+    /// session state
+    pub fn log_received(&mut self, model: &TtlvCommandModel) {
+        self.command_log
+            .record(Direction::Received, model.cmd, model.packet_id);
+        self.guard.on_receive(model);
+    }
+
+    /// Minimum spacing between writes on this connection, zero (the default) disables
+    /// throttling. Writes wait out the rest of the interval instead of failing
+    pub fn set_min_write_interval(&mut self, interval: Duration) {
+        self.throttle.set_min_interval(interval);
+    }
+
+    /// Writes a frame to the characteristic `cmd` is routed to, once the throttle allows
+    async fn write(&self, cmd: i32, data: &[u8], options: WriteOptions) -> Result<()> {
+        self.throttle.wait().await;
+        write_to_characteristic_with(self.characteristic_for(cmd), data, options).await
+    }
+
+    /// Sends one `FileControl` data chunk, over the data characteristic when available
+    pub async fn send_data_chunk(&mut self, chunk: &TtlvCommandModel) -> Result<()> {
+        if IotCmd::from_i32(chunk.cmd) != Some(IotCmd::FileControl) {
+            return Err(bluer::Error {
+                kind: bluer::ErrorKind::InvalidArguments,
+                message: format!("Not a FileControl chunk: 0x{:04X}", chunk.cmd),
+            });
+        }
+
+        self.send_command(chunk).await
+    }
+
+    /// How far the handshake got, from the commands sent and received so far
+    pub fn state(&self) -> ConnectionState {
+        self.guard.get_state()
+    }
+
+    /// With enforcement on, `send_command` fails with `NotPermitted` for commands the
+    /// device would refuse in the current state, e.g. `WifiPair` before the login. Off by
+    /// default
+    pub fn set_enforce_state(&mut self, enforce: bool) {
+        self.guard.set_enforce(enforce);
+    }
+
+    /// What this session sends on its own after the login
+    pub fn session_options(&self) -> SessionOptions {
+        self.session_options
+    }
+
+    /// The last commands sent and received on this connection, oldest first
+    pub fn recent_commands(&self) -> Vec<CommandLogEntry> {
+        self.command_log.entries()
+    }
+
+    /// How many entries `recent_commands` keeps, 64 by default
+    pub fn set_command_log_capacity(&mut self, capacity: usize) {
+        self.command_log.set_capacity(capacity);
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Disconnects from the device
+    pub async fn close(mut self) -> Result<()> {
+        self.closed = true;
+        self.device.disconnect().await
+    }
+
+    pub fn characteristic(&self) -> &Characteristic {
+        &self.characteristic
+    }
+
+    /// Secondary characteristic for file/OTA data, if the module exposes one
+    pub fn data_characteristic(&self) -> Option<&Characteristic> {
+        self.data_characteristic.as_ref()
+    }
+
+    /// `FileControl` goes to the data characteristic when there is one, everything else
+    /// to the command characteristic
+    fn characteristic_for(&self, cmd: i32) -> &Characteristic {
+        match (IotCmd::from_i32(cmd), &self.data_characteristic) {
+            (Some(IotCmd::FileControl), Some(data_characteristic)) => data_characteristic,
+            _ => &self.characteristic,
+        }
+    }
+
+    /// Identity from the advertisement the connection was opened from
+    pub fn device_info(&self) -> &QuecBLEDevice {
+        &self.device_info
+    }
+
+    /// Firmware details from the last successful `refresh_device_info`
+    pub fn reported_info(&self) -> Option<&DeviceInfo> {
+        self.reported_info.as_ref()
+    }
+
+    /// Queries the device with `ReadDeviceInfo` and keeps the response next to the
+    /// advertisement data. `None` if the device did not answer in time. Other frames
+    /// arriving meanwhile are dropped
+    pub async fn refresh_device_info(&mut self) -> Result<Option<&DeviceInfo>> {
+        let read_model = TtlvCommandModel::new(IotCmd::ReadDeviceInfo.as_i32(), 0);
+        self.send_command(&read_model).await?;
+
+        let response = async {
+            while let Some(result) = self.recv().await {
+                if let DecodeResult::Success(model) = result
+                    && IotCmd::from_i32(model.cmd) == Some(IotCmd::ReadDeviceInfoAck)
+                {
+                    return Some(DeviceInfo::from_model(&model));
+                }
+            }
+
+            None
+        };
+
+        if let Ok(Some(info)) = tokio::time::timeout(DEVICE_INFO_TIMEOUT, response).await {
+            self.reported_info = Some(info);
+            return Ok(self.reported_info.as_ref());
+        }
+
+        Ok(None)
+    }
+
+    /// Asks the device for nearby networks and returns their SSIDs, collecting every
+    /// `WifiScanResp` that arrives within `window`, see `wifi::scan_wifi`
+    pub async fn scan_wifi(&mut self, window: Duration) -> Result<Vec<String>> {
+        wifi::scan_wifi(self, window).await
+    }
+}
+
+impl Link for QuecConnection {
+    async fn send(&mut self, model: &TtlvCommandModel) -> Result<()> {
+        self.send_command(model).await
+    }
+
+    async fn reply(&mut self, model: &TtlvCommandModel) -> Result<()> {
+        self.send_reply(model).await
+    }
+
+    async fn recv(&mut self) -> Result<Option<DecodeResult>> {
+        Ok(QuecConnection::recv(self).await)
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        self.closed = true;
+        self.device.disconnect().await
+    }
+
+    fn session_options(&self) -> SessionOptions {
+        self.session_options
+    }
+}
+
+impl Drop for QuecConnection {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+
+        log::warn!(
+            "QuecConnection to {} dropped without close(), disconnecting in the background",
+            self.device.address()
+        );
+
+        // Outside a runtime there is nothing to run the disconnect on
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let device = self.device.clone();
+            handle.spawn(async move {
+                if let Err(err) = device.disconnect().await {
+                    log::warn!("Background disconnect failed: {}", err);
+                }
+            });
+        }
+    }
+}
+
+fn wrong_state_error(err: WrongState) -> bluer::Error {
+    bluer::Error {
+        kind: bluer::ErrorKind::NotPermitted,
+        message: format!(
+            "Command 0x{:04X} not allowed in state {:?}",
+            err.cmd, err.state
+        ),
+    }
+}
+
+/// Subscribes to `characteristic`. Fails with `NotSupported` if it has neither the notify
+/// nor the indicate flag; if BlueZ refuses to start notifying, falls back to enabling
+/// notifications through the CCCD
+async fn subscribe(
+    characteristic: &impl GattCharacteristic,
+) -> Result<BoxStream<'static, Vec<u8>>> {
+    let flags = characteristic.flags().await?;
+    if !flags.notify && !flags.indicate {
+        return Err(bluer::Error {
+            kind: bluer::ErrorKind::NotSupported,
+            message: "Characteristic supports neither notify nor indicate".to_string(),
+        });
+    }
+
+    match characteristic.notify().await {
+        Ok(notify) => Ok(notify),
+        Err(err) => {
+            println!("    Notify failed: {}, enabling via CCCD", &err);
+            characteristic.notify_via_cccd(flags.notify).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bluer::gatt::CharacteristicFlags;
+
+    use unquec_model::{commands::Cmd, ttlv::model::TTLVData};
+
+    use super::*;
+    use crate::gatt::MockCharacteristic;
+
+    fn characteristic(notify: bool, indicate: bool) -> MockCharacteristic {
+        MockCharacteristic {
+            flags: CharacteristicFlags {
+                notify,
+                indicate,
+                ..CharacteristicFlags::default()
+            },
+            values: vec![vec![0xaa, 0x01]],
+            ..MockCharacteristic::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn characteristic_without_notify_or_indicate_is_not_supported() {
+        let characteristic = MockCharacteristic {
+            notify_works: true,
+            ..characteristic(false, false)
+        };
+
+        let err = subscribe(&characteristic).await.err().unwrap();
+
+        assert!(matches!(err.kind, bluer::ErrorKind::NotSupported));
+        assert!(characteristic.cccd_writes.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn working_notify_does_not_touch_the_cccd() {
+        let characteristic = MockCharacteristic {
+            notify_works: true,
+            ..characteristic(true, false)
+        };
+
+        let values: Vec<_> = subscribe(&characteristic).await.unwrap().collect().await;
+
+        assert_eq!(values, [vec![0xaa, 0x01]]);
+        assert!(characteristic.cccd_writes.borrow().is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_notify_falls_back_to_the_cccd() {
+        let characteristic = characteristic(false, true);
+
+        let values: Vec<_> = subscribe(&characteristic).await.unwrap().collect().await;
+
+        assert_eq!(values, [vec![0xaa, 0x01]]);
+        // Indicate only, so the CCCD gets the indication bit
+        assert_eq!(*characteristic.cccd_writes.borrow(), [false]);
+    }
+
+    #[tokio::test]
+    async fn notification_before_the_first_read_is_kept() {
+        let mut model = TtlvCommandModel::new(Cmd::RandomResp.as_i32(), 0);
+        model.add_payload(TTLVData::new(1, 3, true).with_binary(b"1234".to_vec()));
+        let frame = EncodeTools::new().start_encode(&model).unwrap();
+
+        // Sent by the device while the CCCD was being enabled, nothing has been read yet
+        let characteristic = MockCharacteristic {
+            values: vec![frame.get_cmd_data().to_vec()],
+            ..characteristic(true, false)
+        };
+        let mut reader = FrameReader::new(subscribe(&characteristic).await.unwrap());
+
+        match reader.next().await {
+            Some(DecodeResult::Success(decoded)) => assert_eq!(decoded.cmd, model.cmd),
+            other => panic!("unexpected decode: {:?}", other),
+        }
+        assert_eq!(*characteristic.cccd_writes.borrow(), [true]);
+    }
+}
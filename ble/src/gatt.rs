@@ -0,0 +1,241 @@
+use bluer::{
+    Device, Result,
+    gatt::{
+        CharacteristicFlags,
+        remote::{Characteristic, CharacteristicProperty, Service},
+    },
+};
+use futures::{StreamExt, stream::BoxStream};
+use uuid::Uuid;
+
+use crate::CCCD_UUID;
+
+/// The service list of a connected device, what `find_our_characteristic` walks.
+/// `Device` asks BlueZ, tests script one with `MockDevice`
+pub trait GattDevice {
+    type Service: GattService<Characteristic = Self::Characteristic>;
+    type Characteristic: GattCharacteristic;
+
+    /// Empty while BlueZ has not resolved the services yet
+    async fn services(&self) -> Result<Vec<Self::Service>>;
+}
+
+/// A resolved service and the characteristics under it
+pub trait GattService {
+    type Characteristic;
+
+    async fn uuid(&self) -> Result<Uuid>;
+
+    async fn characteristics(&self) -> Result<Vec<Self::Characteristic>>;
+}
+
+/// What `find_our_characteristic` and `subscribe` need from a characteristic.
+/// `Characteristic` asks BlueZ, tests script one with `MockCharacteristic`
+pub trait GattCharacteristic {
+    async fn uuid(&self) -> Result<Uuid>;
+
+    /// Every D-Bus property, for logging
+    async fn properties(&self) -> Result<Vec<CharacteristicProperty>>;
+
+    async fn flags(&self) -> Result<CharacteristicFlags>;
+
+    /// Notifications through BlueZ's `StartNotify`
+    async fn notify(&self) -> Result<BoxStream<'static, Vec<u8>>>;
+
+    /// Notifications (or indications if `notify` is false) enabled by writing the CCCD
+    /// directly, for devices where `StartNotify` fails
+    async fn notify_via_cccd(&self, notify: bool) -> Result<BoxStream<'static, Vec<u8>>>;
+}
+
+impl GattDevice for Device {
+    type Service = Service;
+    type Characteristic = Characteristic;
+
+    async fn services(&self) -> Result<Vec<Service>> {
+        Device::services(self).await
+    }
+}
+
+impl GattService for Service {
+    type Characteristic = Characteristic;
+
+    async fn uuid(&self) -> Result<Uuid> {
+        Service::uuid(self).await
+    }
+
+    async fn characteristics(&self) -> Result<Vec<Characteristic>> {
+        Service::characteristics(self).await
+    }
+}
+
+impl GattCharacteristic for Characteristic {
+    async fn uuid(&self) -> Result<Uuid> {
+        Characteristic::uuid(self).await
+    }
+
+    async fn properties(&self) -> Result<Vec<CharacteristicProperty>> {
+        self.all_properties().await
+    }
+
+    async fn flags(&self) -> Result<CharacteristicFlags> {
+        Characteristic::flags(self).await
+    }
+
+    async fn notify(&self) -> Result<BoxStream<'static, Vec<u8>>> {
+        Ok(Characteristic::notify(self).await?.boxed())
+    }
+
+    /// Writes the enable bits to the CCCD and reads values from the acquired notification
+    /// socket
+    async fn notify_via_cccd(&self, notify: bool) -> Result<BoxStream<'static, Vec<u8>>> {
+        let mut cccd = None;
+        for descriptor in self.descriptors().await? {
+            if descriptor.uuid().await? == CCCD_UUID {
+                cccd = Some(descriptor);
+                break;
+            }
+        }
+
+        let Some(cccd) = cccd else {
+            return Err(bluer::Error {
+                kind: bluer::ErrorKind::NotFound,
+                message: "Characteristic has no CCCD".to_string(),
+            });
+        };
+
+        // Bit 0 enables notifications, bit 1 indications, little endian
+        let value: &[u8] = if notify { &[0x01, 0x00] } else { &[0x02, 0x00] };
+        cccd.write(value).await?;
+
+        let reader = self.notify_io().await?;
+        Ok(futures::stream::unfold(reader, |mut reader| async move {
+            let value = reader.recv().await.ok()?;
+            Some((value, reader))
+        })
+        .boxed())
+    }
+}
+
+#[cfg(test)]
+pub use mock::{MockCharacteristic, MockDevice, MockService};
+
+#[cfg(test)]
+mod mock {
+    use std::{
+        cell::{Cell, RefCell},
+        collections::VecDeque,
+        rc::Rc,
+    };
+
+    use bluer::{
+        Result,
+        gatt::{CharacteristicFlags, remote::CharacteristicProperty},
+    };
+    use futures::stream::{self, BoxStream, StreamExt};
+    use uuid::Uuid;
+
+    use super::{GattCharacteristic, GattDevice, GattService};
+
+    /// Answers each `services` call with the next scripted list, the last one repeats
+    pub struct MockDevice {
+        services: RefCell<VecDeque<Vec<MockService>>>,
+        /// How often `services` was asked
+        pub lookups: Cell<usize>,
+    }
+
+    impl MockDevice {
+        pub fn new(services: impl IntoIterator<Item = Vec<MockService>>) -> Self {
+            Self {
+                services: RefCell::new(services.into_iter().collect()),
+                lookups: Cell::new(0),
+            }
+        }
+    }
+
+    impl GattDevice for MockDevice {
+        type Service = MockService;
+        type Characteristic = MockCharacteristic;
+
+        async fn services(&self) -> Result<Vec<MockService>> {
+            self.lookups.set(self.lookups.get() + 1);
+            let mut services = self.services.borrow_mut();
+            match services.len() {
+                0 => Ok(Vec::new()),
+                1 => Ok(services[0].clone()),
+                _ => Ok(services.pop_front().unwrap_or_default()),
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct MockService {
+        pub uuid: Uuid,
+        pub characteristics: Vec<MockCharacteristic>,
+    }
+
+    impl GattService for MockService {
+        type Characteristic = MockCharacteristic;
+
+        async fn uuid(&self) -> Result<Uuid> {
+            Ok(self.uuid)
+        }
+
+        async fn characteristics(&self) -> Result<Vec<MockCharacteristic>> {
+            Ok(self.characteristics.clone())
+        }
+    }
+
+    /// A characteristic with the given flags. `notify` fails unless `notify_works`, the CCCD
+    /// fallback always works; both yield `values`
+    #[derive(Clone, Default)]
+    pub struct MockCharacteristic {
+        pub uuid: Uuid,
+        pub flags: CharacteristicFlags,
+        pub notify_works: bool,
+        pub values: Vec<Vec<u8>>,
+        /// The `notify` argument of every `notify_via_cccd` call
+        pub cccd_writes: Rc<RefCell<Vec<bool>>>,
+    }
+
+    impl MockCharacteristic {
+        pub fn new(uuid: Uuid) -> Self {
+            Self {
+                uuid,
+                ..Self::default()
+            }
+        }
+
+        fn stream(&self) -> BoxStream<'static, Vec<u8>> {
+            stream::iter(self.values.clone()).boxed()
+        }
+    }
+
+    impl GattCharacteristic for MockCharacteristic {
+        async fn uuid(&self) -> Result<Uuid> {
+            Ok(self.uuid)
+        }
+
+        async fn properties(&self) -> Result<Vec<CharacteristicProperty>> {
+            Ok(Vec::new())
+        }
+
+        async fn flags(&self) -> Result<CharacteristicFlags> {
+            Ok(self.flags)
+        }
+
+        async fn notify(&self) -> Result<BoxStream<'static, Vec<u8>>> {
+            if !self.notify_works {
+                return Err(bluer::Error {
+                    kind: bluer::ErrorKind::Failed,
+                    message: "StartNotify failed".to_string(),
+                });
+            }
+            Ok(self.stream())
+        }
+
+        async fn notify_via_cccd(&self, notify: bool) -> Result<BoxStream<'static, Vec<u8>>> {
+            self.cccd_writes.borrow_mut().push(notify);
+            Ok(self.stream())
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use bluer::Result;
+
+use unquec_model::{
+    commands::TtlvCommandModel, connection::SessionOptions, ttlv::decode::DecodeResult,
+};
+
+/// Commands out, decoded frames in: what the protocol flows need from a device connection.
+/// `BleClient` talks to a real device, tests script one with `Loopback`
+pub trait Link {
+    /// Encodes `model` with the next packet id and writes it
+    async fn send(&mut self, model: &TtlvCommandModel) -> Result<()>;
+
+    /// Like `send`, but keeps `model.packet_id`, for acks the device matches by packet id
+    async fn reply(&mut self, model: &TtlvCommandModel) -> Result<()>;
+
+    /// Next decoded frame, `None` once notifications end
+    async fn recv(&mut self) -> Result<Option<DecodeResult>>;
+
+    async fn disconnect(&mut self) -> Result<()>;
+
+    /// What to send on our own once the device accepted the login
+    fn session_options(&self) -> SessionOptions;
+}
+
+#[cfg(test)]
+pub use loopback::Loopback;
+
+#[cfg(test)]
+mod loopback {
+    use std::collections::VecDeque;
+
+    use bluer::Result;
+
+    use unquec_model::{
+        commands::TtlvCommandModel,
+        connection::SessionOptions,
+        ttlv::{
+            decode::{DecodeResult, DecodeTools},
+            encode::EncodeTools,
+        },
+    };
+
+    use super::Link;
+
+    type Device = Box<dyn FnMut(&TtlvCommandModel) -> Vec<TtlvCommandModel>>;
+
+    /// A scripted device on the far end of a full encode/decode round trip. Every command
+    /// is framed, decoded as the device would see it and passed to `device`, whose answers
+    /// are framed with the request's packet id and decoded on the way back. With nothing
+    /// left to read, `recv` waits forever like a silent device
+    pub struct Loopback {
+        device: Device,
+        host_encoder: EncodeTools,
+        host_decoder: DecodeTools,
+        device_encoder: EncodeTools,
+        device_decoder: DecodeTools,
+        to_host: VecDeque<Vec<u8>>,
+        pending: VecDeque<DecodeResult>,
+        /// Commands as the device decoded them, in order
+        pub received: Vec<TtlvCommandModel>,
+        pub connected: bool,
+        pub session_options: SessionOptions,
+    }
+
+    impl Loopback {
+        pub fn new(
+            device: impl FnMut(&TtlvCommandModel) -> Vec<TtlvCommandModel> + 'static,
+        ) -> Self {
+            Self {
+                device: Box::new(device),
+                host_encoder: EncodeTools::new(),
+                host_decoder: DecodeTools::new(),
+                device_encoder: EncodeTools::new(),
+                device_decoder: DecodeTools::new(),
+                to_host: VecDeque::new(),
+                pending: VecDeque::new(),
+                received: Vec::new(),
+                connected: true,
+                session_options: SessionOptions::default(),
+            }
+        }
+
+        /// Commands the device received, by command id
+        pub fn received_cmds(&self) -> Vec<i32> {
+            self.received.iter().map(|model| model.cmd).collect()
+        }
+
+        fn deliver(&mut self, model: &TtlvCommandModel, keep_packet_id: bool) -> Result<()> {
+            let frame = self
+                .host_encoder
+                .start_encode_with_packet_id(model, keep_packet_id)
+                .map_err(crate::encode_error)?;
+
+            for result in self.device_decoder.packet_slice(frame.get_cmd_data()) {
+                let DecodeResult::Success(request) = result else {
+                    panic!("device could not decode {:?}", result);
+                };
+
+                for mut response in (self.device)(&request) {
+                    response.packet_id = request.packet_id;
+                    let frame = self
+                        .device_encoder
+                        .start_encode_with_packet_id(&response, true)
+                        .map_err(crate::encode_error)?;
+                    self.to_host.push_back(frame.get_cmd_data().to_vec());
+                }
+                self.received.push(request);
+            }
+            Ok(())
+        }
+    }
+
+    impl Link for Loopback {
+        async fn send(&mut self, model: &TtlvCommandModel) -> Result<()> {
+            self.deliver(model, false)
+        }
+
+        async fn reply(&mut self, model: &TtlvCommandModel) -> Result<()> {
+            self.deliver(model, true)
+        }
+
+        async fn recv(&mut self) -> Result<Option<DecodeResult>> {
+            loop {
+                if !self.connected {
+                    return Ok(None);
+                }
+                if let Some(result) = self.pending.pop_front() {
+                    return Ok(Some(result));
+                }
+                match self.to_host.pop_front() {
+                    Some(frame) => self.pending.extend(self.host_decoder.packet_slice(&frame)),
+                    None => std::future::pending::<()>().await,
+                }
+            }
+        }
+
+        async fn disconnect(&mut self) -> Result<()> {
+            self.connected = false;
+            Ok(())
+        }
+
+        fn session_options(&self) -> SessionOptions {
+            self.session_options
+        }
+    }
+}
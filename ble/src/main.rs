@@ -1,260 +1,279 @@
 use bluer::{
-    AdapterEvent, Address, Device, DiscoveryFilter, DiscoveryTransport, Result,
+    Adapter, Address, Device, DiscoveryFilter, DiscoveryTransport, Result,
     gatt::{
         WriteOp,
         remote::{Characteristic, CharacteristicWriteRequest},
     },
 };
 use futures::{StreamExt, pin_mut};
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-    time::Duration,
-};
+use std::{collections::HashMap, time::Duration};
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 use unquec_model::{
-    commands::{Cmd, IotCmd, TtlvCommandModel},
-    quec_ble_device::QuecBLEDevice,
+    auth::{HashAlgo, LoginStateMachine},
+    commands::{Cmd, IotCmd, TtlvCommandModel, build_data_report_ack},
+    connection::SessionOptions,
+    quec_ble_device::{QuecBLEDevice, QuecBLEDeviceDecodeError},
     ttlv::{
         decode::{DecodeResult, DecodeTools},
-        encode::EncodeTools,
-        model::{TTLVData, TTLVValue},
+        dedup::DedupFilter,
+        model::{EncodeError, TTLVValue},
     },
+    wifi::WifiPairConfig,
 };
 
-use base64::{Engine as _, engine::general_purpose::STANDARD as b64};
-
-
+mod cli;
+mod client;
+mod connection;
+mod gatt;
+mod link;
+mod pairing;
+mod reader;
+mod retry;
+mod throttle;
+mod wifi;
+
+use clap::Parser;
+use cli::Cli;
+use client::BleClient;
+use gatt::{GattCharacteristic, GattDevice, GattService};
+use link::Link;
+use pairing::run_pairing;
+use retry::{RetryPolicy, retry};
 
 fn try_get_quec_device(
     name: &Option<String>,
     address: &Address,
     manufacturer_data: &HashMap<u16, Vec<u8>>,
-) -> Option<QuecBLEDevice> {
-    match manufacturer_data.get(&0x55_51 /* QU */) {
-        Some(d) => {
-            if !(d.len() >= 19 && d[0] == b'E' && d[1] == b'C') {
-                return None;
-            };
+) -> std::result::Result<QuecBLEDevice, QuecBLEDeviceDecodeError> {
+    let mut quec_device = QuecBLEDevice::from_manufacturer_data(manufacturer_data)?;
 
-            match QuecBLEDevice::decode_data(&d) {
-                Ok(mut quec_device) => {
-                    if name.is_some() {
-                        quec_device.name = name.clone().unwrap();
-                    }
+    if let Some(name) = name {
+        quec_device.name = name.clone();
+    }
 
-                    quec_device.mac = address.to_string();
+    quec_device.mac = address.to_string();
 
-                    return Some(quec_device);
-                }
+    Ok(quec_device)
+}
 
-                Err(err) => {
-                    println!("Error decoding manufacturer data: {:?}", &err);
-                    return None;
-                }
-            }
-        }
-        _ => {
-            return None;
-        }
-    };
+/// How long connecting, or enumerating services once connected, may take before giving up.
+/// Some devices advertise but never accept a GATT connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Runs `fut`, failing with "connect timed out" if it is not done within `timeout`
+async fn with_connect_timeout<T>(
+    timeout: Duration,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::time::timeout(timeout, fut)
+        .await
+        .unwrap_or_else(|_| {
+            Err(bluer::Error {
+                kind: bluer::ErrorKind::Failed,
+                message: "connect timed out".to_string(),
+            })
+        })
 }
 
-async fn connect_to_device(device: &Device) -> Result<()> {
+/// Connects unless already connected, the retries together must finish within `timeout`
+async fn connect_to_device(device: &Device, timeout: Duration) -> Result<()> {
     if !device.is_connected().await? {
         println!("    Connecting...");
-        let mut retries = 2;
-        loop {
-            match device.connect().await {
-                Ok(()) => break,
-                Err(err) if retries > 0 => {
-                    println!("    Connect error: {}", &err);
-                    retries -= 1;
-                }
-                Err(err) => return Err(err),
-            }
-        }
+        let policy = RetryPolicy::fixed(2, Duration::ZERO);
+        let attempts = retry(&policy, || async move {
+            device
+                .connect()
+                .await
+                .inspect_err(|err| println!("    Connect error: {}", err))
+        });
+        with_connect_timeout(timeout, attempts).await?;
         println!("    Connected");
     } else {
         println!("    Already connected");
     }
 
-    return Ok(());
+    Ok(())
 }
 
 const SERVICE_UUID: Uuid = Uuid::from_u128(0x00000180_a000_1000_8000_00805f9b34fb);
-const CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(0x00009c40_0000_1000_8000_00805f9b34fb);
-
-const CHARACTERISTIC_UUID_2: Uuid = Uuid::from_u128(0x00002902_0000_1000_8000_00805f9b34fb);
+const CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x9c40);
+/// High-throughput characteristic some modules expose for file/OTA data, next to the
+/// command one. Optional, modules without it take file data on `CHARACTERISTIC_UUID`
+const DATA_CHARACTERISTIC_UUID: Uuid = uuid_from_u16(0x9c41);
+
+/// Expands a 16-bit assigned number onto the Bluetooth base UUID
+/// (`0000XXXX-0000-1000-8000-00805f9b34fb`)
+const fn uuid_from_u16(short: u16) -> Uuid {
+    Uuid::from_u128(0x00000000_0000_1000_8000_00805f9b34fb | ((short as u128) << 96))
+}
 
-async fn find_our_characteristic(device: &Device) -> Result<Option<Characteristic>> {
+/// Minimum spacing between writes on a new connection, zero disables throttling. Change
+/// it per connection with `QuecConnection::set_min_write_interval`
+const MIN_WRITE_INTERVAL: Duration = Duration::ZERO;
+
+/// Login digest, switch if the device rejects SHA-256 logins
+const LOGIN_HASH_ALGO: HashAlgo = HashAlgo::Sha256;
+
+/// Base64 binding key for the first login, replaced by the one `WifiPairResp` returns
+const DEFAULT_BINDING_KEY: &str = "3EB24BC7957DB49D";
+
+/// Acknowledge `DeviceDataReport`s as they arrive
+const AUTO_ACK_DATA_REPORTS: bool = true;
+
+/// Drop frames the device retransmits within this window, `None` forwards every frame
+const DEDUP_WINDOW: Option<Duration> = None;
+
+/// Client Characteristic Configuration descriptor, holds the notify/indicate enable bits
+const CCCD_UUID: Uuid = uuid_from_u16(0x2902);
+
+/// Looks up the characteristic matching `target` across all services, custom 128-bit
+/// UUIDs are passed as-is, 16-bit ones can be built with [`uuid_from_u16`].
+///
+/// An empty service list means BlueZ has not resolved the services yet, so it is retried like
+/// an error. If it is still empty afterwards this fails with `ServicesUnresolved` rather than
+/// returning `None`, callers should rediscover instead of treating the characteristic as absent.
+/// Enumeration as a whole fails with "connect timed out" past `timeout`
+async fn find_our_characteristic(
+    device: &Device,
+    target: Uuid,
+    timeout: Duration,
+) -> Result<Option<Characteristic>> {
     let addr = device.address();
     let uuids = device.uuids().await?.unwrap_or_default();
     println!("Discovered device {} with service UUIDs {:?}", addr, &uuids);
+
+    find_characteristic(device, target, timeout).await
+}
+
+/// The service walk behind `find_our_characteristic`
+async fn find_characteristic<D: GattDevice>(
+    device: &D,
+    target: Uuid,
+    timeout: Duration,
+) -> Result<Option<D::Characteristic>> {
     println!("    Enumerating services...");
 
-    let mut retries = 2;
-    let mut services = Vec::new();
+    let lookup = async move {
+        let services = retry(
+            &RetryPolicy::fixed(2, Duration::from_secs(1)),
+            || async move {
+                let services = device
+                    .services()
+                    .await
+                    .inspect_err(|err| println!("    Services error: {}", err))?;
+
+                if services.is_empty() {
+                    println!("    Services not resolved yet");
+                    return Err(bluer::Error {
+                        kind: bluer::ErrorKind::ServicesUnresolved,
+                        message: "Services not resolved".to_string(),
+                    });
+                }
 
-    loop {
-        match device.services().await {
-            Ok(s) => {
-                services.extend(s);
-                break;
-            }
-            Err(err) if retries > 0 => {
-                println!("    Services error: {}", &err);
-                sleep(Duration::from_secs(1)).await;
-                retries -= 1;
-            }
-            Err(err) => return Err(err),
-        }
-    }
+                Ok(services)
+            },
+        )
+        .await?;
 
-    for service in services {
-        let uuid = service.uuid().await?;
-        println!("    Service UUID: {}", &uuid);
+        for service in services {
+            let uuid = service.uuid().await?;
+            println!("    Service UUID: {}", &uuid);
 
-        for char in service.characteristics().await? {
-            let uuid = char.uuid().await?;
-            println!("    Characteristic UUID: {}", &uuid);
-            println!(
-                "    Characteristic data: {:?}",
-                char.all_properties().await?
-            );
+            for char in service.characteristics().await? {
+                let uuid = char.uuid().await?;
+                println!("    Characteristic UUID: {}", &uuid);
+                println!("    Characteristic data: {:?}", char.properties().await?);
 
-            if uuid == CHARACTERISTIC_UUID {
-                println!("    Found our characteristic!");
-                return Ok(Some(char));
+                if uuid == target {
+                    println!("    Found our characteristic!");
+                    return Ok(Some(char));
+                }
             }
         }
-    }
 
-    println!("    Not found!");
+        println!("    Not found!");
 
-    Ok(None)
+        Ok(None)
+    };
+    with_connect_timeout(timeout, lookup).await
 }
 
-async fn write_to_characteristic(characteristic: &Characteristic, data: &[u8]) -> Result<()> {
-    let mut retries = 2;
+/// GATT write flavour, secure characteristics may need reliable or authorized writes.
+/// The default is a plain write request, what every command used so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    pub op_type: WriteOp,
+    pub prepare_authorize: bool,
+}
 
-    loop {
-        match characteristic
-            .write_ext(
-                data,
-                &CharacteristicWriteRequest {
-                    offset: 0,
-                    op_type: WriteOp::Request,
-                    prepare_authorize: false,
-                    _non_exhaustive: (),
-                },
-            )
-            .await
-        {
-            Ok(()) => return Ok(()),
-            Err(err) if retries > 0 => {
-                println!("Write failed: {}", &err);
-                sleep(Duration::from_secs(1)).await;
-                retries -= 1;
-            }
-            Err(err) => return Err(err),
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            op_type: WriteOp::Request,
+            prepare_authorize: false,
         }
     }
 }
 
-async fn writre_random_command(
-    our_characteristic: &Characteristic,
-    encode_tools: &mut EncodeTools,
-) -> Result<()> {
-    println!("Trying writing random command...");
-
-    let command_model = TtlvCommandModel::new(Cmd::Random.as_i32(), 0);
+impl WriteOptions {
+    fn request(self) -> CharacteristicWriteRequest {
+        CharacteristicWriteRequest {
+            offset: 0,
+            op_type: self.op_type,
+            prepare_authorize: self.prepare_authorize,
+            _non_exhaustive: (),
+        }
+    }
+}
 
-    write_to_characteristic(
-        &our_characteristic,
-        encode_tools.start_encode(&command_model).get_cmd_data(),
-    )
-    .await
+async fn write_to_characteristic(characteristic: &Characteristic, data: &[u8]) -> Result<()> {
+    write_to_characteristic_with(characteristic, data, WriteOptions::default()).await
 }
 
-async fn write_login_command(
-    our_characteristic: &Characteristic,
-    encode_tools: &mut EncodeTools,
-    random_value: String,
-    binding_key: String,
+async fn write_to_characteristic_with(
+    characteristic: &Characteristic,
+    data: &[u8],
+    options: WriteOptions,
 ) -> Result<()> {
-    println!("Trying writing login command...");
-
-    let bk = bytes_to_hex_str(b64.decode(binding_key).unwrap().as_slice());
-    println!("  bk: {:?}", bk);
-
-    let params = bk + ";" + &random_value;
-
-    let value = digest(&params);
-
-    println!("  params: {:?}", params);
-    println!("  value: {:?}", value);
-
-    let mut login_model = TtlvCommandModel::new(Cmd::Login.as_i32(), 1001);
-    login_model.add_payload(TTLVData::new(2, 3, true).with_binary(value.as_bytes().to_vec()));
-
-    write_to_characteristic(
-        &our_characteristic,
-        encode_tools
-            .start_encode_with_packet_id(&login_model, true)
-            .get_cmd_data(),
+    let request = &options.request();
+
+    retry(
+        &RetryPolicy::fixed(2, Duration::from_secs(1)),
+        || async move {
+            characteristic
+                .write_ext(data, request)
+                .await
+                .inspect_err(|err| println!("Write failed: {}", err))
+        },
     )
     .await
 }
 
-async fn write_pure_login_command(
-    our_characteristic: &Characteristic,
-    encode_tools: &mut EncodeTools,
-) -> Result<()> {
-    println!("Trying writing pure login command...");
-
-    let mut login_model = TtlvCommandModel::new(Cmd::BLEAccountAuthentication.as_i32(), 1001);
+/// Surfaces encode failures as BLE errors so write helpers can propagate them with `?`
+fn encode_error(err: EncodeError) -> bluer::Error {
+    bluer::Error {
+        kind: bluer::ErrorKind::InvalidArguments,
+        message: format!("Encode failed: {:?}", err),
+    }
+}
 
-    login_model.add_payload(TTLVData::new(1, 2, true).with_integer(1));
+async fn write_pure_login_command(link: &mut impl Link) -> Result<()> {
+    println!("Trying writing pure login command...");
 
-    write_to_characteristic(
-        &our_characteristic,
-        encode_tools
-            .start_encode_with_packet_id(&login_model, true)
-            .get_cmd_data(),
-    )
-    .await
-}
+    let login_model = TtlvCommandModel::builder(Cmd::BLEAccountAuthentication, 1001)
+        .integer(1, 1)
+        .build();
 
-async fn write_wifi_pair_command(
-    our_characteristic: &Characteristic,
-    encode_tools: &mut EncodeTools,
-) -> Result<()> {
-    println!("Trying writing wifi pair command...");
-
-    let mut wifi_pair_model = TtlvCommandModel::new(Cmd::WifiPair.as_i32(), 1001);
-
-    wifi_pair_model
-        .add_payload(TTLVData::new(1, 3, true).with_binary("Xata290.2".as_bytes().to_vec()));
-    wifi_pair_model
-        .add_payload(TTLVData::new(2, 3, true).with_binary("Feedbacc290".as_bytes().to_vec()));
-    wifi_pair_model.add_payload(TTLVData::new(11, 2, true).with_integer(30));
-    wifi_pair_model.add_payload(TTLVData::new(12, 2, true).with_integer(380));
-    wifi_pair_model.add_payload(
-        TTLVData::new(13, 3, true).with_binary("mqtt://local-mqtt.test:1337".as_bytes().to_vec()),
-    );
-
-    write_to_characteristic(
-        &our_characteristic,
-        encode_tools.start_encode(&wifi_pair_model).get_cmd_data(),
-    )
-    .await
+    link.send(&login_model).await
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> bluer::Result<()> {
+    let cli = Cli::parse();
+
     // pretty_env_logger::init();
 
     simple_logger::SimpleLogger::new()
@@ -264,7 +283,7 @@ async fn main() -> bluer::Result<()> {
         .unwrap();
 
     let session = bluer::Session::new().await?;
-    let adapter = session.default_adapter().await?;
+    let adapter = select_adapter(&session, cli.adapter).await?;
 
     // adapter.set_powered(false).await?;
     // println!("Powered off");
@@ -273,413 +292,410 @@ async fn main() -> bluer::Result<()> {
     // adapter.set_powered(true).await?;
     // println!("Powered on");
 
-    let mut our_device: Option<Device> = None;
-    let mut our_quec_device: Option<QuecBLEDevice> = None;
+    match cli.command {
+        cli::Command::Scan { timeout } => scan(&adapter, Duration::from_secs(timeout)).await,
+        cli::Command::Pair {
+            ssid,
+            password,
+            mqtt_url,
+            sync_time,
+        } => {
+            let config = WifiPairConfig::new(ssid, password, mqtt_url);
+            let options = SessionOptions {
+                sync_time_on_connect: sync_time,
+            };
+            pair(&adapter, config, options).await
+        }
+        cli::Command::Read => read_device_info(&adapter).await,
+        cli::Command::Monitor => monitor(&adapter).await,
+    }
+}
 
-    {
-        println!(
-            "Discovering on Bluetooth adapter {} with address {}\n",
-            adapter.name(),
-            adapter.address().await?
-        );
-
-        adapter
-            .set_discovery_filter(DiscoveryFilter {
-                transport: DiscoveryTransport::Le,
-                ..Default::default()
-            })
-            .await?;
+/// The adapter named or addressed by `preferred`, or the default adapter when none is given
+async fn select_adapter(session: &bluer::Session, preferred: Option<String>) -> Result<Adapter> {
+    let Some(preferred) = preferred else {
+        return session.default_adapter().await;
+    };
+    let preferred_addr = preferred.parse::<Address>().ok();
 
-        let discover = adapter.discover_devices().await?;
+    for name in session.adapter_names().await? {
+        let adapter = session.adapter(&name)?;
+        if name == preferred || Some(adapter.address().await?) == preferred_addr {
+            return Ok(adapter);
+        }
+    }
 
-        pin_mut!(discover);
+    Err(bluer::Error {
+        kind: bluer::ErrorKind::NotFound,
+        message: format!("No Bluetooth adapter named or addressed {:?}", preferred),
+    })
+}
 
-        while let Some(evt) = discover.next().await {
-            match evt {
-                AdapterEvent::DeviceAdded(addr) => {
-                    let device = adapter.device(addr)?;
+/// Discovery restricted to LE devices, Quectel modules never advertise over BR/EDR
+fn le_discovery_filter() -> DiscoveryFilter {
+    DiscoveryFilter {
+        transport: DiscoveryTransport::Le,
+        ..Default::default()
+    }
+}
 
-                    let name = device.name().await?;
+/// Decodes the advertisement of a newly seen device, `None` if it is not a Quectel one
+async fn quec_device_at(
+    adapter: &Adapter,
+    addr: Address,
+) -> Result<Option<(Device, QuecBLEDevice)>> {
+    let device = adapter.device(addr)?;
 
-                    let manufacturer_data = match device.manufacturer_data().await? {
-                        Some(data) => data,
-                        None => {
-                            continue;
-                        }
-                    };
+    let name = device.name().await?;
 
-                    let quec_device = match try_get_quec_device(&name, &addr, &manufacturer_data) {
-                        Some(d) => d,
-                        None => {
-                            continue;
-                        }
-                    };
+    let manufacturer_data = match device.manufacturer_data().await? {
+        Some(data) => data,
+        None => {
+            return Ok(None);
+        }
+    };
 
-                    println!("Found device with \"{:?}\" with address {:?}:", name, addr);
-                    println!("  device key: {:?}", quec_device.device_key);
-                    println!("  product key: {:?}", quec_device.product_key);
+    match try_get_quec_device(&name, &addr, &manufacturer_data) {
+        Ok(quec_device) => {
+            println!(
+                "Found device with \"{:?}\" with address {:?}: {}",
+                name, addr, quec_device
+            );
+
+            Ok(Some((device, quec_device)))
+        }
+        Err(QuecBLEDeviceDecodeError::NoManufacturerData) => Ok(None),
+        Err(err) => {
+            println!("Skipping device {}: {}", addr, err);
+            Ok(None)
+        }
+    }
+}
 
-                    device.set_blocked(false).await?;
-                    device.set_trusted(true).await?;
+/// Lists every Quectel device advertising within `timeout`
+async fn scan(adapter: &Adapter, timeout: Duration) -> Result<()> {
+    let devices = BleClient::discover(adapter, le_discovery_filter()).await?;
 
-                    our_device = Some(device);
-                    our_quec_device = Some(quec_device);
+    // Running out of time is the normal way for a scan to end, devices print as found
+    let _ = tokio::time::timeout(timeout, devices.for_each(|_| async {})).await;
 
-                    break;
-                    // if our_characteristic.flags().await?.read {
-                    //     let value = our_characteristic.read().await?;
-                    //     println!("    Read value back: {:x?}", &value);
-                    //     sleep(Duration::from_secs(1)).await;
-                    // }
+    println!("Stopping discovery");
 
-                    // match device.disconnect().await {
-                    //     Ok(()) => println!("Device disconnected"),
-                    //     Err(err) => println!("Device disconnection failed: {}", &err),
-                    // }
+    Ok(())
+}
 
-                    // break;
-                }
-                // AdapterEvent::DeviceRemoved(addr) => {
-                //     println!("Device removed {addr}");
-                // }
-                _ => (),
+/// Waits for the first Quectel device to advertise
+async fn first_quec_device(adapter: &Adapter) -> Result<QuecBLEDevice> {
+    let devices = BleClient::discover(adapter, le_discovery_filter()).await?;
+    pin_mut!(devices);
+
+    let found = devices.next().await;
+    println!("Stopping discovery");
+
+    found.ok_or_else(|| bluer::Error {
+        kind: bluer::ErrorKind::NotFound,
+        message: "Discovery ended without a Quectel device".to_string(),
+    })
+}
+
+/// Asks the first device found for its device info and prints it
+async fn read_device_info(adapter: &Adapter) -> Result<()> {
+    let mut client = BleClient::new(adapter.clone());
+    let connection = client.connect(&first_quec_device(adapter).await?).await?;
+
+    println!("Product key: {}", connection.device_info().product_key);
+    println!("Device key: {}", connection.device_info().device_key);
+
+    match connection.refresh_device_info().await? {
+        Some(info) => {
+            println!("Firmware: {:?}", info.firmware);
+            println!("Components: {:?}", info.components);
+            if let Some(version) = info.firmware_version() {
+                println!("Firmware version: {}", version);
             }
         }
-
-        println!("Stopping discovery");
+        None => println!("No device info response"),
     }
 
-    let device = our_device.unwrap();
+    client.close().await
+}
 
-    match connect_to_device(&device).await {
-        Ok(()) => println!("Device connected"),
-        Err(err) => {
-            println!("Device connection failed: {}", &err);
-            return Err(err);
+/// Prints every decoded frame from the first device found until Ctrl-C
+async fn monitor(adapter: &Adapter) -> Result<()> {
+    let mut client = BleClient::new(adapter.clone());
+    client.connect(&first_quec_device(adapter).await?).await?;
+
+    let mut dedup = DEDUP_WINDOW.map(DedupFilter::new);
+
+    loop {
+        let result = tokio::select! {
+            result = client.recv() => result?,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        let Some(result) = result else {
+            println!("    Notification session was terminated");
+            break;
+        };
+
+        ack_data_report(&mut client, &result).await?;
+
+        if !dedup.as_mut().is_none_or(|dedup| dedup.accept(&result)) {
+            continue;
         }
+
+        print_result(&result);
     }
 
-    let our_characteristic = match find_our_characteristic(&device).await {
-        Ok(Some(char)) => char,
-        Ok(None) => {
-            println!("    Not found!");
-            return Err(bluer::Error {
-                kind: bluer::ErrorKind::NotFound,
-                message: "Characteristic not found".to_string(),
-            });
-        }
-        Err(err) => {
-            println!("    Device failed: {}", &err);
-            let _ = adapter.remove_device(device.address()).await;
-            return Err(err);
-        }
-    };
+    client.close().await
+}
+
+/// Pairs the first device found with `config`, stopping early on Ctrl-C
+async fn pair(adapter: &Adapter, config: WifiPairConfig, options: SessionOptions) -> Result<()> {
+    let device = first_quec_device(adapter).await?;
+    let mut client = BleClient::new(adapter.clone()).with_session_options(options);
+    client.connect(&device).await?;
+
+    let login = LoginStateMachine::new(DEFAULT_BINDING_KEY)
+        .with_hash_algo(LOGIN_HASH_ALGO)
+        .with_wifi_pair(config);
+    let outcome = run_pairing(&mut client, login, &cancel_on_ctrl_c()).await?;
+    println!("Pairing outcome: {:?}", outcome);
+
+    Ok(())
+}
 
-    device.set_trusted(true).await?;
-
-    sleep(Duration::from_secs(1)).await;
-
-    // our_characteristic.write_ext(encode_tools.start_encode(&command_model).get_cmd_data(), &CharacteristicWriteRequest {
-    //     offset: 0,
-    //     op_type: WriteOp::Request,
-    //     prepare_authorize: false,
-    //     _non_exhaustive: (),
-    // }).await?;
-
-    println!("Trying notify...");
-
-    // Create shared container that both tasks can access
-    let shared_container = Arc::new(Mutex::new(LoginInfoContainer::new()));
-    let shared_container_clone = Arc::clone(&shared_container);
-
-    let notify = our_characteristic.notify().await.unwrap();
-
-    let our_characteristic_clone = our_characteristic.clone();
-
-    // Spawn the notify task
-    let notify_task = tokio::spawn(async move {
-        pin_mut!(notify);
-
-        let mut decode_tools = DecodeTools::new();
-        let mut encode_tools = EncodeTools::new();
-
-        let mut binding_key: String = "3EB24BC7957DB49D".to_string();
-
-        loop {
-            match notify.next().await {
-                Some(value) => {
-                    println!("    Notification value: {:x?}", &value);
-
-                    let results = decode_tools.packet_slice(&value);
-                    for result in results {
-                        match result {
-                            DecodeResult::Success(model) => {
-                                println!("Decoded command: 0x{:04X}", model.cmd);
-                                println!("Packet ID: {}", model.packet_id);
-                                println!("Payload count: {}", model.payloads.len());
-
-                                match Cmd::from_i32(model.cmd) {
-                                    Some(Cmd::RandomResp) => {
-                                        println!("Random response");
-
-                                        let random_ttlv = &model
-                                            .payloads
-                                            .iter()
-                                            .find(|payload| payload.id == 1)
-                                            .unwrap()
-                                            .value;
-
-                                        match random_ttlv {
-                                            TTLVValue::Binary(data) => {
-                                                let random_value =
-                                                    String::from_utf8_lossy(data.as_slice())
-                                                        .to_string();
-                                                println!("Random value: {}", random_value);
-                                                // write_pure_login_command(&our_characteristic_clone, &mut encode_tools).await;
-
-                                                let binding_key_clone = binding_key.clone();
-                                                write_login_command(
-                                                    &our_characteristic_clone,
-                                                    &mut encode_tools,
-                                                    random_value,
-                                                    binding_key_clone,
-                                                )
-                                                .await;
-                                            }
-                                            _ => (),
-                                        }
-                                    }
-
-                                    Some(Cmd::LoginResp) => {
-                                        println!("Login response");
-
-                                        let login_ttlv = &model
-                                            .payloads
-                                            .iter()
-                                            .find(|payload| payload.id == 3)
-                                            .unwrap()
-                                            .value;
-
-                                        match login_ttlv {
-                                            TTLVValue::Binary(data) => {
-                                                let login_value =
-                                                    String::from_utf8_lossy(data.as_slice())
-                                                        .to_string();
-                                                println!("Login value: {}", login_value);
-                                            }
-                                            _ => (),
-                                        }
-                                    }
-
-                                    Some(Cmd::BLEAccountAuthenticationResp) => {
-                                        println!("BLEAccountAuthentication response");
-                                    }
-
-                                    Some(Cmd::WifiPairResp) => {
-                                        println!("Wifi pair response");
-
-                                        let binding_ttlv =
-                                            model.payloads.iter().find(|payload| payload.id == 9);
-
-                                        match binding_ttlv {
-                                            Some(ttlv) => match &ttlv.value {
-                                                TTLVValue::Binary(data) => {
-                                                    let binding_key_value =
-                                                        String::from_utf8_lossy(data.as_slice())
-                                                            .to_string();
-                                                    println!(
-                                                        "Binding key value: {}",
-                                                        binding_key_value
-                                                    );
-                                                    binding_key = binding_key_value;
-
-                                                    // writre_random_command(
-                                                    //     &our_characteristic_clone,
-                                                    //     &mut encode_tools,
-                                                    // )
-                                                    // .await;
-                                                }
-                                                _ => {
-                                                    println!("Binding key format messed up.");
-                                                }
-                                            },
-                                            _ => {
-                                                println!(
-                                                    "Binding key not found. Device seems to be not in pairing mode."
-                                                );
-                                            }
-                                        }
-                                    }
-
-                                    _ => (),
-                                }
-
-                                for (i, payload) in model.payloads.iter().enumerate() {
-                                    println!(
-                                        "  Payload {}: ID=0x{:04X}, Type={}, TTLV={}",
-                                        i, payload.id, payload.type_id, payload.ttlv
-                                    );
-
-                                    match &payload.value {
-                                        TTLVValue::Binary(data) => {
-                                            let code = String::from_utf8_lossy(data);
-
-                                            // Update shared container
-                                            if let Ok(mut container) = shared_container_clone.lock()
-                                            {
-                                                container.set_random(code.to_string());
-                                            }
-
-                                            println!("    Value: {:?} as string: {}", data, code);
-                                        }
-                                        TTLVValue::Integer(data) => {
-                                            println!("    Integer value: {}", data);
-                                        }
-                                        _ => (),
-                                    }
-                                }
-                            }
-                            DecodeResult::Transparent(model) => {
-                                println!("Transparent command: 0x{:04X}", model.cmd);
-                            }
-                            DecodeResult::Incomplete => {
-                                println!("Incomplete data");
-                            }
-                            DecodeResult::Error(err) => {
-                                println!("Error: {}", err);
-                            }
-                        }
+/// Acks `result` if it is a `DeviceDataReport` and `AUTO_ACK_DATA_REPORTS` is on. Unacked
+/// reports make the device throttle or stop reporting, so this runs before any dedup
+async fn ack_data_report(link: &mut impl Link, result: &DecodeResult) -> Result<()> {
+    if let DecodeResult::Success(model) = result
+        && AUTO_ACK_DATA_REPORTS
+        && IotCmd::from_i32(model.cmd) == Some(IotCmd::DeviceDataReport)
+    {
+        link.reply(&build_data_report_ack(model)).await?;
+    }
+    Ok(())
+}
+
+/// Prints a decoded frame with its payloads
+fn print_result(result: &DecodeResult) {
+    match result {
+        DecodeResult::Success(model) => {
+            println!("Decoded command: 0x{:04X}", model.cmd);
+            println!("Packet ID: {}", model.packet_id);
+            println!("Payload count: {}", model.payloads.len());
+
+            for (i, payload) in model.payloads.iter().enumerate() {
+                println!(
+                    "  Payload {}: ID=0x{:04X}, Type={}, TTLV={}",
+                    i, payload.id, payload.type_id, payload.ttlv
+                );
+
+                match &payload.value {
+                    TTLVValue::Binary(data) => {
+                        let code = String::from_utf8_lossy(data);
+                        println!("    Value: {:?} as string: {}", data, code);
                     }
-                }
-                None => {
-                    println!("    Notification session was terminated");
-                    break;
+                    TTLVValue::Integer(data) => {
+                        println!("    Integer value: {}", data);
+                    }
+                    _ => (),
                 }
             }
         }
+        DecodeResult::Transparent(model) => {
+            println!("Transparent command: 0x{:04X}", model.cmd);
+        }
+        DecodeResult::Incomplete => {
+            println!("Incomplete data");
+        }
+        DecodeResult::ChecksumMismatch {
+            expected,
+            actual,
+            frame,
+        } => {
+            println!(
+                "Checksum mismatch: expected 0x{:02X}, got 0x{:02X} in {:02X?}",
+                expected, actual, frame
+            );
+        }
+        DecodeResult::Error(err) => {
+            println!("Error: {}", err);
+        }
+    }
+}
+
+/// Cancelled on the first Ctrl-C
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let on_ctrl_c = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_ctrl_c.cancel();
+        }
     });
+    cancel
+}
 
-    // Spawn the write task
-    let write_task = tokio::spawn(async move {
-        sleep(Duration::from_secs(1)).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gatt::{MockCharacteristic, MockDevice, MockService};
 
-        let mut encode_tools = EncodeTools::new();
+    fn service_with(uuids: &[Uuid]) -> MockService {
+        MockService {
+            uuid: uuid_from_u16(0x180f),
+            characteristics: uuids.iter().copied().map(MockCharacteristic::new).collect(),
+        }
+    }
 
-        if our_characteristic.flags().await?.write {
-            // let command_model = TtlvCommandModel::new(Cmd::Random.as_i32(), 0);
+    #[test]
+    fn short_uuids_expand_onto_the_base_uuid() {
+        let characteristic = "00009c40-0000-1000-8000-00805f9b34fb";
+        let cccd = "00002902-0000-1000-8000-00805f9b34fb";
 
-            // println!("Trying write random command...");
+        assert_eq!(CHARACTERISTIC_UUID.to_string(), characteristic);
+        assert_eq!(CCCD_UUID.to_string(), cccd);
+    }
 
-            // write_to_characteristic(
-            //     &our_characteristic,
-            //     encode_tools.start_encode(&command_model).get_cmd_data(),
-            // )
-            // .await?;
+    #[test]
+    fn custom_uuid_is_not_mistaken_for_a_short_one() {
+        // Same 16 bits in the usual place, but not on the Bluetooth base UUID
+        let custom = Uuid::parse_str("00009c40-1234-5678-9abc-def012345678").unwrap();
 
-            // writre_random_command(&our_characteristic.clone(), &mut encode_tools).await?;
-            write_wifi_pair_command(&our_characteristic.clone(), &mut encode_tools).await?;
-            // write_pure_login_command(&our_characteristic, &mut encode_tools).await?;
+        assert_ne!(custom, CHARACTERISTIC_UUID);
+        assert_eq!(custom.as_u128() >> 96, CHARACTERISTIC_UUID.as_u128() >> 96);
+    }
 
-            sleep(Duration::from_secs(1)).await;
+    #[test]
+    fn default_write_is_a_plain_request() {
+        let request = WriteOptions::default().request();
 
-            // println!("Trying writing device info command...");
-            // let model2 = TtlvCommandModel::new(IotCmd::ReadDeviceInfo.as_i32(), 1001);
-            // write_to_characteristic(
-            //     &our_characteristic,
-            //     encode_tools.start_encode(&model2).get_cmd_data(),
-            // )
-            // .await?;
+        assert_eq!(request.offset, 0);
+        assert_eq!(request.op_type, WriteOp::Request);
+        assert!(!request.prepare_authorize);
+    }
 
-            // println!("Trying writing account authentication command...");
+    #[test]
+    fn write_request_carries_the_options() {
+        let options = WriteOptions {
+            op_type: WriteOp::Reliable,
+            prepare_authorize: true,
+        };
+        let request = options.request();
+
+        assert_eq!(request.offset, 0);
+        assert_eq!(request.op_type, WriteOp::Reliable);
+        assert!(request.prepare_authorize);
+    }
 
-            // let random_value = {
-            //     if let Ok(container) = shared_container.lock() {
-            //         container.get_random()
-            //     } else {
-            //         String::new()
-            //     }
-            // };
+    const ADDRESS: Address = Address([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
 
-            // let mut login_model = TtlvCommandModel::new(Cmd::BLEAccountAuthentication.as_i32(), 1001);
-            // login_model.add_payload(TTLVData::new(1, 2, true).with_integer(1));
-            // login_model.add_payload(TTLVData::new(3, 3, true).with_binary(random_value.as_bytes().to_vec()));
+    fn decode_advertisement(
+        data: Vec<u8>,
+    ) -> std::result::Result<QuecBLEDevice, QuecBLEDeviceDecodeError> {
+        let manufacturer_data = HashMap::from([(0x55_51, data)]);
+        try_get_quec_device(&None, &ADDRESS, &manufacturer_data)
+    }
 
-            // write_to_characteristic(
-            //     &our_characteristic,
-            //     encode_tools.start_encode(&login_model).get_cmd_data(),
-            // )
-            // .await?;
-        }
+    #[test]
+    fn quec_device_takes_name_and_address_from_the_advertisement() {
+        let manufacturer_data = unquec_model::mock::advertisement("p11xyz", "a1b2c3", 0);
 
-        Ok::<(), bluer::Error>(())
-    });
+        let device =
+            try_get_quec_device(&Some("QUEC".to_string()), &ADDRESS, &manufacturer_data).unwrap();
+
+        assert_eq!(device.name, "QUEC");
+        assert_eq!(device.mac, "11:22:33:44:55:66");
+        assert_eq!(device.product_key, "p11xyz");
+    }
 
-    // Wait for both tasks to complete
-    let (notify_result, write_result) =
-        tokio::join!(notify_task, write_task);
+    #[test]
+    fn other_manufacturers_are_not_quec_devices() {
+        let manufacturer_data = HashMap::from([(0x004c, vec![0x02, 0x15])]);
 
-    // Handle any errors from the tasks
-    if let Err(e) = notify_result {
-        println!("Notify task error: {:?}", e);
+        assert!(matches!(
+            try_get_quec_device(&None, &ADDRESS, &manufacturer_data),
+            Err(QuecBLEDeviceDecodeError::NoManufacturerData)
+        ));
     }
 
-    if let Err(e) = write_result {
-        println!("Write task error: {:?}", e);
-        // The write task returns Result<(), bluer::Error>, so we need to handle the JoinError
-        // and then extract the bluer::Error if it exists
-        return Err(bluer::Error {
-            kind: bluer::ErrorKind::Failed,
-            message: format!("Write task failed: {:?}", e),
-        });
+    #[test]
+    fn wrong_header_is_reported_with_its_bytes() {
+        assert!(matches!(
+            decode_advertisement(b"ECho and more bytes".to_vec()),
+            Err(QuecBLEDeviceDecodeError::InvalidHeader(found)) if found == b"ECho"
+        ));
     }
 
-    device.disconnect().await?;
+    #[test]
+    fn truncated_advertisement_is_too_short() {
+        assert!(matches!(
+            decode_advertisement(b"ECig\x00\x01\x06p11".to_vec()),
+            Err(QuecBLEDeviceDecodeError::DataTooShort)
+        ));
+    }
 
-    // sleep(Duration::from_secs(10)).await;
+    #[test]
+    fn overrunning_field_is_named() {
+        let mut data = b"ECig\x00\x01".to_vec();
+        data.push(6);
+        data.extend(b"p11xyz");
+        data.push(20); // device key, 20 bytes announced
+        data.extend([0xab; 6]);
+
+        assert!(matches!(
+            decode_advertisement(data),
+            Err(QuecBLEDeviceDecodeError::InsufficientFieldData(field, 20)) if field == "device_key"
+        ));
+    }
 
-    return Ok(());
-}
+    #[tokio::test(start_paused = true)]
+    async fn services_resolved_on_a_retry_are_searched() {
+        let device = MockDevice::new([
+            Vec::new(),
+            vec![service_with(&[
+                DATA_CHARACTERISTIC_UUID,
+                CHARACTERISTIC_UUID,
+            ])],
+        ]);
 
-#[derive(Default)]
-struct LoginInfoContainer {
-    random: Arc<Mutex<String>>,
-}
+        let found = find_characteristic(&device, CHARACTERISTIC_UUID, CONNECT_TIMEOUT).await;
 
-impl LoginInfoContainer {
-    fn new() -> Self {
-        let shared_data = Arc::new(Mutex::new(String::from("")));
-        LoginInfoContainer {
-            random: shared_data,
-        }
+        assert_eq!(found.unwrap().unwrap().uuid, CHARACTERISTIC_UUID);
+        assert_eq!(device.lookups.get(), 2);
     }
 
-    fn set_random(&mut self, random: String) {
-        let mut lock = self.random.lock().unwrap();
-        *lock = random;
-    }
+    #[tokio::test(start_paused = true)]
+    async fn services_that_never_resolve_are_not_a_missing_characteristic() {
+        let device = MockDevice::new([Vec::new()]);
 
-    fn get_random(&self) -> String {
-        let lock = self.random.lock().unwrap();
-        lock.clone()
+        let err = find_characteristic(&device, CHARACTERISTIC_UUID, CONNECT_TIMEOUT)
+            .await
+            .err()
+            .unwrap();
+
+        assert!(matches!(err.kind, bluer::ErrorKind::ServicesUnresolved));
+        assert_eq!(device.lookups.get(), 3);
     }
-}
 
-fn bytes_to_hex_str(bytes: &[u8]) -> String {
-    bytes
-        .iter()
-        .map(|b| format!("{:02x}", b))
-        .collect::<String>()
-}
+    #[tokio::test]
+    async fn resolved_services_without_the_target_find_nothing() {
+        let device = MockDevice::new([vec![service_with(&[DATA_CHARACTERISTIC_UUID])]]);
 
-use sha2::{Digest, Sha256};
+        let found = find_characteristic(&device, CHARACTERISTIC_UUID, CONNECT_TIMEOUT).await;
 
-fn digest(input: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(input.as_bytes());
-    let result = hasher.finalize();
-    byte2hex(&result)
-}
+        assert!(found.unwrap().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_connect_step_that_never_finishes_times_out() {
+        let err = with_connect_timeout(CONNECT_TIMEOUT, std::future::pending::<Result<()>>())
+            .await
+            .err()
+            .unwrap();
 
-fn byte2hex(bytes: &[u8]) -> String {
-    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        assert!(matches!(err.kind, bluer::ErrorKind::Failed));
+        assert_eq!(err.message, "connect timed out");
+    }
 }
@@ -0,0 +1,317 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bluer::Result;
+use tokio_util::sync::CancellationToken;
+
+use unquec_model::{
+    auth::{LoginState, LoginStateMachine},
+    ttlv::dedup::DedupFilter,
+};
+
+use crate::{DEDUP_WINDOW, ack_data_report, link::Link, print_result};
+
+/// How a pairing attempt ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingOutcome {
+    Completed,
+    /// The device rejected the login or the wifi pair request
+    Failed,
+    Cancelled,
+}
+
+/// Runs the handshake `login` describes over `link`, then disconnects: opens with
+/// `Random`, answers each response as `login` dictates, and ends once the device is paired
+/// or refused. `WifiPair` is only sent after the device accepted the login.
+///
+/// Cancelling `cancel` stops at the next frame boundary. The protocol has no abort
+/// command, the device times out the half-finished pairing once the link drops
+pub async fn run_pairing(
+    link: &mut impl Link,
+    login: LoginStateMachine,
+    cancel: &CancellationToken,
+) -> Result<PairingOutcome> {
+    let outcome = handshake(link, login, DEDUP_WINDOW, cancel).await;
+    link.disconnect().await?;
+    outcome
+}
+
+async fn handshake(
+    link: &mut impl Link,
+    mut login: LoginStateMachine,
+    dedup_window: Option<Duration>,
+    cancel: &CancellationToken,
+) -> Result<PairingOutcome> {
+    let mut dedup = dedup_window.map(DedupFilter::new);
+
+    link.send(&login.start()).await?;
+
+    loop {
+        let result = tokio::select! {
+            result = link.recv() => result?,
+            _ = cancel.cancelled() => return Ok(PairingOutcome::Cancelled),
+        };
+
+        // The device forgets the login with the link, a reconnect could not resume
+        let Some(result) = result else {
+            println!("    Notification session was terminated");
+            return Err(bluer::Error {
+                kind: bluer::ErrorKind::Failed,
+                message: "Notifications ended before pairing completed".to_string(),
+            });
+        };
+
+        // Retransmitted reports are acked too, the device resends until one gets through
+        ack_data_report(link, &result).await?;
+
+        if !dedup.as_mut().is_none_or(|dedup| dedup.accept(&result)) {
+            continue;
+        }
+
+        print_result(&result);
+
+        let previous_state = login.state();
+        let next = login.on_response(&result);
+
+        if login.state() != previous_state {
+            println!("Login state: {:?}", login.state());
+
+            if matches!(login.state(), LoginState::LoggedIn | LoginState::Pairing) {
+                send_after_login_commands(link).await?;
+            }
+        }
+
+        if let Some(next) = next {
+            link.send(&next).await?;
+        }
+
+        match login.state() {
+            LoginState::Paired => {
+                println!("Binding key value: {}", login.binding_key());
+                return Ok(PairingOutcome::Completed);
+            }
+            LoginState::Failed => return Ok(PairingOutcome::Failed),
+            _ => (),
+        }
+    }
+}
+
+/// Sends what the link's session options ask for after a successful login, e.g. the time
+/// sync
+async fn send_after_login_commands(link: &mut impl Link) -> Result<()> {
+    let unix_millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or(0);
+
+    for model in link.session_options().after_login(unix_millis) {
+        println!("Sending post-login command 0x{:04X}...", model.cmd);
+        link.send(&model).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use unquec_model::{
+        commands::{Cmd, IotCmd, TtlvCommandModel},
+        connection::SessionOptions,
+        ttlv::model::TTLVData,
+        wifi::WifiPairConfig,
+    };
+
+    use super::*;
+    use crate::link::Loopback;
+
+    fn login() -> LoginStateMachine {
+        LoginStateMachine::new("3EB24BC7957DB49D").with_wifi_pair(WifiPairConfig::new(
+            "home",
+            "secret",
+            "mqtt://10.0.0.2:1883",
+        ))
+    }
+
+    fn response(cmd: Cmd, id: i32, value: &str) -> TtlvCommandModel {
+        let mut model = TtlvCommandModel::new(cmd.as_i32(), 0);
+        model.add_payload(TTLVData::new(id, 3, true).with_binary(value.as_bytes().to_vec()));
+        model
+    }
+
+    /// Hands out a random, answers the login with `login_status` (0 accepts it) and the
+    /// pair request with `binding_key` if the device is in pairing mode
+    fn device(
+        login_status: i64,
+        binding_key: Option<&'static str>,
+    ) -> impl FnMut(&TtlvCommandModel) -> Vec<TtlvCommandModel> {
+        move |request| match Cmd::from_i32(request.cmd) {
+            Some(Cmd::Random) => vec![response(Cmd::RandomResp, 1, "1234")],
+            Some(Cmd::Login) => {
+                let mut login_resp = TtlvCommandModel::new(Cmd::LoginResp.as_i32(), 0);
+                login_resp.add_payload(TTLVData::new(1, 2, true).with_integer(login_status));
+                vec![login_resp]
+            }
+            Some(Cmd::WifiPair) => match binding_key {
+                Some(key) => vec![response(Cmd::WifiPairResp, 9, key)],
+                None => vec![TtlvCommandModel::new(Cmd::WifiPairResp.as_i32(), 0)],
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pairs_after_login() {
+        let mut link = Loopback::new(device(0, Some("AAECAw==")));
+
+        let outcome = run_pairing(&mut link, login(), &CancellationToken::new()).await;
+
+        assert_eq!(outcome.unwrap(), PairingOutcome::Completed);
+        assert_eq!(
+            link.received_cmds(),
+            [
+                Cmd::Random.as_i32(),
+                Cmd::Login.as_i32(),
+                Cmd::WifiPair.as_i32()
+            ]
+        );
+        let ssid = link.received[2]
+            .payloads
+            .iter()
+            .find(|payload| payload.id == 1);
+        assert_eq!(
+            ssid.and_then(|payload| payload.as_binary()),
+            Some(&b"home".to_vec())
+        );
+        assert!(!link.connected);
+    }
+
+    #[tokio::test]
+    async fn syncs_time_after_login_when_asked() {
+        let mut link = Loopback::new(device(0, Some("AAECAw==")));
+        link.session_options = SessionOptions {
+            sync_time_on_connect: true,
+        };
+
+        let outcome = run_pairing(&mut link, login(), &CancellationToken::new()).await;
+
+        assert_eq!(outcome.unwrap(), PairingOutcome::Completed);
+        assert_eq!(
+            link.received_cmds(),
+            [
+                Cmd::Random.as_i32(),
+                Cmd::Login.as_i32(),
+                IotCmd::SendDeviceTimeSyncEvent.as_i32(),
+                Cmd::WifiPair.as_i32()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_login_fails_without_pairing() {
+        let mut link = Loopback::new(device(1, Some("AAECAw==")));
+
+        let outcome = run_pairing(&mut link, login(), &CancellationToken::new()).await;
+
+        assert_eq!(outcome.unwrap(), PairingOutcome::Failed);
+        assert_eq!(
+            link.received_cmds(),
+            [Cmd::Random.as_i32(), Cmd::Login.as_i32()]
+        );
+        assert!(!link.connected);
+    }
+
+    #[tokio::test]
+    async fn device_not_in_pairing_mode_fails() {
+        let mut link = Loopback::new(device(0, None));
+
+        let outcome = run_pairing(&mut link, login(), &CancellationToken::new()).await;
+
+        assert_eq!(outcome.unwrap(), PairingOutcome::Failed);
+        assert!(!link.connected);
+    }
+
+    #[tokio::test]
+    async fn data_reports_are_acked_with_their_packet_id() {
+        let mut answer = device(0, Some("AAECAw=="));
+        let mut link = Loopback::new(move |request| {
+            let mut responses = answer(request);
+            if request.cmd == Cmd::Login.as_i32() {
+                let mut report = TtlvCommandModel::new(IotCmd::DeviceDataReport.as_i32(), 0);
+                report.add_payload(TTLVData::new(1, 2, true).with_integer(42));
+                responses.insert(0, report);
+            }
+            responses
+        });
+
+        let outcome = run_pairing(&mut link, login(), &CancellationToken::new()).await;
+
+        assert_eq!(outcome.unwrap(), PairingOutcome::Completed);
+        assert_eq!(
+            link.received_cmds(),
+            [
+                Cmd::Random.as_i32(),
+                Cmd::Login.as_i32(),
+                IotCmd::DeviceDataReportAck.as_i32(),
+                Cmd::WifiPair.as_i32()
+            ]
+        );
+        assert_eq!(link.received[2].packet_id, link.received[1].packet_id);
+    }
+
+    #[tokio::test]
+    async fn retransmitted_reports_are_acked_even_when_deduped() {
+        // The device reports twice under the login's packet id, the second one a resend
+        let mut answer = device(0, Some("AAECAw=="));
+        let mut link = Loopback::new(move |request| {
+            let mut responses = answer(request);
+            if request.cmd == Cmd::Login.as_i32() {
+                let mut report = TtlvCommandModel::new(IotCmd::DeviceDataReport.as_i32(), 0);
+                report.add_payload(TTLVData::new(1, 2, true).with_integer(42));
+                responses.extend([report.clone(), report]);
+            }
+            responses
+        });
+
+        let outcome = handshake(
+            &mut link,
+            login(),
+            Some(Duration::from_secs(60)),
+            &CancellationToken::new(),
+        )
+        .await;
+
+        assert_eq!(outcome.unwrap(), PairingOutcome::Completed);
+        assert_eq!(
+            link.received_cmds(),
+            [
+                Cmd::Random.as_i32(),
+                Cmd::Login.as_i32(),
+                Cmd::WifiPair.as_i32(),
+                IotCmd::DeviceDataReportAck.as_i32(),
+                IotCmd::DeviceDataReportAck.as_i32()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelling_mid_flow_disconnects() {
+        let cancel = CancellationToken::new();
+
+        // The device asks for a login, then goes silent and the user gives up waiting
+        let on_login = cancel.clone();
+        let mut link = Loopback::new(move |request| match Cmd::from_i32(request.cmd) {
+            Some(Cmd::Random) => vec![response(Cmd::RandomResp, 1, "1234")],
+            _ => {
+                on_login.cancel();
+                Vec::new()
+            }
+        });
+
+        let outcome = run_pairing(&mut link, login(), &cancel).await;
+
+        assert_eq!(outcome.unwrap(), PairingOutcome::Cancelled);
+        assert_eq!(
+            link.received_cmds(),
+            [Cmd::Random.as_i32(), Cmd::Login.as_i32()]
+        );
+        assert!(!link.connected);
+    }
+}
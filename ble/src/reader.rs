@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+
+use futures::{FutureExt, Stream, StreamExt};
+
+use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+
+/// Decodes a notification stream into frames. Forwarding can be paused without
+/// unsubscribing: notifications that arrive while paused are dropped, and decoding starts
+/// over on resume since a frame may have been cut in half
+pub struct FrameReader<S> {
+    notifications: S,
+    decode_tools: DecodeTools,
+    pending: VecDeque<DecodeResult>,
+    paused: bool,
+}
+
+impl<S: Stream<Item = Vec<u8>> + Unpin> FrameReader<S> {
+    pub fn new(notifications: S) -> Self {
+        Self {
+            notifications,
+            decode_tools: DecodeTools::new(),
+            pending: VecDeque::new(),
+            paused: false,
+        }
+    }
+
+    /// Read from a new subscription, e.g. after reconnecting. A frame cut short by the
+    /// disconnect is not resumable, the device sends it again from the start, so the
+    /// decoder drops any partial frame instead of prepending it to the new stream
+    pub fn resubscribe(&mut self, notifications: S) {
+        self.notifications = notifications;
+        self.decode_tools.reset();
+        self.pending.clear();
+        self.paused = false;
+    }
+
+    /// Stop forwarding frames, the subscription stays open
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.pending.clear();
+    }
+
+    /// Forward frames again. Notifications queued while paused are discarded
+    pub fn resume(&mut self) {
+        if !self.paused {
+            return;
+        }
+
+        while let Some(Some(_)) = self.notifications.next().now_or_never() {}
+        self.decode_tools.reset();
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Next decoded frame, `None` once the notification stream ends. While paused,
+    /// notifications are discarded as they arrive
+    pub async fn next(&mut self) -> Option<DecodeResult> {
+        loop {
+            if !self.paused
+                && let Some(result) = self.pending.pop_front()
+            {
+                return Some(result);
+            }
+
+            let value = self.notifications.next().await?;
+            if !self.paused {
+                self.pending.extend(self.decode_tools.feed(&value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc;
+
+    use unquec_model::commands::{Cmd, TtlvCommandModel};
+    use unquec_model::ttlv::{encode::EncodeTools, model::TTLVData};
+
+    use super::*;
+
+    fn frame(cmd: Cmd) -> Vec<u8> {
+        let mut model = TtlvCommandModel::new(cmd.as_i32(), 0);
+        model.add_payload(TTLVData::new(1, 2, true).with_integer(7));
+        EncodeTools::new()
+            .start_encode(&model)
+            .unwrap()
+            .get_cmd_data()
+            .to_vec()
+    }
+
+    fn decoded_cmd(result: Option<DecodeResult>) -> i32 {
+        match result {
+            Some(DecodeResult::Success(model)) => model.cmd,
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn forwards_decoded_frames() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut reader = FrameReader::new(rx);
+
+        tx.unbounded_send(frame(Cmd::RandomResp)).unwrap();
+        tx.unbounded_send(frame(Cmd::LoginResp)).unwrap();
+
+        assert_eq!(decoded_cmd(reader.next().await), Cmd::RandomResp.as_i32());
+        assert_eq!(decoded_cmd(reader.next().await), Cmd::LoginResp.as_i32());
+
+        drop(tx);
+        assert!(reader.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_notifications_while_paused() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut reader = FrameReader::new(rx);
+
+        reader.pause();
+        tx.unbounded_send(frame(Cmd::RandomResp)).unwrap();
+        reader.resume();
+
+        tx.unbounded_send(frame(Cmd::LoginResp)).unwrap();
+        drop(tx);
+
+        assert_eq!(decoded_cmd(reader.next().await), Cmd::LoginResp.as_i32());
+        assert!(reader.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn pausing_discards_undelivered_frames() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut reader = FrameReader::new(rx);
+
+        // Both frames arrive in one notification, the second is still pending on pause
+        let mut both = frame(Cmd::RandomResp);
+        both.extend(frame(Cmd::LoginResp));
+        tx.unbounded_send(both).unwrap();
+        assert_eq!(decoded_cmd(reader.next().await), Cmd::RandomResp.as_i32());
+
+        reader.pause();
+        reader.resume();
+        drop(tx);
+
+        assert!(reader.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn partial_frame_does_not_survive_a_pause() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut reader = FrameReader::new(rx);
+
+        let cut = frame(Cmd::RandomResp);
+        tx.unbounded_send(cut[..cut.len() / 2].to_vec()).unwrap();
+        assert!(matches!(
+            reader.next().await,
+            Some(DecodeResult::Incomplete)
+        ));
+
+        reader.pause();
+        tx.unbounded_send(cut[cut.len() / 2..].to_vec()).unwrap();
+        reader.resume();
+
+        tx.unbounded_send(frame(Cmd::LoginResp)).unwrap();
+        assert_eq!(decoded_cmd(reader.next().await), Cmd::LoginResp.as_i32());
+    }
+
+    #[tokio::test]
+    async fn resubscribing_drops_a_partial_frame() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut reader = FrameReader::new(rx);
+
+        let cut = frame(Cmd::WifiScanResp);
+        tx.unbounded_send(cut[..cut.len() - 3].to_vec()).unwrap();
+        assert!(matches!(
+            reader.next().await,
+            Some(DecodeResult::Incomplete)
+        ));
+
+        // The device restarts the frame on the new subscription
+        let (tx, rx) = mpsc::unbounded();
+        reader.resubscribe(rx);
+        tx.unbounded_send(frame(Cmd::WifiScanResp)).unwrap();
+        drop(tx);
+
+        assert_eq!(decoded_cmd(reader.next().await), Cmd::WifiScanResp.as_i32());
+        assert!(reader.next().await.is_none());
+    }
+}
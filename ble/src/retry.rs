@@ -0,0 +1,137 @@
+use std::{future::Future, time::Duration};
+
+use tokio::time::sleep;
+
+/// How often and how patiently to retry a failing operation
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub retries: u32,
+    /// Wait before the first retry
+    pub delay: Duration,
+    /// Factor applied to the wait after every retry, 1 keeps it fixed
+    pub multiplier: u32,
+}
+
+impl RetryPolicy {
+    pub fn fixed(retries: u32, delay: Duration) -> Self {
+        Self {
+            retries,
+            delay,
+            multiplier: 1,
+        }
+    }
+
+    pub fn exponential(retries: u32, initial_delay: Duration) -> Self {
+        Self {
+            retries,
+            delay: initial_delay,
+            multiplier: 2,
+        }
+    }
+}
+
+/// Run `op` until it succeeds or the policy's retries are used up,
+/// returning the last error in the latter case
+pub async fn retry<F, Fut, T, E>(policy: &RetryPolicy, mut op: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut retries = policy.retries;
+    let mut delay = policy.delay;
+
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(_) if retries > 0 => {
+                retries -= 1;
+                if !delay.is_zero() {
+                    sleep(delay).await;
+                }
+                delay *= policy.multiplier;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use tokio::time::Instant;
+
+    use super::*;
+
+    const DELAY: Duration = Duration::from_millis(100);
+
+    /// Fails `failures` times, then succeeds; records when each attempt ran
+    async fn flaky(policy: RetryPolicy, failures: usize) -> (Result<usize, usize>, Vec<Duration>) {
+        let start = Instant::now();
+        let attempts = RefCell::new(Vec::new());
+
+        let result = retry(&policy, || {
+            let mut attempts = attempts.borrow_mut();
+            attempts.push(start.elapsed());
+            let attempt = attempts.len();
+            async move {
+                if attempt > failures {
+                    Ok(attempt)
+                } else {
+                    Err(attempt)
+                }
+            }
+        })
+        .await;
+
+        (result, attempts.into_inner())
+    }
+
+    fn millis(attempts: &[u64]) -> Vec<Duration> {
+        attempts
+            .iter()
+            .map(|ms| Duration::from_millis(*ms))
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fixed_policy_waits_the_same_between_attempts() {
+        let (result, attempts) = flaky(RetryPolicy::fixed(3, DELAY), 2).await;
+
+        assert_eq!(result, Ok(3));
+        assert_eq!(attempts, millis(&[0, 100, 200]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn exponential_policy_doubles_the_wait() {
+        let (result, attempts) = flaky(RetryPolicy::exponential(4, DELAY), 4).await;
+
+        assert_eq!(result, Ok(5));
+        assert_eq!(attempts, millis(&[0, 100, 300, 700, 1500]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_with_the_last_error() {
+        let (result, attempts) = flaky(RetryPolicy::exponential(2, DELAY), 5).await;
+
+        assert_eq!(result, Err(3));
+        assert_eq!(attempts, millis(&[0, 100, 300]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn success_on_the_first_attempt_does_not_wait() {
+        let (result, attempts) = flaky(RetryPolicy::fixed(3, DELAY), 0).await;
+
+        assert_eq!(result, Ok(1));
+        assert_eq!(attempts, millis(&[0]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn no_retries_runs_once() {
+        let (result, attempts) = flaky(RetryPolicy::fixed(0, DELAY), 1).await;
+
+        assert_eq!(result, Err(1));
+        assert_eq!(attempts.len(), 1);
+    }
+}
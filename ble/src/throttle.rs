@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use tokio::{
+    sync::Mutex,
+    time::{Instant, sleep_until},
+};
+
+/// Enforces a minimum interval between consecutive characteristic writes,
+/// giving the device time to process each command
+#[derive(Debug, Default)]
+pub struct WriteThrottle {
+    min_interval: Duration,
+    last_write: Mutex<Option<Instant>>,
+}
+
+impl WriteThrottle {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_write: Mutex::new(None),
+        }
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    /// Zero disables throttling. Applies from the next write on
+    pub fn set_min_interval(&mut self, min_interval: Duration) {
+        self.min_interval = min_interval;
+    }
+
+    /// Wait until the next write is allowed and claim the slot. The lock is held while
+    /// waiting, so concurrent writers queue up instead of sharing a slot
+    pub async fn wait(&self) {
+        if self.min_interval.is_zero() {
+            return;
+        }
+
+        let mut last_write = self.last_write.lock().await;
+        if let Some(last) = *last_write {
+            sleep_until(last + self.min_interval).await;
+        }
+        *last_write = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(200);
+
+    #[tokio::test(start_paused = true)]
+    async fn spaces_rapid_writes() {
+        let throttle = WriteThrottle::new(INTERVAL);
+        let start = Instant::now();
+
+        let mut writes = Vec::new();
+        for _ in 0..3 {
+            throttle.wait().await;
+            writes.push(start.elapsed());
+        }
+
+        assert_eq!(writes, [Duration::ZERO, INTERVAL, INTERVAL * 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrent_writers_take_turns() {
+        let throttle = WriteThrottle::new(INTERVAL);
+        let start = Instant::now();
+
+        let write = || async {
+            throttle.wait().await;
+            start.elapsed()
+        };
+        let (a, b, c) = tokio::join!(write(), write(), write());
+
+        let mut writes = [a, b, c];
+        writes.sort();
+        assert_eq!(writes, [Duration::ZERO, INTERVAL, INTERVAL * 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn write_after_a_pause_is_not_delayed() {
+        let throttle = WriteThrottle::new(INTERVAL);
+
+        throttle.wait().await;
+        tokio::time::sleep(INTERVAL * 3).await;
+
+        let before = Instant::now();
+        throttle.wait().await;
+        assert_eq!(before.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_interval_never_waits() {
+        let throttle = WriteThrottle::default();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            throttle.wait().await;
+        }
+
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use bluer::Result;
+use tokio::time::{Instant, timeout_at};
+
+use unquec_model::{
+    commands::Cmd,
+    ttlv::decode::DecodeResult,
+    wifi::{build_wifi_scan_command, parse_wifi_scan_resp},
+};
+
+use crate::link::Link;
+
+/// Sends `WifiScan` and collects the SSIDs from every `WifiScanResp` that arrives within
+/// `window`. Long lists may come split over several responses, so this always waits out
+/// the window. SSIDs are listed once, in the order first seen; other frames are ignored
+pub async fn scan_wifi(link: &mut impl Link, window: Duration) -> Result<Vec<String>> {
+    let deadline = Instant::now() + window;
+    link.send(&build_wifi_scan_command(0)).await?;
+
+    let mut ssids = Vec::new();
+    loop {
+        let result = match timeout_at(deadline, link.recv()).await {
+            Ok(result) => result?,
+            Err(_) => return Ok(ssids),
+        };
+
+        let Some(result) = result else {
+            return Err(bluer::Error {
+                kind: bluer::ErrorKind::Failed,
+                message: "Notifications ended before the wifi scan completed".to_string(),
+            });
+        };
+
+        if let DecodeResult::Success(model) = result
+            && Cmd::from_i32(model.cmd) == Some(Cmd::WifiScanResp)
+        {
+            for ssid in parse_wifi_scan_resp(&model) {
+                if !ssids.contains(&ssid) {
+                    ssids.push(ssid);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unquec_model::{commands::TtlvCommandModel, ttlv::model::TTLVData};
+
+    use super::*;
+    use crate::link::Loopback;
+
+    const WINDOW: Duration = Duration::from_secs(5);
+
+    fn scan_resp(ssids: &[&str]) -> TtlvCommandModel {
+        let entries = ssids
+            .iter()
+            .map(|ssid| TTLVData::new(0, 3, true).with_binary(ssid.as_bytes().to_vec()))
+            .collect();
+        TtlvCommandModel::builder(Cmd::WifiScanResp, 0)
+            .structure(51, entries)
+            .build()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn collects_ssids_from_the_scan_response() {
+        let mut link = Loopback::new(|request| match Cmd::from_i32(request.cmd) {
+            Some(Cmd::WifiScan) => vec![scan_resp(&["home", "", "office"])],
+            _ => Vec::new(),
+        });
+
+        let started = Instant::now();
+        let ssids = scan_wifi(&mut link, WINDOW).await.unwrap();
+
+        assert_eq!(ssids, ["home", "office"]);
+        assert_eq!(link.received_cmds(), [Cmd::WifiScan.as_i32()]);
+        assert_eq!(started.elapsed(), WINDOW);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn merges_responses_and_skips_other_frames() {
+        let mut link = Loopback::new(|request| match Cmd::from_i32(request.cmd) {
+            Some(Cmd::WifiScan) => vec![
+                scan_resp(&["home", "office"]),
+                TtlvCommandModel::builder(Cmd::RandomResp, 0)
+                    .string(1, "1234")
+                    .build(),
+                scan_resp(&["office", "cafe"]),
+            ],
+            _ => Vec::new(),
+        });
+
+        let ssids = scan_wifi(&mut link, WINDOW).await.unwrap();
+
+        assert_eq!(ssids, ["home", "office", "cafe"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_device_yields_no_networks() {
+        let mut link = Loopback::new(|_| Vec::new());
+
+        assert!(scan_wifi(&mut link, WINDOW).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,28 @@
+//! Feeds arbitrary bytes to `DecodeTools::packet_slice`, both at once and split into
+//! notification-sized chunks, to catch panics and hangs in the frame parser.
+//!
+//! Run from the repository root with a nightly toolchain and `cargo-fuzz` installed:
+//!
+//! ```sh
+//! cargo +nightly fuzz run decode fuzz/corpus/decode
+//! ```
+//!
+//! The seed corpus holds the sample frames from `model/examples/samples.txt`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use unquec_model::ttlv::decode::DecodeTools;
+
+fuzz_target!(|data: &[u8]| {
+    let mut decoder = DecodeTools::new();
+    decoder.packet_slice(data);
+
+    // First byte picks the chunk size, mimicking BLE notifications of varying MTU
+    if let Some((&chunk_size, rest)) = data.split_first() {
+        let mut decoder = DecodeTools::new();
+        for chunk in rest.chunks(chunk_size.max(1) as usize) {
+            decoder.packet_slice(chunk);
+        }
+    }
+});
@@ -0,0 +1,74 @@
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use unquec_model::commands::{IotCmd, TtlvCommandModel};
+use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+use unquec_model::ttlv::encode::EncodeTools;
+
+/// `count` data reports back to back, each with payload bytes that get stuffed
+fn pipelined_frames(count: usize) -> Vec<u8> {
+    let mut encode_tools = EncodeTools::new();
+    let mut stream = Vec::new();
+
+    for i in 0..count {
+        let model = TtlvCommandModel::builder(IotCmd::DeviceDataReport, 0)
+            .integer(1, i as i64)
+            .binary(2, vec![0xaa, 0xaa, 0x01, 0xaa, 0x02, 0x03, 0xaa, 0xaa])
+            .string(3, "MCU:2.0.3;DSP:1.1.5")
+            .build();
+        stream.extend(encode_tools.start_encode(&model).unwrap().get_cmd_data());
+    }
+    stream
+}
+
+fn decode_pipelined(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_pipelined");
+
+    for count in [16, 256, 2048] {
+        let stream = pipelined_frames(count);
+
+        // Chunking must not change the outcome: one call, per-notification chunks, byte by byte
+        let results = DecodeTools::new().packet_slice(&stream);
+        assert_eq!(results.len(), count);
+        assert!(
+            results
+                .iter()
+                .all(|result| matches!(result, DecodeResult::Success(_)))
+        );
+        let whole = format!("{:?}", results);
+        for chunk_len in [20, 1] {
+            let mut decode_tools = DecodeTools::new();
+            let chunked: Vec<_> = stream
+                .chunks(chunk_len)
+                .flat_map(|chunk| decode_tools.packet_slice(chunk))
+                .filter(|result| !matches!(result, DecodeResult::Incomplete))
+                .collect();
+            assert_eq!(whole, format!("{:?}", chunked));
+        }
+
+        group.throughput(Throughput::Bytes(stream.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("single_feed", count),
+            &stream,
+            |b, stream| b.iter(|| DecodeTools::new().feed(black_box(stream)).count()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("ble_chunks", count),
+            &stream,
+            |b, stream| {
+                b.iter(|| {
+                    let mut decode_tools = DecodeTools::new();
+                    stream
+                        .chunks(20)
+                        .map(|chunk| decode_tools.feed(black_box(chunk)).count())
+                        .sum::<usize>()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, decode_pipelined);
+criterion_main!(benches);
@@ -0,0 +1,379 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as b64};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::commands::{Cmd, TtlvCommandModel};
+use crate::ttlv::decode::DecodeResult;
+use crate::wifi::WifiPairConfig;
+
+/// Hash used to derive the login value, older firmware may not use SHA-256
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgo {
+    #[default]
+    Sha256,
+    Sha1,
+    Md5,
+}
+
+/// Login value sent with `Login` (0x7034): lowercase hex digest of `"{binding_key};{random}"`,
+/// where `binding_key` is already hex encoded
+pub fn login_hash(binding_key: &str, random_value: &str, algo: HashAlgo) -> String {
+    digest(&format!("{};{}", binding_key, random_value), algo)
+}
+
+/// Lowercase hex digest of `input`
+pub fn digest(input: &str, algo: HashAlgo) -> String {
+    match algo {
+        HashAlgo::Sha256 => bytes_to_hex_str(&Sha256::digest(input.as_bytes())),
+        HashAlgo::Sha1 => bytes_to_hex_str(&Sha1::digest(input.as_bytes())),
+        HashAlgo::Md5 => bytes_to_hex_str(&Md5::digest(input.as_bytes())),
+    }
+}
+
+/// Lowercase hex, two digits per byte
+pub fn bytes_to_hex_str(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Outcome of a login attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginResult {
+    pub success: bool,
+    pub token: Option<String>,
+    pub error: Option<i64>,
+}
+
+/// Interpret a `LoginResp` (0x7035) response.
+///
+/// Field layout (ids 1 and 2 assumed, not confirmed against a capture):
+/// - id 1, boolean or numeric: login status, numeric 0 means success
+/// - id 2, numeric: error code when the login was rejected
+/// - id 3, binary: session token, as text
+///
+/// If id 1 is missing, a token without an error code is taken as success.
+/// Returns `None` if the model is not a login response
+pub fn parse_login_resp(model: &TtlvCommandModel) -> Option<LoginResult> {
+    if Cmd::from_i32(model.cmd) != Some(Cmd::LoginResp) {
+        return None;
+    }
+
+    let token = model
+        .binary_payload(3)
+        .map(|data| String::from_utf8_lossy(data).to_string());
+    let error = model.integer_payload(2);
+    let success = match model.payload(1) {
+        Some(payload) => payload
+            .as_boolean()
+            .or_else(|| payload.as_integer().map(|status| status == 0))
+            .unwrap_or(false),
+        None => token.is_some() && error.is_none(),
+    };
+
+    Some(LoginResult {
+        success,
+        token,
+        error,
+    })
+}
+
+/// Where `LoginStateMachine` is in the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoginState {
+    Idle,
+    RandomRequested,
+    LoggingIn,
+    LoggedIn,
+    Pairing,
+    Paired,
+    Failed,
+}
+
+/// Drives the handshake from decoded responses: `RandomResp` is answered with a `Login`
+/// carrying `login_hash` of the binding key and the random, a successful `LoginResp` with
+/// the `WifiPair` request when one is configured, and `WifiPairResp` hands out the binding
+/// key for the next login.
+///
+/// Responses are handled whatever the current state, since the device may also be paired
+/// directly with `WifiPair` and only ask for a login afterwards
+///
+/// ```
+/// use unquec_model::auth::{HashAlgo, LoginState, LoginStateMachine, login_hash};
+/// use unquec_model::commands::{Cmd, TtlvCommandModel};
+/// use unquec_model::ttlv::decode::DecodeResult;
+/// use unquec_model::wifi::WifiPairConfig;
+///
+/// let mut login = LoginStateMachine::new("3EB24BC7957DB49D")
+///     .with_wifi_pair(WifiPairConfig::new("home", "secret", "mqtt://10.0.0.2:1883"));
+/// assert_eq!(login.start().cmd, Cmd::Random.as_i32());
+///
+/// let random_resp = TtlvCommandModel::builder(Cmd::RandomResp, 1).string(1, "1234").build();
+/// let login_model = login.on_response(&DecodeResult::Success(random_resp)).unwrap();
+/// assert_eq!(login_model.cmd, Cmd::Login.as_i32());
+/// // The binding key is base64, the hash is taken over its hex form
+/// let expected = login_hash("dc4076e010bbf79ec3078f43", "1234", HashAlgo::Sha256);
+/// assert_eq!(login_model.binary_payload(2).unwrap(), expected.as_bytes());
+/// assert_eq!(login.state(), LoginState::LoggingIn);
+///
+/// let login_resp = TtlvCommandModel::builder(Cmd::LoginResp, 2).integer(1, 0).build();
+/// let pair = login.on_response(&DecodeResult::Success(login_resp)).unwrap();
+/// assert_eq!(pair.cmd, Cmd::WifiPair.as_i32());
+/// assert_eq!(login.state(), LoginState::Pairing);
+///
+/// let pair_resp = TtlvCommandModel::builder(Cmd::WifiPairResp, 3).string(9, "AAECAw==").build();
+/// assert!(login.on_response(&DecodeResult::Success(pair_resp)).is_none());
+/// assert_eq!(login.state(), LoginState::Paired);
+/// assert_eq!(login.binding_key(), "AAECAw==");
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoginStateMachine {
+    /// Base64, as `WifiPairResp` hands it out
+    binding_key: String,
+    algo: HashAlgo,
+    wifi_pair: Option<WifiPairConfig>,
+    state: LoginState,
+}
+
+impl LoginStateMachine {
+    /// `binding_key` is base64, as the device handed it out in `WifiPairResp`
+    pub fn new(binding_key: impl Into<String>) -> Self {
+        Self {
+            binding_key: binding_key.into(),
+            algo: HashAlgo::default(),
+            wifi_pair: None,
+            state: LoginState::Idle,
+        }
+    }
+
+    pub fn with_hash_algo(mut self, algo: HashAlgo) -> Self {
+        self.algo = algo;
+        self
+    }
+
+    /// Send `WifiPair` with `config` once logged in
+    pub fn with_wifi_pair(mut self, config: WifiPairConfig) -> Self {
+        self.wifi_pair = Some(config);
+        self
+    }
+
+    pub fn state(&self) -> LoginState {
+        self.state
+    }
+
+    /// The current binding key, updated by each `WifiPairResp`
+    pub fn binding_key(&self) -> &str {
+        &self.binding_key
+    }
+
+    /// The `Random` request that opens the handshake
+    pub fn start(&mut self) -> TtlvCommandModel {
+        self.state = LoginState::RandomRequested;
+        TtlvCommandModel::builder(Cmd::Random, 0).build()
+    }
+
+    /// The command to send in answer to `result`, if any
+    pub fn on_response(&mut self, result: &DecodeResult) -> Option<TtlvCommandModel> {
+        let DecodeResult::Success(model) = result else {
+            return None;
+        };
+
+        match Cmd::from_i32(model.cmd)? {
+            Cmd::RandomResp => {
+                let Some(random) = model.binary_payload(1) else {
+                    log::warn!("Random value missing from response");
+                    self.state = LoginState::Failed;
+                    return None;
+                };
+                let random = String::from_utf8_lossy(random);
+
+                let Ok(binding_key) = b64.decode(&self.binding_key) else {
+                    log::warn!("Binding key {:?} is not base64", self.binding_key);
+                    self.state = LoginState::Failed;
+                    return None;
+                };
+                let value = login_hash(&bytes_to_hex_str(&binding_key), &random, self.algo);
+
+                let login = TtlvCommandModel::builder(Cmd::Login, 0)
+                    .string(2, value)
+                    .build();
+
+                self.state = LoginState::LoggingIn;
+                Some(login)
+            }
+            Cmd::LoginResp => {
+                let login = parse_login_resp(model)?;
+                if !login.success {
+                    log::warn!("Login rejected, error: {:?}", login.error);
+                    self.state = LoginState::Failed;
+                    return None;
+                }
+
+                let Some(config) = &self.wifi_pair else {
+                    self.state = LoginState::LoggedIn;
+                    return None;
+                };
+                match config.build_command(0) {
+                    Ok(pair) => {
+                        self.state = LoginState::Pairing;
+                        Some(pair)
+                    }
+                    Err(err) => {
+                        log::warn!("Invalid wifi pair config: {:?}", err);
+                        self.state = LoginState::Failed;
+                        None
+                    }
+                }
+            }
+            Cmd::WifiPairResp => {
+                match model.binary_payload(9) {
+                    Some(key) => {
+                        self.binding_key = String::from_utf8_lossy(key).to_string();
+                        self.state = LoginState::Paired;
+                    }
+                    None => {
+                        log::warn!("Binding key not found, device is not in pairing mode");
+                        self.state = LoginState::Failed;
+                    }
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ttlv::decode::DecodeTools;
+    use crate::ttlv::encode::EncodeTools;
+
+    /// `model` as the device would send it: framed, then decoded
+    fn received(model: &TtlvCommandModel) -> TtlvCommandModel {
+        let frame = EncodeTools::new().start_encode(model).unwrap();
+        match DecodeTools::new()
+            .packet_slice(frame.get_cmd_data())
+            .as_slice()
+        {
+            [DecodeResult::Success(model)] => model.clone(),
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    fn logging_in() -> LoginStateMachine {
+        let mut login = LoginStateMachine::new("3EB24BC7957DB49D").with_wifi_pair(
+            WifiPairConfig::new("home", "secret", "mqtt://10.0.0.2:1883"),
+        );
+        login.start();
+        let random_resp = TtlvCommandModel::builder(Cmd::RandomResp, 1)
+            .string(1, "1234")
+            .build();
+        login.on_response(&DecodeResult::Success(received(&random_resp)));
+        assert_eq!(login.state(), LoginState::LoggingIn);
+        login
+    }
+
+    const HEX_KEY: &str = "dc4076e010bbf79ec3078f43";
+
+    #[test]
+    fn login_hash_known_answers() {
+        let cases = [
+            (
+                HashAlgo::Sha256,
+                "a83b023a49b5ac90afbae9e0762051d7c08a33aedb04e0251550b19a9806e901",
+            ),
+            (HashAlgo::Sha1, "96a629328f91841e042ec7a213f12e32202fc7af"),
+            (HashAlgo::Md5, "d5e740512c494d87dffd066053e88505"),
+        ];
+
+        for (algo, expected) in cases {
+            assert_eq!(login_hash(HEX_KEY, "1234", algo), expected, "{:?}", algo);
+        }
+    }
+
+    #[test]
+    fn login_uses_the_configured_algo() {
+        let mut login = LoginStateMachine::new("3EB24BC7957DB49D").with_hash_algo(HashAlgo::Md5);
+        login.start();
+        let random_resp = TtlvCommandModel::builder(Cmd::RandomResp, 1)
+            .string(1, "1234")
+            .build();
+
+        let model = login
+            .on_response(&DecodeResult::Success(received(&random_resp)))
+            .unwrap();
+
+        let expected = login_hash(HEX_KEY, "1234", HashAlgo::Md5);
+        assert_eq!(model.binary_payload(2).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn parse_login_resp_success() {
+        let model = TtlvCommandModel::builder(Cmd::LoginResp, 2)
+            .integer(1, 0)
+            .string(3, "token")
+            .build();
+
+        assert_eq!(
+            parse_login_resp(&received(&model)),
+            Some(LoginResult {
+                success: true,
+                token: Some("token".to_string()),
+                error: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_login_resp_failure() {
+        let model = TtlvCommandModel::builder(Cmd::LoginResp, 2)
+            .integer(1, 1)
+            .integer(2, 17)
+            .build();
+
+        assert_eq!(
+            parse_login_resp(&received(&model)),
+            Some(LoginResult {
+                success: false,
+                token: None,
+                error: Some(17),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_login_resp_ignores_other_commands() {
+        let model = TtlvCommandModel::builder(Cmd::RandomResp, 2).build();
+        assert_eq!(parse_login_resp(&model), None);
+    }
+
+    #[test]
+    fn wifi_pair_follows_accepted_login() {
+        let mut login = logging_in();
+
+        let login_resp = TtlvCommandModel::builder(Cmd::LoginResp, 2)
+            .integer(1, 0)
+            .build();
+        let next = login.on_response(&DecodeResult::Success(received(&login_resp)));
+
+        assert_eq!(next.map(|model| model.cmd), Some(Cmd::WifiPair.as_i32()));
+        assert_eq!(login.state(), LoginState::Pairing);
+    }
+
+    #[test]
+    fn rejected_login_does_not_pair() {
+        let mut login = logging_in();
+
+        let login_resp = TtlvCommandModel::builder(Cmd::LoginResp, 2)
+            .integer(1, 1)
+            .integer(2, 17)
+            .build();
+        let next = login.on_response(&DecodeResult::Success(received(&login_resp)));
+
+        assert!(next.is_none());
+        assert_eq!(login.state(), LoginState::Failed);
+    }
+}
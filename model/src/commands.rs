@@ -1,4 +1,10 @@
-use crate::ttlv::model::TTLVData;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::ttlv::config::CodecConfig;
+use crate::ttlv::encode::EncodeTools;
+use crate::ttlv::model::{EncodeError, TTLVData, TtlvTransparentModel, ValidationError};
 
 /// Command model for TTLV protocol
 #[derive(Debug, Clone)]
@@ -40,6 +46,271 @@ impl TtlvCommandModel {
     pub fn get_payloads(&self) -> &Vec<TTLVData> {
         &self.payloads
     }
+
+    /// First payload carrying TTLV `id`, if any
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    ///
+    /// let model = TtlvCommandModel::builder(Cmd::RandomResp, 1)
+    ///     .string(1, "1234")
+    ///     .integer(2, 7)
+    ///     .build();
+    ///
+    /// assert!(model.payload(1).is_some());
+    /// assert!(model.payload(3).is_none());
+    /// assert_eq!(model.binary_payload(1).map(|data| data.as_slice()), Some(&b"1234"[..]));
+    /// assert_eq!(model.binary_payload(2), None);
+    /// assert_eq!(model.integer_payload(2), Some(7));
+    /// assert_eq!(model.integer_payload(9), None);
+    /// ```
+    pub fn payload(&self, id: i32) -> Option<&TTLVData> {
+        self.payloads.iter().find(|payload| payload.id == id)
+    }
+
+    pub fn payload_mut(&mut self, id: i32) -> Option<&mut TTLVData> {
+        self.payloads.iter_mut().find(|payload| payload.id == id)
+    }
+
+    /// Binary value of payload `id`; `None` if missing or of another type
+    pub fn binary_payload(&self, id: i32) -> Option<&Vec<u8>> {
+        self.payload(id).and_then(|payload| payload.as_binary())
+    }
+
+    /// Integer value of payload `id`; `None` if missing or of another type
+    pub fn integer_payload(&self, id: i32) -> Option<i64> {
+        self.payload(id).and_then(|payload| payload.as_integer())
+    }
+
+    /// Total binary/string bytes carried by the payloads, to gauge memory before cloning
+    pub fn payload_bytes_total(&self) -> usize {
+        self.payloads
+            .iter()
+            .map(|payload| payload.value.data_len())
+            .sum()
+    }
+
+    /// Check every payload's `type_id` against its value before sending
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.validate_with(ValidateOptions::default())
+    }
+
+    /// `validate` with extra checks that only some commands call for
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel, ValidateOptions};
+    /// use unquec_model::ttlv::model::ValidationError;
+    ///
+    /// let strict = ValidateOptions { unique_ids: true };
+    ///
+    /// let pair = TtlvCommandModel::builder(Cmd::WifiPair, 1001)
+    ///     .string(1, "home")
+    ///     .string(2, "secret")
+    ///     .build();
+    /// assert_eq!(pair.has_duplicate_ids(), None);
+    /// assert_eq!(pair.validate_with(strict), Ok(()));
+    ///
+    /// // A scan response repeats the SSID id, fine unless uniqueness is asked for
+    /// let scan = TtlvCommandModel::builder(Cmd::WifiScanResp, 7)
+    ///     .string(1, "home")
+    ///     .string(2, "x")
+    ///     .string(1, "office")
+    ///     .build();
+    /// assert_eq!(scan.has_duplicate_ids(), Some(1));
+    /// assert_eq!(scan.validate(), Ok(()));
+    /// assert_eq!(scan.validate_with(strict), Err(ValidationError::DuplicateId(1)));
+    /// ```
+    pub fn validate_with(&self, options: ValidateOptions) -> Result<(), ValidationError> {
+        for payload in &self.payloads {
+            payload.validate()?;
+        }
+        if options.unique_ids
+            && let Some(id) = self.has_duplicate_ids()
+        {
+            return Err(ValidationError::DuplicateId(id));
+        }
+        Ok(())
+    }
+
+    /// First top-level payload id that occurs more than once, in payload order
+    pub fn has_duplicate_ids(&self) -> Option<i32> {
+        self.payloads
+            .iter()
+            .enumerate()
+            .find(|(i, payload)| self.payloads[..*i].iter().any(|seen| seen.id == payload.id))
+            .map(|(_, payload)| payload.id)
+    }
+
+    /// Whether the frame answers one of our requests and should be matched to it by packet id.
+    /// Unknown commands are neither responses nor reports
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    ///
+    /// let random_resp = TtlvCommandModel::new(Cmd::RandomResp.as_i32(), 1);
+    /// assert!(random_resp.is_response());
+    /// assert!(!random_resp.is_report());
+    ///
+    /// let wifi_pair = TtlvCommandModel::new(Cmd::WifiPair.as_i32(), 2);
+    /// assert!(!wifi_pair.is_response());
+    /// assert!(!wifi_pair.is_report());
+    ///
+    /// let report = TtlvCommandModel::new(Cmd::TlsDeviceReport.as_i32(), 3);
+    /// assert!(!report.is_response());
+    /// assert!(report.is_report());
+    /// ```
+    pub fn is_response(&self) -> bool {
+        Command::from_i32(self.cmd).is_some_and(|cmd| cmd.is_response())
+    }
+
+    /// Whether the frame was sent unsolicited by the device, such as a data report
+    pub fn is_report(&self) -> bool {
+        Command::from_i32(self.cmd).is_some_and(|cmd| cmd.is_report())
+    }
+
+    /// This model's fields TTLV-encoded into a transparent payload, for the IoT transparent
+    /// commands (`SendDeviceTransparent` 0x0023, `ReceiveDeviceTransparent` 0x0024) that
+    /// wrap TTLV in raw bytes. The inverse of `TtlvTransparentModel::decode_inner`
+    ///
+    /// ```
+    /// use unquec_model::commands::{IotCmd, TtlvCommandModel};
+    ///
+    /// let model = TtlvCommandModel::builder(IotCmd::SendDeviceTransparent, 12)
+    ///     .integer(1, 42)
+    ///     .string(2, "on")
+    ///     .build();
+    /// let transparent = model.to_transparent().unwrap();
+    ///
+    /// assert_eq!(transparent.cmd, 0x0023);
+    /// assert_eq!(transparent.packet_id, Some(12));
+    /// let inner = transparent.decode_inner();
+    /// assert_eq!(inner.integer_payload(1), Some(42));
+    /// assert_eq!(inner.binary_payload(2).unwrap(), b"on");
+    /// ```
+    pub fn to_transparent(&self) -> Result<TtlvTransparentModel, EncodeError> {
+        let payload = EncodeTools::new().encode_model_payload(self)?;
+        Ok(TtlvTransparentModel::from_bytes(
+            self.cmd as u16,
+            Some(self.packet_id),
+            payload,
+        ))
+    }
+
+    /// Checksum byte the frame would carry when encoded with this model's packet id and
+    /// `config`. Computed over packet id, cmd and payload (frame bytes `[5..]`) with
+    /// `config.checksum`, same as `start_encode`. Assumes a clear payload: with a cipher the
+    /// checksum covers the enciphered bytes, so encode the frame instead
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::config::{ChecksumKind, CodecConfig};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    ///
+    /// let model = TtlvCommandModel::builder(Cmd::Login, 1001).integer(1, 1).build();
+    /// let xor = CodecConfig::builder().checksum(ChecksumKind::Xor).build();
+    ///
+    /// let frame = EncodeTools::with_config(xor.clone())
+    ///     .start_encode_with_packet_id(&model, true)
+    ///     .unwrap();
+    /// assert_eq!(model.checksum_preview(&xor), Ok(frame.pre_stuffed()[4]));
+    /// ```
+    pub fn checksum_preview(&self, config: &CodecConfig) -> Result<u8, EncodeError> {
+        let packet_id = (self.packet_id & 0xFFFF) as u16;
+        let cmd = self.cmd as u16;
+
+        let mut valid_array = Vec::new();
+        valid_array.extend_from_slice(&packet_id.to_be_bytes());
+        valid_array.extend_from_slice(&cmd.to_be_bytes());
+        valid_array.extend(EncodeTools::new().encode_model_payload(self)?);
+
+        Ok(config.checksum.compute(&valid_array))
+    }
+
+    /// Start building a model for `cmd`
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::model::TTLVData;
+    ///
+    /// let chained = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+    ///     .packet_id(1001)
+    ///     .string(1, "home")
+    ///     .string(2, "secret")
+    ///     .integer(11, 30)
+    ///     .integer(12, 380)
+    ///     .string(13, "mqtt://10.0.0.2:1883")
+    ///     .build();
+    ///
+    /// let mut verbose = TtlvCommandModel::new(Cmd::WifiPair.as_i32(), 1001);
+    /// verbose.add_payload(TTLVData::new(1, 3, true).with_binary_str("home"));
+    /// verbose.add_payload(TTLVData::new(2, 3, true).with_binary_str("secret"));
+    /// verbose.add_payload(TTLVData::new(11, 2, true).with_integer(30));
+    /// verbose.add_payload(TTLVData::new(12, 2, true).with_integer(380));
+    /// verbose.add_payload(TTLVData::new(13, 3, true).with_binary_str("mqtt://10.0.0.2:1883"));
+    ///
+    /// assert_eq!(chained.packet_id, verbose.packet_id);
+    /// assert_eq!(chained.payloads, verbose.payloads);
+    /// ```
+    pub fn builder(cmd: impl Into<Command>, packet_id: i32) -> CommandBuilder {
+        CommandBuilder {
+            model: Self::new(cmd.into().as_i32(), packet_id),
+        }
+    }
+}
+
+/// Optional checks for `TtlvCommandModel::validate_with`, all off by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ValidateOptions {
+    /// Reject repeated top-level payload ids. Off by default since some commands, such as
+    /// wifi scan responses, legitimately repeat an id
+    pub unique_ids: bool,
+}
+
+/// Chains payload additions onto a model bound to a command
+#[derive(Debug, Clone)]
+pub struct CommandBuilder {
+    model: TtlvCommandModel,
+}
+
+impl CommandBuilder {
+    pub fn packet_id(mut self, packet_id: i32) -> Self {
+        self.model.packet_id = packet_id;
+        self
+    }
+
+    pub fn boolean(self, id: i32, value: bool) -> Self {
+        self.payload(TTLVData::new(id, 0, true).with_boolean(value))
+    }
+
+    pub fn integer(self, id: i32, value: i64) -> Self {
+        self.payload(TTLVData::new(id, 2, true).with_integer(value))
+    }
+
+    pub fn float(self, id: i32, value: f64) -> Self {
+        self.payload(TTLVData::new(id, 2, true).with_float(value))
+    }
+
+    /// Text is sent as a binary (type 3) field, the way the device expects SSIDs and URLs
+    pub fn string(self, id: i32, value: impl Into<String>) -> Self {
+        self.binary(id, value.into().into_bytes())
+    }
+
+    pub fn binary(self, id: i32, value: Vec<u8>) -> Self {
+        self.payload(TTLVData::new(id, 3, true).with_binary(value))
+    }
+
+    pub fn structure(self, id: i32, value: Vec<TTLVData>) -> Self {
+        self.payload(TTLVData::new(id, 4, true).with_struct(value))
+    }
+
+    pub fn payload(mut self, payload: TTLVData) -> Self {
+        self.model.add_payload(payload);
+        self
+    }
+
+    pub fn build(self) -> TtlvCommandModel {
+        self.model
+    }
 }
 
 /// Base command constants
@@ -59,7 +330,7 @@ pub enum Cmd {
     Login = 0x7034,
     LoginResp = 0x7035,
 
-    BLEAccountAuthentication = 0x7016, //0x7017
+    BLEAccountAuthentication = 0x7016,     //0x7017
     BLEAccountAuthenticationResp = 0x7017, //0x7012
 
     // TLS (Thing Model) commands
@@ -68,7 +339,7 @@ pub enum Cmd {
     TlsWrite = 0x0013,
     TlsDeviceReport = 0x0014,
     TlsWriteRes = 0x7036,
-    
+
     // Wifi pair commands
     WifiPair = 0x7010,
     WifiPairResp = 0x7011,
@@ -110,6 +381,97 @@ impl Cmd {
             _ => None,
         }
     }
+
+    /// Functional group of the command
+    pub fn category(&self) -> CommandCategory {
+        match self {
+            Self::UdpBroadcast | Self::UdpBroadcastResp => CommandCategory::Discovery,
+            Self::TcpHeartBeat | Self::TcpHeartBeatResp => CommandCategory::Heartbeat,
+            Self::Random
+            | Self::RandomResp
+            | Self::Login
+            | Self::LoginResp
+            | Self::BLEAccountAuthentication
+            | Self::BLEAccountAuthenticationResp => CommandCategory::Auth,
+            Self::TlsRead
+            | Self::TlsReadRes
+            | Self::TlsWrite
+            | Self::TlsDeviceReport
+            | Self::TlsWriteRes => CommandCategory::ThingModel,
+            Self::WifiPair | Self::WifiPairResp | Self::WifiScan | Self::WifiScanResp => {
+                CommandCategory::Wifi
+            }
+        }
+    }
+
+    /// Command the peer answers this one with, `None` for responses and reports
+    pub fn response_of(&self) -> Option<Self> {
+        match self {
+            Self::UdpBroadcast => Some(Self::UdpBroadcastResp),
+            Self::TcpHeartBeat => Some(Self::TcpHeartBeatResp),
+            Self::Random => Some(Self::RandomResp),
+            Self::Login => Some(Self::LoginResp),
+            Self::BLEAccountAuthentication => Some(Self::BLEAccountAuthenticationResp),
+            Self::TlsRead => Some(Self::TlsReadRes),
+            Self::TlsWrite => Some(Self::TlsWriteRes),
+            Self::WifiPair => Some(Self::WifiPairResp),
+            Self::WifiScan => Some(Self::WifiScanResp),
+            _ => None,
+        }
+    }
+
+    /// Whether the command answers a request, i.e. is the `response_of` another command
+    pub fn is_response(&self) -> bool {
+        matches!(
+            self,
+            Self::UdpBroadcastResp
+                | Self::TcpHeartBeatResp
+                | Self::RandomResp
+                | Self::LoginResp
+                | Self::BLEAccountAuthenticationResp
+                | Self::TlsReadRes
+                | Self::TlsWriteRes
+                | Self::WifiPairResp
+                | Self::WifiScanResp
+        )
+    }
+
+    /// Whether the command is sent unsolicited by the device
+    pub fn is_report(&self) -> bool {
+        matches!(self, Self::TlsDeviceReport)
+    }
+
+    /// TTLV field ids and names the command is known to carry, empty if undocumented
+    ///
+    /// ```
+    /// use unquec_model::commands::Cmd;
+    ///
+    /// let ids: Vec<i32> = Cmd::WifiPair.known_fields().iter().map(|(id, _)| *id).collect();
+    /// for id in [1, 2, 11, 12, 13] {
+    ///     assert!(ids.contains(&id));
+    /// }
+    /// assert!(Cmd::TcpHeartBeat.known_fields().is_empty());
+    /// ```
+    pub fn known_fields(&self) -> &'static [(i32, &'static str)] {
+        match self {
+            Self::RandomResp => &[(1, "random")],
+            Self::Login => &[(2, "login_hash")],
+            // Ids 1 and 2 assumed, not confirmed against a capture
+            Self::LoginResp => &[(1, "status"), (2, "error"), (3, "token")],
+            Self::BLEAccountAuthentication => &[(1, "auth_type")],
+            // See `wifi::WifiPairConfig` for how 11 and 12 were identified
+            Self::WifiPair => &[
+                (1, "ssid"),
+                (2, "password"),
+                (11, "connect_timeout_sec"),
+                (12, "keepalive_sec"),
+                (13, "mqtt_url"),
+            ],
+            Self::WifiPairResp => &[(9, "binding_key")],
+            Self::WifiScanResp => &[(51, "ssid_list")],
+            _ => &[],
+        }
+    }
 }
 
 /// IoT-specific command constants
@@ -192,6 +554,109 @@ impl IotCmd {
             _ => None,
         }
     }
+
+    /// Functional group of the command
+    pub fn category(&self) -> CommandCategory {
+        match self {
+            Self::ReadDeviceStatus
+            | Self::ReadDeviceStatusAck
+            | Self::ReadDeviceInfo
+            | Self::ReadDeviceInfoAck => CommandCategory::Device,
+            Self::ReadDeviceWifiList
+            | Self::ReadDeviceWifiListAck
+            | Self::ReadDeviceWifiListReport
+            | Self::ReadDeviceWifiListReportAck
+            | Self::ReadDeviceSwitchWifi
+            | Self::ReadDeviceSwitchWifiAck => CommandCategory::Wifi,
+            Self::FileControl | Self::FileControlAck => CommandCategory::File,
+            Self::DeviceDataReport | Self::DeviceDataReportAck => CommandCategory::Report,
+            Self::SendDeviceTransparent | Self::ReceiveDeviceTransparent => {
+                CommandCategory::Transparent
+            }
+            Self::DeviceTimeSyncReport
+            | Self::DeviceTimeSyncReportAck
+            | Self::SendDeviceTimeSyncEvent => CommandCategory::TimeSync,
+            Self::DeviceUnbindReport
+            | Self::DeviceUnbindReportAck
+            | Self::SendDeviceAccountAuth
+            | Self::SendDeviceAccountAuthAck => CommandCategory::Auth,
+        }
+    }
+
+    /// Command the peer answers this one with, `None` for acks and one-way commands.
+    /// Device reports map to the ack the host sends back
+    pub fn response_of(&self) -> Option<Self> {
+        match self {
+            Self::ReadDeviceStatus => Some(Self::ReadDeviceStatusAck),
+            Self::ReadDeviceWifiList => Some(Self::ReadDeviceWifiListAck),
+            Self::ReadDeviceWifiListReport => Some(Self::ReadDeviceWifiListReportAck),
+            Self::ReadDeviceSwitchWifi => Some(Self::ReadDeviceSwitchWifiAck),
+            Self::ReadDeviceInfo => Some(Self::ReadDeviceInfoAck),
+            Self::FileControl => Some(Self::FileControlAck),
+            Self::DeviceDataReport => Some(Self::DeviceDataReportAck),
+            Self::DeviceTimeSyncReport => Some(Self::DeviceTimeSyncReportAck),
+            Self::DeviceUnbindReport => Some(Self::DeviceUnbindReportAck),
+            Self::SendDeviceAccountAuth => Some(Self::SendDeviceAccountAuthAck),
+            _ => None,
+        }
+    }
+
+    /// Whether the command answers a request, i.e. is the `response_of` another command
+    pub fn is_response(&self) -> bool {
+        matches!(
+            self,
+            Self::ReadDeviceStatusAck
+                | Self::ReadDeviceWifiListAck
+                | Self::ReadDeviceWifiListReportAck
+                | Self::ReadDeviceSwitchWifiAck
+                | Self::ReadDeviceInfoAck
+                | Self::FileControlAck
+                | Self::DeviceDataReportAck
+                | Self::DeviceTimeSyncReportAck
+                | Self::DeviceUnbindReportAck
+                | Self::SendDeviceAccountAuthAck
+        )
+    }
+
+    /// Whether the command is sent unsolicited by the device
+    pub fn is_report(&self) -> bool {
+        matches!(
+            self,
+            Self::ReadDeviceWifiListReport
+                | Self::DeviceDataReport
+                | Self::DeviceTimeSyncReport
+                | Self::DeviceUnbindReport
+        )
+    }
+
+    /// TTLV field ids and names the command is known to carry, empty if undocumented
+    pub fn known_fields(&self) -> &'static [(i32, &'static str)] {
+        match self {
+            // Both assumed, not confirmed against a capture
+            Self::ReadDeviceInfoAck => &[(25, "firmware"), (26, "components")],
+            Self::ReadDeviceSwitchWifiAck => &[(1, "connected"), (2, "ip"), (3, "error")],
+            _ => &[],
+        }
+    }
+}
+
+/// Functional grouping of commands, for routing and UI grouping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandCategory {
+    /// UDP broadcast discovery
+    Discovery,
+    Heartbeat,
+    /// Random/login handshake, account auth and unbinding
+    Auth,
+    /// TLS (Thing Model) reads, writes and reports
+    ThingModel,
+    Wifi,
+    /// Device status and info queries
+    Device,
+    File,
+    Report,
+    Transparent,
+    TimeSync,
 }
 
 /// Combined command enum that includes both base commands and IoT commands
@@ -216,12 +681,12 @@ impl Command {
         if let Some(cmd) = Cmd::from_i32(value) {
             return Some(Self::Base(cmd));
         }
-        
+
         // Try IoT commands
         if let Some(cmd) = IotCmd::from_i32(value) {
             return Some(Self::Iot(cmd));
         }
-        
+
         None
     }
 
@@ -234,6 +699,44 @@ impl Command {
     pub fn is_iot(&self) -> bool {
         matches!(self, Self::Iot(_))
     }
+
+    /// Functional group of the command
+    pub fn category(&self) -> CommandCategory {
+        match self {
+            Self::Base(cmd) => cmd.category(),
+            Self::Iot(cmd) => cmd.category(),
+        }
+    }
+
+    /// Command the peer answers this one with, `None` for responses and reports
+    pub fn response_of(&self) -> Option<Self> {
+        match self {
+            Self::Base(cmd) => cmd.response_of().map(Self::Base),
+            Self::Iot(cmd) => cmd.response_of().map(Self::Iot),
+        }
+    }
+
+    pub fn is_response(&self) -> bool {
+        match self {
+            Self::Base(cmd) => cmd.is_response(),
+            Self::Iot(cmd) => cmd.is_response(),
+        }
+    }
+
+    pub fn is_report(&self) -> bool {
+        match self {
+            Self::Base(cmd) => cmd.is_report(),
+            Self::Iot(cmd) => cmd.is_report(),
+        }
+    }
+
+    /// TTLV field ids and names the command is known to carry, empty if undocumented
+    pub fn known_fields(&self) -> &'static [(i32, &'static str)] {
+        match self {
+            Self::Base(cmd) => cmd.known_fields(),
+            Self::Iot(cmd) => cmd.known_fields(),
+        }
+    }
 }
 
 impl From<Cmd> for Command {
@@ -266,6 +769,12 @@ impl From<IotCmd> for i32 {
     }
 }
 
+/// Build the `DeviceDataReportAck` (0x7066) for a received data report, echoing its packet id
+/// so the device can match it. Encode with `start_encode_with_packet_id(.., true)`
+pub fn build_data_report_ack(report: &TtlvCommandModel) -> TtlvCommandModel {
+    TtlvCommandModel::new(IotCmd::DeviceDataReportAck.as_i32(), report.packet_id)
+}
+
 /// Helper functions for working with commands
 pub mod command_utils {
     use super::*;
@@ -300,88 +809,108 @@ pub mod command_utils {
         if let Some(cmd) = Cmd::from_i32(value) {
             return Some(format!("{:?}", cmd));
         }
-        
+
         if let Some(cmd) = IotCmd::from_i32(value) {
             return Some(format!("{:?}", cmd));
         }
-        
+
         None
     }
 }
 
 /// Example usage of the command enums
+#[cfg(feature = "std")]
 pub fn example_command_usage() {
     println!("=== Command Enum Examples ===");
-    
+
     // Using base commands
     let tls_read = Cmd::TlsRead;
     println!("TLS Read command: 0x{:04X}", tls_read.as_i32());
-    
+
     let login = Cmd::Login;
     println!("Login command: 0x{:04X}", login.as_i32());
-    
+
     // Using IoT commands
     let wifi_list = IotCmd::ReadDeviceWifiList;
     println!("WiFi List command: 0x{:04X}", wifi_list.as_i32());
-    
+
     let device_status = IotCmd::ReadDeviceStatus;
     println!("Device Status command: 0x{:04X}", device_status.as_i32());
-    
+
     // Using combined Command enum
     let combined_tls = Command::Base(Cmd::TlsRead);
     let combined_wifi = Command::Iot(IotCmd::ReadDeviceWifiList);
-    
+
     println!("Combined TLS: 0x{:04X}", combined_tls.as_i32());
     println!("Combined WiFi: 0x{:04X}", combined_wifi.as_i32());
-    
+
     // Converting from i32 back to enums
     if let Some(cmd) = Cmd::from_i32(0x0011) {
         println!("0x0011 is: {:?}", cmd);
     }
-    
+
     if let Some(cmd) = IotCmd::from_i32(0x7051) {
         println!("0x7051 is: {:?}", cmd);
     }
-    
+
     if let Some(cmd) = Command::from_i32(0x0011) {
         println!("0x0011 as Command: {:?}", cmd);
     }
-    
+
     // Using utility functions
     let model1 = command_utils::create_base_command(Cmd::TlsRead, 123);
     let model2 = command_utils::create_iot_command(IotCmd::ReadDeviceWifiList, 456);
-    
+
     println!("Model 1 command: 0x{:04X}", model1.cmd);
     println!("Model 2 command: 0x{:04X}", model2.cmd);
-    
+
     // Command name lookup
     if let Some(name) = command_utils::get_command_name(0x0011) {
         println!("Command 0x0011 name: {}", name);
     }
-    
+
     if let Some(name) = command_utils::get_command_name(0x7051) {
         println!("Command 0x7051 name: {}", name);
     }
-    
+
     // Type checking
-    println!("Is 0x0011 a base command? {}", command_utils::is_base_command(0x0011));
-    println!("Is 0x7051 an IoT command? {}", command_utils::is_iot_command(0x7051));
-    println!("Is 0x9999 a valid command? {}", command_utils::is_base_command(0x9999) || command_utils::is_iot_command(0x9999));
+    println!(
+        "Is 0x0011 a base command? {}",
+        command_utils::is_base_command(0x0011)
+    );
+    println!(
+        "Is 0x7051 an IoT command? {}",
+        command_utils::is_iot_command(0x7051)
+    );
+    println!(
+        "Is 0x9999 a valid command? {}",
+        command_utils::is_base_command(0x9999) || command_utils::is_iot_command(0x9999)
+    );
 }
 
 /// Example of creating command models with different command types
+#[cfg(feature = "std")]
 pub fn example_command_models() {
     println!("\n=== Command Model Examples ===");
-    
+
     // Create models using different approaches
     let model1 = TtlvCommandModel::new(Cmd::TlsRead.as_i32(), 1);
     let model2 = TtlvCommandModel::new(IotCmd::ReadDeviceWifiList.as_i32(), 2);
     let model3 = command_utils::create_command(Command::Base(Cmd::Login), 3);
-    
-    println!("Model 1: cmd=0x{:04X}, packet_id={}", model1.cmd, model1.packet_id);
-    println!("Model 2: cmd=0x{:04X}, packet_id={}", model2.cmd, model2.packet_id);
-    println!("Model 3: cmd=0x{:04X}, packet_id={}", model3.cmd, model3.packet_id);
-    
+
+    println!(
+        "Model 1: cmd=0x{:04X}, packet_id={}",
+        model1.cmd, model1.packet_id
+    );
+    println!(
+        "Model 2: cmd=0x{:04X}, packet_id={}",
+        model2.cmd, model2.packet_id
+    );
+    println!(
+        "Model 3: cmd=0x{:04X}, packet_id={}",
+        model3.cmd, model3.packet_id
+    );
+
     // Pattern matching on command types
     for (i, model) in [model1, model2, model3].iter().enumerate() {
         match Command::from_i32(model.cmd) {
@@ -397,3 +926,204 @@ pub fn example_command_models() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ttlv::config::ChecksumKind;
+    use crate::ttlv::decode::{DecodeResult, DecodeTools};
+    use crate::ttlv::model::TTLVValue;
+
+    fn login() -> TtlvCommandModel {
+        let mut model = TtlvCommandModel::new(Cmd::Login as i32, 1001);
+        model.add_payload(TTLVData::new(1, 0, true).with_integer(1));
+        model.add_payload(TTLVData::new(2, 0, true).with_binary(b"token".to_vec()));
+        model
+    }
+
+    /// The checksum byte of the encoded frame. Header and length never need stuffing for
+    /// these short payloads, so it sits at `[4]` even in the stuffed frame
+    fn encoded_checksum(model: &TtlvCommandModel, config: CodecConfig) -> u8 {
+        EncodeTools::with_config(config)
+            .start_encode_with_packet_id(model, true)
+            .unwrap()
+            .pre_stuffed()[4]
+    }
+
+    #[test]
+    fn builder_sets_the_command_and_payloads_in_order() {
+        let model = TtlvCommandModel::builder(Cmd::WifiPair, 1001)
+            .boolean(1, true)
+            .integer(2, -5)
+            .float(3, 1.5)
+            .string(4, "home")
+            .structure(5, vec![TTLVData::new(1, 2, true).with_integer(7)])
+            .build();
+
+        assert_eq!(model.cmd, 0x7010);
+        assert_eq!(model.packet_id, 1001);
+
+        let fields: Vec<_> = model.payloads.iter().map(|p| (p.id, p.type_id)).collect();
+        assert_eq!(fields, [(1, 1), (2, 2), (3, 2), (4, 3), (5, 4)]);
+        assert!(matches!(model.payloads[0].value, TTLVValue::Boolean(true)));
+        assert!(matches!(model.payloads[1].value, TTLVValue::Integer(-5)));
+        assert!(matches!(model.payloads[2].value, TTLVValue::Float(f) if f == 1.5));
+        assert!(matches!(&model.payloads[3].value, TTLVValue::Binary(b) if b == b"home"));
+        assert!(matches!(&model.payloads[4].value, TTLVValue::Struct(s) if s.len() == 1));
+    }
+
+    #[test]
+    fn builder_takes_iot_commands_and_a_later_packet_id() {
+        let model = TtlvCommandModel::builder(IotCmd::ReadDeviceInfo, 0)
+            .packet_id(7)
+            .build();
+
+        assert_eq!(model.cmd, IotCmd::ReadDeviceInfo.as_i32());
+        assert_eq!(model.packet_id, 7);
+        assert!(model.payloads.is_empty());
+    }
+
+    #[test]
+    fn checksum_preview_matches_the_default_encoder() {
+        let config = CodecConfig::default();
+
+        assert_eq!(
+            login().checksum_preview(&config),
+            Ok(encoded_checksum(&login(), config))
+        );
+    }
+
+    #[test]
+    fn checksum_preview_uses_the_configured_checksum() {
+        let sum = CodecConfig::default();
+        let xor = CodecConfig::builder().checksum(ChecksumKind::Xor).build();
+
+        let preview = login().checksum_preview(&xor).unwrap();
+
+        assert_eq!(preview, encoded_checksum(&login(), xor));
+        assert_ne!(preview, login().checksum_preview(&sum).unwrap());
+    }
+
+    #[test]
+    fn checksum_preview_covers_the_payload_before_stuffing() {
+        let mut model = TtlvCommandModel::new(Cmd::WifiPair as i32, 0xaaaa);
+        model.add_payload(TTLVData::new(1, 0, true).with_binary(vec![0xaa, 0xaa, 0x55, 0xaa]));
+
+        for config in [
+            CodecConfig::default(),
+            CodecConfig::builder().checksum(ChecksumKind::Xor).build(),
+        ] {
+            let frame = EncodeTools::with_config(config.clone())
+                .start_encode_with_packet_id(&model, true)
+                .unwrap();
+
+            assert_ne!(frame.get_cmd_data(), frame.pre_stuffed());
+            assert_eq!(
+                model.checksum_preview(&config),
+                Ok(encoded_checksum(&model, config))
+            );
+        }
+    }
+
+    #[test]
+    fn checksum_preview_of_an_empty_payload() {
+        let model = TtlvCommandModel::new(IotCmd::ReadDeviceStatus as i32, 1001);
+
+        for config in [
+            CodecConfig::default(),
+            CodecConfig::builder().checksum(ChecksumKind::Xor).build(),
+        ] {
+            assert_eq!(
+                model.checksum_preview(&config),
+                Ok(encoded_checksum(&model, config))
+            );
+        }
+    }
+
+    #[test]
+    fn data_report_ack_echoes_the_report_packet_id() {
+        let mut report = TtlvCommandModel::new(IotCmd::DeviceDataReport.as_i32(), 4242);
+        report.add_payload(TTLVData::new(1, 2, true).with_integer(23));
+
+        let ack = build_data_report_ack(&report);
+
+        assert_eq!(ack.cmd, 0x7066);
+        assert_eq!(ack.packet_id, 4242);
+        assert!(ack.payloads.is_empty());
+
+        let frame = EncodeTools::new()
+            .start_encode_with_packet_id(&ack, true)
+            .unwrap();
+        let decoded = DecodeTools::new().packet_slice(frame.get_cmd_data()).pop();
+        assert!(matches!(decoded, Some(DecodeResult::Success(m)) if m.packet_id == 4242));
+    }
+
+    #[test]
+    fn every_category_has_a_representative() {
+        let base = [
+            (Cmd::UdpBroadcast, CommandCategory::Discovery),
+            (Cmd::TcpHeartBeatResp, CommandCategory::Heartbeat),
+            (Cmd::Login, CommandCategory::Auth),
+            (Cmd::TlsWriteRes, CommandCategory::ThingModel),
+            (Cmd::WifiScan, CommandCategory::Wifi),
+        ];
+        let iot = [
+            (IotCmd::DeviceUnbindReport, CommandCategory::Auth),
+            (IotCmd::ReadDeviceSwitchWifi, CommandCategory::Wifi),
+            (IotCmd::ReadDeviceInfo, CommandCategory::Device),
+            (IotCmd::FileControlAck, CommandCategory::File),
+            (IotCmd::DeviceDataReport, CommandCategory::Report),
+            (IotCmd::SendDeviceTransparent, CommandCategory::Transparent),
+            (IotCmd::SendDeviceTimeSyncEvent, CommandCategory::TimeSync),
+        ];
+
+        for (cmd, category) in base {
+            assert_eq!(Command::from(cmd).category(), category, "{:?}", cmd);
+        }
+        for (cmd, category) in iot {
+            assert_eq!(Command::from(cmd).category(), category, "{:?}", cmd);
+        }
+    }
+
+    #[test]
+    fn request_and_response_share_a_category() {
+        for value in 0..=0xffff {
+            let Some(cmd) = Command::from_i32(value) else {
+                continue;
+            };
+            if let Some(response) = cmd.response_of() {
+                assert_eq!(response.category(), cmd.category(), "{:?}", cmd);
+            }
+        }
+    }
+
+    #[test]
+    fn payload_bytes_total_counts_binary_string_and_struct_data() {
+        let mut model = TtlvCommandModel::new(Cmd::WifiPair.as_i32(), 0);
+        model.add_payload(TTLVData::new(1, 3, true).with_binary(b"home".to_vec()));
+        model.add_payload(TTLVData::new(2, 3, true).with_binary(vec![0; 10]));
+        model.add_payload(TTLVData::new(11, 2, true).with_integer(30));
+        model.add_payload(TTLVData::new(3, 1, true).with_boolean(true));
+        model.add_payload(TTLVData::new(4, 2, true).with_string("ON".into()));
+        model.add_payload(
+            TTLVData::new(5, 4, true)
+                .with_struct(vec![TTLVData::new(1, 3, true).with_binary(vec![0; 3])]),
+        );
+
+        assert_eq!(model.payload_bytes_total(), 4 + 10 + 2 + 3);
+        assert_eq!(TtlvCommandModel::new(0, 0).payload_bytes_total(), 0);
+    }
+
+    #[test]
+    fn binary_payload_is_borrowed_from_the_model() {
+        let mut model = TtlvCommandModel::new(Cmd::WifiScanResp.as_i32(), 0);
+        model.add_payload(TTLVData::new(1, 3, true).with_binary(vec![0x42; 202]));
+
+        let data: &Vec<u8> = model.binary_payload(1).unwrap();
+
+        let TTLVValue::Binary(stored) = &model.payloads[0].value else {
+            panic!("not binary");
+        };
+        assert!(core::ptr::eq(data, stored));
+    }
+}
@@ -0,0 +1,423 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::{collections::VecDeque, time::Instant};
+
+use crate::auth::parse_login_resp;
+use crate::commands::{Cmd, Command, TtlvCommandModel};
+use crate::time_sync::build_time_sync_event;
+use crate::ttlv::model::EncodeResult;
+
+/// Progress of the BLE session handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ConnectionState {
+    #[default]
+    Connected,
+    RandomRequested,
+    LoggedIn,
+    Bound,
+}
+
+impl ConnectionState {
+    /// Whether `cmd` may be sent in this state.
+    /// Handshake and keep-alive commands are always allowed, `Login` needs a requested
+    /// random, everything else needs a completed login
+    pub fn permits(&self, cmd: i32) -> bool {
+        match Cmd::from_i32(cmd) {
+            Some(
+                Cmd::Random | Cmd::BLEAccountAuthentication | Cmd::UdpBroadcast | Cmd::TcpHeartBeat,
+            ) => true,
+            Some(Cmd::Login) => *self != Self::Connected,
+            _ => matches!(self, Self::LoggedIn | Self::Bound),
+        }
+    }
+}
+
+/// Command rejected because the session has not reached the required state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongState {
+    pub state: ConnectionState,
+    pub cmd: i32,
+}
+
+/// Tracks the session state from sent and received commands and, when enforcing,
+/// rejects commands the device would refuse in the current state
+///
+/// ```
+/// use unquec_model::commands::{Cmd, TtlvCommandModel};
+/// use unquec_model::connection::{ConnectionGuard, ConnectionState};
+/// use unquec_model::ttlv::model::TTLVData;
+///
+/// let mut guard = ConnectionGuard::enforcing();
+/// assert!(guard.on_send(Cmd::WifiPair.as_i32()).is_err());
+///
+/// guard.on_send(Cmd::Random.as_i32()).unwrap();
+/// guard.on_send(Cmd::Login.as_i32()).unwrap();
+/// let mut login_resp = TtlvCommandModel::new(Cmd::LoginResp.as_i32(), 0);
+/// login_resp.add_payload(TTLVData::new(1, 2, true).with_integer(0));
+/// guard.on_receive(&login_resp);
+/// assert_eq!(guard.get_state(), ConnectionState::LoggedIn);
+/// assert!(guard.on_send(Cmd::WifiPair.as_i32()).is_ok());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionGuard {
+    state: ConnectionState,
+    enforce: bool,
+}
+
+impl ConnectionGuard {
+    /// Tracking only, every command is allowed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject commands not permitted in the current state
+    pub fn enforcing() -> Self {
+        Self {
+            enforce: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn get_state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn is_enforcing(&self) -> bool {
+        self.enforce
+    }
+
+    /// Switch enforcement on or off, the tracked state is kept
+    pub fn set_enforce(&mut self, enforce: bool) {
+        self.enforce = enforce;
+    }
+
+    /// Back to `Connected` for a fresh link, e.g. after a reconnect. Enforcement is kept
+    pub fn reset(&mut self) {
+        self.state = ConnectionState::Connected;
+    }
+
+    /// Check `cmd` before sending it and advance the state on success
+    pub fn on_send(&mut self, cmd: i32) -> Result<(), WrongState> {
+        if self.enforce && !self.state.permits(cmd) {
+            return Err(WrongState {
+                state: self.state,
+                cmd,
+            });
+        }
+
+        if cmd == Cmd::Random.as_i32() && self.state == ConnectionState::Connected {
+            self.state = ConnectionState::RandomRequested;
+        }
+
+        Ok(())
+    }
+
+    /// Advance the state from a decoded response. A rejected login leaves it unchanged
+    pub fn on_receive(&mut self, model: &TtlvCommandModel) {
+        match Command::from_i32(model.cmd) {
+            Some(Command::Base(Cmd::LoginResp))
+                if self.state != ConnectionState::Bound
+                    && parse_login_resp(model).is_some_and(|login| login.success) =>
+            {
+                self.state = ConnectionState::LoggedIn;
+            }
+            Some(Command::Base(Cmd::BLEAccountAuthenticationResp))
+                if self.state != ConnectionState::Bound =>
+            {
+                self.state = ConnectionState::LoggedIn;
+            }
+            Some(Command::Base(Cmd::WifiPairResp)) if self.state == ConnectionState::LoggedIn => {
+                self.state = ConnectionState::Bound;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// What a session does on its own once the handshake completes, all off by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SessionOptions {
+    /// Send a `SendDeviceTimeSyncEvent` right after login so the device clock is set
+    pub sync_time_on_connect: bool,
+}
+
+impl SessionOptions {
+    /// Commands to send, in order, once login succeeded. `unix_millis` is the current time
+    ///
+    /// ```
+    /// use unquec_model::commands::IotCmd;
+    /// use unquec_model::connection::SessionOptions;
+    ///
+    /// let options = SessionOptions {
+    ///     sync_time_on_connect: true,
+    /// };
+    /// let commands = options.after_login(1_700_000_000_000);
+    /// assert_eq!(commands.len(), 1);
+    /// assert_eq!(commands[0].cmd, IotCmd::SendDeviceTimeSyncEvent.as_i32());
+    /// assert_eq!(commands[0].integer_payload(1), Some(1_700_000_000_000));
+    ///
+    /// assert!(SessionOptions::default().after_login(1_700_000_000_000).is_empty());
+    /// ```
+    pub fn after_login(&self, unix_millis: i64) -> Vec<TtlvCommandModel> {
+        let mut commands = Vec::new();
+        if self.sync_time_on_connect {
+            commands.push(build_time_sync_event(0, unix_millis));
+        }
+        commands
+    }
+}
+
+/// Capacity of a `CommandLog` built with `Default`
+pub const DEFAULT_COMMAND_LOG_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandLogEntry {
+    pub direction: Direction,
+    pub cmd: i32,
+    pub packet_id: i32,
+    pub timestamp: Instant,
+}
+
+/// The last `capacity` commands sent and received, oldest first, for post-mortem
+/// debugging. Only ids are kept, not frames
+///
+/// ```
+/// use unquec_model::connection::{CommandLog, Direction};
+///
+/// let mut log = CommandLog::new(4);
+/// for packet_id in 0..10 {
+///     log.record(Direction::Sent, 0x7032, packet_id);
+///     log.record(Direction::Received, 0x7033, packet_id);
+/// }
+///
+/// let recent: Vec<_> = log
+///     .entries()
+///     .iter()
+///     .map(|entry| (entry.direction, entry.cmd, entry.packet_id))
+///     .collect();
+/// assert_eq!(
+///     recent,
+///     [
+///         (Direction::Sent, 0x7032, 8),
+///         (Direction::Received, 0x7033, 8),
+///         (Direction::Sent, 0x7032, 9),
+///         (Direction::Received, 0x7033, 9),
+///     ]
+/// );
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct CommandLog {
+    capacity: usize,
+    entries: VecDeque<CommandLogEntry>,
+}
+
+#[cfg(feature = "std")]
+impl CommandLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the capacity, dropping the oldest entries if it shrinks
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn record(&mut self, direction: Direction, cmd: i32, packet_id: i32) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(CommandLogEntry {
+            direction,
+            cmd,
+            packet_id,
+            timestamp: Instant::now(),
+        });
+    }
+
+    /// Entries oldest first
+    pub fn entries(&self) -> Vec<CommandLogEntry> {
+        self.entries.iter().copied().collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for CommandLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_COMMAND_LOG_CAPACITY)
+    }
+}
+
+/// Requests still waiting for a response, keyed by the packet id the encoder assigned.
+/// The device echoes the request's packet id in its response, so `take` recovers which
+/// command a decoded response answers without relying on ordering. Packet ids wrap at
+/// 16 bits, so a reused id replaces the stale entry
+///
+/// ```
+/// use unquec_model::commands::{Cmd, Command, TtlvCommandModel};
+/// use unquec_model::connection::PacketTracker;
+/// use unquec_model::ttlv::encode::EncodeTools;
+///
+/// let mut encode_tools = EncodeTools::new();
+/// let mut tracker = PacketTracker::new();
+///
+/// let random = encode_tools
+///     .start_encode(&TtlvCommandModel::builder(Cmd::Random, 0).build())
+///     .unwrap();
+/// let pair = encode_tools
+///     .start_encode(&TtlvCommandModel::builder(Cmd::WifiPair, 0).build())
+///     .unwrap();
+/// tracker.record(&random);
+/// tracker.record(&pair);
+///
+/// // Responses may arrive in any order
+/// let pair_resp = TtlvCommandModel::new(Cmd::WifiPairResp.as_i32(), pair.get_packet_id() as i32);
+/// assert_eq!(tracker.take(pair_resp.packet_id), Some(Command::Base(Cmd::WifiPair)));
+/// assert_eq!(tracker.take(pair_resp.packet_id), None);
+///
+/// assert_eq!(tracker.take(random.get_packet_id() as i32), Some(Command::Base(Cmd::Random)));
+/// assert!(tracker.is_empty());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PacketTracker {
+    outstanding: BTreeMap<u16, Command>,
+}
+
+impl PacketTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember an encoded request. Commands outside `Command` are not tracked
+    pub fn record(&mut self, result: &EncodeResult) {
+        if let Some(cmd) = Command::from_i32(result.get_cmd() as i32) {
+            self.outstanding.insert(result.get_packet_id(), cmd);
+        }
+    }
+
+    /// The request a response with `packet_id` answers, forgetting it
+    pub fn take(&mut self, packet_id: i32) -> Option<Command> {
+        let packet_id = u16::try_from(packet_id).ok()?;
+        self.outstanding.remove(&packet_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outstanding.is_empty()
+    }
+
+    /// Forget every outstanding request, e.g. after reconnecting
+    pub fn clear(&mut self) {
+        self.outstanding.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ttlv::model::TTLVData;
+
+    use super::*;
+
+    fn login_resp(status: i64) -> TtlvCommandModel {
+        let mut model = TtlvCommandModel::new(Cmd::LoginResp.as_i32(), 0);
+        model.add_payload(TTLVData::new(1, 2, true).with_integer(status));
+        model
+    }
+
+    fn logged_in(mut guard: ConnectionGuard) -> ConnectionGuard {
+        guard.on_send(Cmd::Random.as_i32()).unwrap();
+        guard.on_send(Cmd::Login.as_i32()).unwrap();
+        guard.on_receive(&login_resp(0));
+        guard
+    }
+
+    #[test]
+    fn enforcing_rejects_wifi_pair_before_login() {
+        let mut guard = ConnectionGuard::enforcing();
+
+        assert_eq!(
+            guard.on_send(Cmd::WifiPair.as_i32()),
+            Err(WrongState {
+                state: ConnectionState::Connected,
+                cmd: Cmd::WifiPair.as_i32(),
+            })
+        );
+    }
+
+    #[test]
+    fn enforcing_allows_wifi_pair_after_login() {
+        let mut guard = logged_in(ConnectionGuard::enforcing());
+
+        assert_eq!(guard.get_state(), ConnectionState::LoggedIn);
+        assert_eq!(guard.on_send(Cmd::WifiPair.as_i32()), Ok(()));
+
+        guard.on_receive(&TtlvCommandModel::new(Cmd::WifiPairResp.as_i32(), 0));
+        assert_eq!(guard.get_state(), ConnectionState::Bound);
+    }
+
+    #[test]
+    fn rejected_login_keeps_wifi_pair_blocked() {
+        let mut guard = ConnectionGuard::enforcing();
+        guard.on_send(Cmd::Random.as_i32()).unwrap();
+        guard.on_send(Cmd::Login.as_i32()).unwrap();
+        guard.on_receive(&login_resp(1));
+
+        assert_eq!(guard.get_state(), ConnectionState::RandomRequested);
+        assert!(guard.on_send(Cmd::WifiPair.as_i32()).is_err());
+    }
+
+    #[test]
+    fn login_needs_a_random_first() {
+        let mut guard = ConnectionGuard::enforcing();
+
+        assert!(guard.on_send(Cmd::Login.as_i32()).is_err());
+        guard.on_send(Cmd::Random.as_i32()).unwrap();
+        assert_eq!(guard.on_send(Cmd::Login.as_i32()), Ok(()));
+    }
+
+    #[test]
+    fn reset_starts_over_and_keeps_enforcing() {
+        let mut guard = logged_in(ConnectionGuard::enforcing());
+
+        guard.reset();
+
+        assert_eq!(guard.get_state(), ConnectionState::Connected);
+        assert!(guard.is_enforcing());
+        assert!(guard.on_send(Cmd::WifiPair.as_i32()).is_err());
+    }
+
+    #[test]
+    fn tracking_only_allows_everything_but_follows_the_handshake() {
+        let mut guard = ConnectionGuard::default();
+
+        assert_eq!(guard.on_send(Cmd::WifiPair.as_i32()), Ok(()));
+
+        let guard = logged_in(guard);
+        assert!(!guard.is_enforcing());
+        assert_eq!(guard.get_state(), ConnectionState::LoggedIn);
+    }
+}
@@ -0,0 +1,158 @@
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use crate::commands::TtlvCommandModel;
+
+/// Device information as reported in the device info response.
+///
+/// Field layout (assumed, not confirmed against a capture, as are the example values):
+/// - id 25: module firmware, e.g. `FCM100DAAR12A09_QTHQL_MCU_GENERIC_PROT_V2.3.3`
+/// - id 26: component versions, e.g. `MCU:2.0.3;DSP:1.1.5`
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInfo {
+    pub firmware: Option<String>,
+    pub components: Option<String>,
+}
+
+impl DeviceInfo {
+    pub fn from_model(model: &TtlvCommandModel) -> Self {
+        let text = |id: i32| {
+            model
+                .binary_payload(id)
+                .map(|data| String::from_utf8_lossy(data).to_string())
+        };
+
+        Self {
+            firmware: text(25),
+            components: text(26),
+        }
+    }
+
+    /// Version of the module firmware, taken from the end of the firmware string
+    pub fn firmware_version(&self) -> Option<FirmwareVersion> {
+        self.firmware.as_deref().and_then(FirmwareVersion::parse)
+    }
+}
+
+/// `major.minor.patch` firmware version, ordered for OTA decisions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl FirmwareVersion {
+    /// Parse the last dotted number in `s`, so `..._V2.3.3`, `MCU:2.0.3` and `2.3` all work.
+    /// Missing minor/patch parts default to 0
+    pub fn parse(s: &str) -> Option<Self> {
+        let candidate = s
+            .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .map(|part| part.trim_matches('.'))
+            .rfind(|part| part.contains('.'))?;
+
+        let mut parts = candidate.split('.').map(|part| part.parse::<u32>());
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+        let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::IotCmd;
+
+    fn version(major: u32, minor: u32, patch: u32) -> FirmwareVersion {
+        FirmwareVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    #[test]
+    fn reads_the_device_info_response() {
+        let model = TtlvCommandModel::builder(IotCmd::ReadDeviceInfoAck, 3)
+            .string(25, "FCM100DAAR12A09_QTHQL_MCU_GENERIC_PROT_V2.3.3")
+            .string(26, "MCU:2.0.3;DSP:1.1.5")
+            .build();
+
+        let info = DeviceInfo::from_model(&model);
+
+        assert_eq!(info.components.as_deref(), Some("MCU:2.0.3;DSP:1.1.5"));
+        assert_eq!(info.firmware_version(), Some(version(2, 3, 3)));
+    }
+
+    #[test]
+    fn missing_fields_stay_empty() {
+        let model = TtlvCommandModel::builder(IotCmd::ReadDeviceInfoAck, 3)
+            .integer(25, 7)
+            .build();
+
+        let info = DeviceInfo::from_model(&model);
+
+        assert_eq!(info.firmware, None);
+        assert_eq!(info.components, None);
+        assert_eq!(info.firmware_version(), None);
+    }
+
+    #[test]
+    fn parses_the_module_firmware_string() {
+        assert_eq!(
+            FirmwareVersion::parse("FCM100DAAR12A09_QTHQL_MCU_GENERIC_PROT_V2.3.3"),
+            Some(version(2, 3, 3))
+        );
+        assert_eq!(FirmwareVersion::parse("MCU:2.0.3"), Some(version(2, 0, 3)));
+    }
+
+    #[test]
+    fn parses_without_the_v_prefix() {
+        assert_eq!(
+            FirmwareVersion::parse("FCM100DAAR12A09_QTHQL_MCU_GENERIC_PROT_2.3.3"),
+            Some(version(2, 3, 3))
+        );
+        assert_eq!(FirmwareVersion::parse("2.3"), Some(version(2, 3, 0)));
+    }
+
+    #[test]
+    fn takes_the_last_dotted_number() {
+        assert_eq!(
+            FirmwareVersion::parse("MCU:2.0.3;DSP:1.1.5"),
+            Some(version(1, 1, 5))
+        );
+    }
+
+    #[test]
+    fn rejects_four_components() {
+        assert_eq!(FirmwareVersion::parse("V1.2.3.4"), None);
+    }
+
+    #[test]
+    fn rejects_strings_without_a_version() {
+        assert_eq!(FirmwareVersion::parse("FCM100DAAR12A09"), None);
+        assert_eq!(FirmwareVersion::parse(""), None);
+    }
+
+    #[test]
+    fn orders_numerically() {
+        assert!(version(2, 10, 0) > version(2, 9, 9));
+        assert_eq!(version(2, 3, 3).to_string(), "2.3.3");
+    }
+}
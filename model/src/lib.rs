@@ -1,3 +1,18 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod auth;
 pub mod commands;
+pub mod connection;
+pub mod device_info;
+#[cfg(feature = "test-util")]
+pub mod mock;
+pub mod prelude;
+#[cfg(feature = "std")]
 pub mod quec_ble_device;
+pub mod reports;
+pub mod thing_model;
+pub mod time_sync;
 pub mod ttlv;
+pub mod wifi;
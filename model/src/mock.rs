@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use crate::quec_ble_device::{QUEC_COMPANY_ID, QuecBLEDevice};
+
+/// Advertisement version `advertisement` encodes
+pub const MOCK_ADVERTISEMENT_VERSION: u16 = 0x0001;
+
+/// Manufacturer data of a device advertising `pk`/`dk` with capability `flags`, keyed the
+/// way the BLE stack reports it. The device status byte is 0.
+///
+/// Panics if `dk` is not hex or either key is longer than 255 bytes
+///
+/// ```
+/// use unquec_model::mock;
+/// use unquec_model::quec_ble_device::QuecBLEDevice;
+///
+/// // Binding enabled, Wi-Fi configured, endpoint type 2
+/// let flags = 0b0010_1010;
+/// let data = mock::advertisement("p11xyz", "a1b2c3d4e5f6", flags);
+///
+/// let device = QuecBLEDevice::from_manufacturer_data(&data).unwrap();
+/// assert_eq!(device.product_key, "p11xyz");
+/// assert_eq!(device.device_key, "a1b2c3d4e5f6");
+/// assert_eq!(device.capabilities_bitmask, flags);
+/// assert!(device.is_wifi_config && device.is_enable_bind && !device.is_bind);
+/// assert_eq!(device.endpoint_type, 2);
+///
+/// // Odd-length keys round trip with the old-device bit, upper case with bit 12
+/// let data = mock::advertisement("p11xyz", "A1B2C3D4E5F", 0x1100);
+/// let device = QuecBLEDevice::from_manufacturer_data(&data).unwrap();
+/// assert_eq!(device.device_key, "A1B2C3D4E5F");
+/// ```
+pub fn advertisement(pk: &str, dk: &str, flags: u16) -> HashMap<u16, Vec<u8>> {
+    let device = QuecBLEDevice::from_fields(
+        MOCK_ADVERTISEMENT_VERSION,
+        pk.to_string(),
+        dk.to_string(),
+        0,
+        flags,
+    );
+    let data = device
+        .encode_data()
+        .expect("device key must be hex and both keys at most 255 bytes");
+
+    HashMap::from([(QUEC_COMPANY_ID, data)])
+}
@@ -0,0 +1,40 @@
+//! Commonly used types, for `use unquec_model::prelude::*`
+
+pub use crate::commands::{Cmd, Command, IotCmd, TtlvCommandModel};
+#[cfg(feature = "std")]
+pub use crate::quec_ble_device::QuecBLEDevice;
+pub use crate::ttlv::decode::{DecodeResult, DecodeTools};
+pub use crate::ttlv::encode::EncodeTools;
+pub use crate::ttlv::model::{TTLVData, TTLVValue};
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn prelude_is_enough_for_a_round_trip() {
+        let mut model = TtlvCommandModel::new(Cmd::Login.as_i32(), 1001);
+        model.add_payload(TTLVData::new(1, 1, true).with_integer(1));
+        let frame = EncodeTools::new()
+            .start_encode_with_packet_id(&model, true)
+            .unwrap();
+
+        let decoded = DecodeTools::new().packet_slice(frame.get_cmd_data()).pop();
+
+        let Some(DecodeResult::Success(received)) = decoded else {
+            panic!("unexpected decode: {:?}", decoded);
+        };
+        assert_eq!(
+            Command::from_i32(received.cmd),
+            Some(Command::Base(Cmd::Login))
+        );
+        assert!(matches!(received.payloads[0].value, TTLVValue::Integer(1)));
+        assert_eq!(IotCmd::from_i32(received.cmd), None);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn prelude_names_the_advertisement_decoder() {
+        assert!(QuecBLEDevice::decode_data(&alloc::vec![0; 4]).is_err());
+    }
+}
@@ -1,6 +1,9 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::fmt;
 use std::io::{BufRead, Cursor, Read};
-use byteorder::{BigEndian, ReadBytesExt};
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct QuecBLEDevice {
     pub id: String,
     pub name: String,
@@ -19,12 +22,73 @@ pub struct QuecBLEDevice {
     pub is_old_device: bool,
 }
 
+/// Bluetooth company id the devices advertise their manufacturer data under, "QU"
+pub const QUEC_COMPANY_ID: u16 = 0x55_51;
+
+/// Leading bytes of the manufacturer data under company id 0x5551 ("QU"): "EC" completes
+/// "QUEC", "ig" (0x6967) marks the advertisement layout. Both halves are checked
+pub const ADVERTISEMENT_MAGIC: [u8; 4] = *b"ECig";
+
+/// Shortest manufacturer data that can hold the magic, version and standard fields
+pub const MIN_ADVERTISEMENT_LEN: usize = 19;
+
 #[derive(Debug)]
 pub enum QuecBLEDeviceDecodeError {
+    /// Advertisement carries no manufacturer data under the Quectel company id
+    NoManufacturerData,
     DataTooShort,
-    InvalidHeader(u16),
+    /// Leading bytes (up to 4) that are not `ADVERTISEMENT_MAGIC`
+    InvalidHeader(Vec<u8>),
     DecodeFailed(String),
-    InsufficientFieldData(String, u8)
+    InsufficientFieldData(String, u8),
+}
+
+impl fmt::Display for QuecBLEDeviceDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoManufacturerData => write!(f, "no manufacturer data for company id 0x5551"),
+            Self::DataTooShort => write!(
+                f,
+                "manufacturer data shorter than {} bytes",
+                MIN_ADVERTISEMENT_LEN
+            ),
+            Self::InvalidHeader(found) => write!(
+                f,
+                "manufacturer data starts with {}, expected \"ECig\" ({})",
+                bytes_to_hex_string(found),
+                bytes_to_hex_string(&ADVERTISEMENT_MAGIC)
+            ),
+            Self::DecodeFailed(reason) => write!(f, "decode failed: {}", reason),
+            Self::InsufficientFieldData(name, len) => {
+                write!(f, "field {} declares {} bytes past the end", name, len)
+            }
+        }
+    }
+}
+
+/// Lets the error travel through `?` into `Box<dyn Error>`
+///
+/// ```
+/// use std::error::Error;
+/// use unquec_model::quec_ble_device::QuecBLEDevice;
+///
+/// fn decode(data: &Vec<u8>) -> Result<QuecBLEDevice, Box<dyn Error>> {
+///     Ok(QuecBLEDevice::decode_data(data)?)
+/// }
+///
+/// let err = decode(&vec![0x69, 0x67, 0x00, 0x01]).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "manufacturer data starts with 69670001, expected \"ECig\" (45436967)"
+/// );
+/// ```
+impl std::error::Error for QuecBLEDeviceDecodeError {}
+
+/// Compact `product_key/device_key (mac)` summary
+impl fmt::Display for QuecBLEDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} ({})", self.product_key, self.device_key, self.mac)
+    }
 }
 
 impl From<std::io::Error> for QuecBLEDeviceDecodeError {
@@ -36,29 +100,134 @@ impl From<std::io::Error> for QuecBLEDeviceDecodeError {
     }
 }
 
+/// Device class from bits 4..7 of the advertised flags (`QuecBLEDevice::endpoint_type`).
+/// The names follow the module's connectivity, which is the reading of the nibble we
+/// assume; only Wi-Fi modules have been seen by this tool so far. Other values are kept
+/// as `Unknown`
+///
+/// ```
+/// use unquec_model::quec_ble_device::EndpointType;
+///
+/// assert_eq!(EndpointType::from_nibble(0), EndpointType::Ble);
+/// assert_eq!(EndpointType::from_nibble(1), EndpointType::Wifi);
+/// assert_eq!(EndpointType::from_nibble(2), EndpointType::Cellular);
+/// assert_eq!(EndpointType::from_nibble(9), EndpointType::Unknown(9));
+/// assert_eq!(EndpointType::Unknown(9).as_u8(), 9);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointType {
+    /// Bluetooth-only device
+    Ble,
+    /// Wi-Fi module with BLE for provisioning
+    Wifi,
+    /// Cellular module with BLE for provisioning
+    Cellular,
+    Unknown(u8),
+}
+
+impl EndpointType {
+    pub fn from_nibble(nibble: u8) -> Self {
+        match nibble {
+            0 => Self::Ble,
+            1 => Self::Wifi,
+            2 => Self::Cellular,
+            other => Self::Unknown(other),
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            Self::Ble => 0,
+            Self::Wifi => 1,
+            Self::Cellular => 2,
+            Self::Unknown(other) => other,
+        }
+    }
+}
+
+/// Field layout of the advertisement payload, selected by its version field.
+///
+/// Version to layout mapping:
+/// - any version: `Standard`, i.e. product key, device key, status byte and an optional
+///   2-byte flags word
+///
+/// Versions that reorder or add fields get their own variant once their layout is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdvertisementLayout {
+    Standard,
+}
+
+impl AdvertisementLayout {
+    pub fn for_version(_version: u16) -> Self {
+        // Every version seen so far uses the standard layout
+        Self::Standard
+    }
+}
+
 impl QuecBLEDevice {
-    // Try to decode the manufacturer data into a QuecBLEDevice
-    // This function expects the manufacturer data of the device with id 0x55_51 (QU)
+    /// `endpoint_type` as a device class
+    pub fn endpoint(&self) -> EndpointType {
+        EndpointType::from_nibble(self.endpoint_type)
+    }
+
+    /// Decode the manufacturer data the device advertises under company id 0x5551 ("QU").
+    /// The magic is checked first, so foreign data is reported as `InvalidHeader` whatever
+    /// its length
+    ///
+    /// ```
+    /// use unquec_model::quec_ble_device::{QuecBLEDevice, QuecBLEDeviceDecodeError};
+    ///
+    /// let mut data = b"ECig".to_vec();
+    /// data.extend([0x00, 0x01]); // version
+    /// data.push(8);
+    /// data.extend(b"p11abcde"); // product key
+    /// data.push(6);
+    /// data.extend([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]); // device key
+    /// data.push(0x01); // status
+    ///
+    /// let device = QuecBLEDevice::decode_data(&data).unwrap();
+    /// assert_eq!(device.product_key, "p11abcde");
+    /// assert_eq!(device.device_key, "112233445566");
+    ///
+    /// // Missing the "EC" half, so the layout marker lands first
+    /// let shifted = data[2..].to_vec();
+    /// assert!(matches!(
+    ///     QuecBLEDevice::decode_data(&shifted),
+    ///     Err(QuecBLEDeviceDecodeError::InvalidHeader(found)) if found == [0x69, 0x67, 0x00, 0x01]
+    /// ));
+    ///
+    /// assert!(matches!(
+    ///     QuecBLEDevice::decode_data(&data[..10].to_vec()),
+    ///     Err(QuecBLEDeviceDecodeError::DataTooShort)
+    /// ));
+    /// ```
     pub fn decode_data(manufacturer_data: &Vec<u8>) -> Result<Self, QuecBLEDeviceDecodeError> {
-        // Broadcast data length insufficient. This appears 
-        if manufacturer_data.len() < 19 {
-            return Err(QuecBLEDeviceDecodeError::DataTooShort); 
+        let leading = &manufacturer_data[..manufacturer_data.len().min(ADVERTISEMENT_MAGIC.len())];
+        if !ADVERTISEMENT_MAGIC.starts_with(leading) {
+            return Err(QuecBLEDeviceDecodeError::InvalidHeader(leading.to_vec()));
         }
 
-        let mut ret: QuecBLEDevice;
+        if manufacturer_data.len() < MIN_ADVERTISEMENT_LEN {
+            return Err(QuecBLEDeviceDecodeError::DataTooShort);
+        }
 
         let mut cursor = Cursor::new(manufacturer_data);
+        cursor.set_position(ADVERTISEMENT_MAGIC.len() as u64);
 
-        let header = cursor.read_u16::<BigEndian>()?;
+        let version = cursor.read_u16::<BigEndian>()?;
 
-        if header != 0x69_67 {
-            return Err(QuecBLEDeviceDecodeError::InvalidHeader(header));
+        match AdvertisementLayout::for_version(version) {
+            AdvertisementLayout::Standard => Self::decode_standard(&mut cursor, version),
         }
+    }
 
-        let version = cursor.read_u16::<BigEndian>()?;
-
-        let pk = String::from_utf8_lossy(&read_field(&mut cursor)?).to_string();
-        let mut dk = bytes_to_hex_string(&read_field(&mut cursor)?);
+    /// Fields of the standard layout, following the magic and version
+    fn decode_standard(
+        cursor: &mut Cursor<&Vec<u8>>,
+        version: u16,
+    ) -> Result<Self, QuecBLEDeviceDecodeError> {
+        let pk = String::from_utf8_lossy(&read_field(cursor, "product_key")?).to_string();
+        let mut dk = bytes_to_hex_string(&read_field(cursor, "device_key")?);
 
         let status = cursor.read_u8()?;
         let flags = match cursor.read_u16::<BigEndian>() {
@@ -67,23 +236,34 @@ impl QuecBLEDevice {
         };
 
         if (flags >> 8) & 0x1 == 0x1 {
-            dk = dk[..dk.len() - 1].to_string();
+            dk.pop();
         }
 
         if (flags >> 12) & 0x1 == 0x1 {
             dk = dk.to_uppercase();
         }
 
-        return Ok(QuecBLEDevice {
+        Ok(Self::from_fields(version, pk, dk, status, flags))
+    }
+
+    /// Device with the flag-derived fields filled in, identity fields left empty
+    pub(crate) fn from_fields(
+        version: u16,
+        product_key: String,
+        device_key: String,
+        device_status: u8,
+        flags: u16,
+    ) -> Self {
+        QuecBLEDevice {
             id: String::new(),
             name: String::new(),
             mac: String::new(),
             tag: "QUEC".to_string(),
 
-            version: version,
-            product_key: pk,
-            device_key: dk,
-            device_status: status,
+            version,
+            product_key,
+            device_key,
+            device_status,
             capabilities_bitmask: flags,
             is_cl_dk: check_bit_value(flags, 0),
             is_wifi_config: check_bit_value(flags, 1),
@@ -91,9 +271,67 @@ impl QuecBLEDevice {
             is_enable_bind: check_bit_value(flags, 3),
             endpoint_type: ((flags >> 4) & 0x0F) as u8,
             is_old_device: check_bit_value(flags, 8),
-        });
+        }
     }
 
+    /// Decode the entry under `QUEC_COMPANY_ID` of an advertisement's manufacturer data
+    pub fn from_manufacturer_data(
+        manufacturer_data: &HashMap<u16, Vec<u8>>,
+    ) -> Result<Self, QuecBLEDeviceDecodeError> {
+        let data = manufacturer_data
+            .get(&QUEC_COMPANY_ID)
+            .ok_or(QuecBLEDeviceDecodeError::NoManufacturerData)?;
+
+        Self::decode_data(data)
+    }
+
+    /// Manufacturer data in the standard layout that `decode_data` turns back into this device.
+    /// An odd-length device key is padded with a `0` nibble, which the decoder only drops
+    /// when flag bit 8 (`is_old_device`) is set; the key is written in lower case, which the
+    /// decoder restores to upper case when flag bit 12 is set.
+    /// `None` if the device key is not hex or a key is longer than 255 bytes
+    ///
+    /// ```
+    /// use unquec_model::quec_ble_device::QuecBLEDevice;
+    ///
+    /// let mut data = b"ECig".to_vec();
+    /// data.extend([0x00, 0x02]); // version
+    /// data.push(6);
+    /// data.extend(b"p11xyz"); // product key
+    /// data.push(3);
+    /// data.extend([0xab, 0xcd, 0xe7]); // device key, last nibble dropped
+    /// data.push(0x03); // status
+    /// data.extend([0x11, 0x2c]); // upper case, old device, bound, enable bind, type 2
+    ///
+    /// let device = QuecBLEDevice::decode_data(&data).unwrap();
+    /// assert_eq!(device.device_key, "ABCDE");
+    ///
+    /// let encoded = device.encode_data().unwrap();
+    /// assert_eq!(QuecBLEDevice::decode_data(&encoded).unwrap(), device);
+    /// ```
+    pub fn encode_data(&self) -> Option<Vec<u8>> {
+        let mut dk = self.device_key.to_lowercase();
+        if dk.len() % 2 == 1 {
+            dk.push('0');
+        }
+        let dk = (0..dk.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(dk.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let pk = self.product_key.as_bytes();
+
+        let mut data = ADVERTISEMENT_MAGIC.to_vec();
+        data.write_u16::<BigEndian>(self.version).ok()?;
+        data.push(u8::try_from(pk.len()).ok()?);
+        data.extend_from_slice(pk);
+        data.push(u8::try_from(dk.len()).ok()?);
+        data.extend_from_slice(&dk);
+        data.push(self.device_status);
+        data.write_u16::<BigEndian>(self.capabilities_bitmask)
+            .ok()?;
+
+        Some(data)
+    }
 }
 
 fn check_bit_value(value: u16, bit: u8) -> bool {
@@ -108,11 +346,131 @@ fn bytes_to_hex_string(bytes: &[u8]) -> String {
         .join("")
 }
 
-fn read_field(cursor: &mut Cursor<&Vec<u8>>) -> Result<Vec<u8>, std::io::Error> {
+// Read a length-prefixed field, naming the field if its declared length overruns the data
+fn read_field(
+    cursor: &mut Cursor<&Vec<u8>>,
+    name: &str,
+) -> Result<Vec<u8>, QuecBLEDeviceDecodeError> {
     let len = cursor.read_u8()?;
 
     let mut data = vec![0 as u8; len as usize];
-    cursor.read_exact(&mut data)?;
+    cursor
+        .read_exact(&mut data)
+        .map_err(|error| match error.kind() {
+            std::io::ErrorKind::UnexpectedEof => {
+                QuecBLEDeviceDecodeError::InsufficientFieldData(name.to_string(), len)
+            }
+            _ => error.into(),
+        })?;
 
     Ok(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advertisement(magic: &[u8]) -> Vec<u8> {
+        let mut data = magic.to_vec();
+        data.extend([0x00, 0x01]); // version
+        data.push(8);
+        data.extend(b"p11abcde");
+        data.push(6);
+        data.extend([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+        data.push(0x01); // status
+        data
+    }
+
+    #[test]
+    fn full_magic_decodes() {
+        let device = QuecBLEDevice::decode_data(&advertisement(b"ECig")).unwrap();
+
+        assert_eq!(device.product_key, "p11abcde");
+        assert_eq!(device.device_key, "112233445566");
+    }
+
+    #[test]
+    fn near_miss_magics_are_invalid_headers() {
+        for magic in [b"ecig", b"ECIG", b"EDig", b"ECih", b"igEC", b"\0ECi"] {
+            assert!(
+                matches!(
+                    QuecBLEDevice::decode_data(&advertisement(magic)),
+                    Err(QuecBLEDeviceDecodeError::InvalidHeader(found)) if found == magic
+                ),
+                "{:?}",
+                magic
+            );
+        }
+    }
+
+    #[test]
+    fn partial_magic_is_too_short_not_invalid() {
+        for len in 0..=ADVERTISEMENT_MAGIC.len() {
+            assert!(matches!(
+                QuecBLEDevice::decode_data(&ADVERTISEMENT_MAGIC[..len].to_vec()),
+                Err(QuecBLEDeviceDecodeError::DataTooShort)
+            ));
+        }
+    }
+
+    #[test]
+    fn product_key_overrun_names_the_field() {
+        let mut data = advertisement(b"ECig");
+        data[6] = 0xff;
+
+        assert!(matches!(
+            QuecBLEDevice::decode_data(&data),
+            Err(QuecBLEDeviceDecodeError::InsufficientFieldData(name, 255)) if name == "product_key"
+        ));
+    }
+
+    #[test]
+    fn device_key_overrun_names_the_field() {
+        let mut data = advertisement(b"ECig");
+        data[15] = 20;
+
+        assert!(matches!(
+            QuecBLEDevice::decode_data(&data),
+            Err(QuecBLEDeviceDecodeError::InsufficientFieldData(name, 20)) if name == "device_key"
+        ));
+    }
+
+    #[test]
+    fn odd_length_flag_on_an_empty_device_key() {
+        let mut data = b"ECig".to_vec();
+        data.extend([0x00, 0x01]); // version
+        data.push(9);
+        data.extend(b"p11abcdef");
+        data.push(0); // empty device key
+        data.push(0x00); // status
+        data.extend([0x01, 0x00]); // flags, odd-length device key
+
+        let device = QuecBLEDevice::decode_data(&data).unwrap();
+        assert_eq!(device.product_key, "p11abcdef");
+        assert_eq!(device.device_key, "");
+
+        let field = crate::ttlv::model::TTLVData::new(4, 3, true).with_binary(data);
+        assert_eq!(field.as_quec_device(), Some(device));
+    }
+
+    #[test]
+    fn display_is_a_compact_summary() {
+        let mut device = QuecBLEDevice::decode_data(&advertisement(b"ECig")).unwrap();
+        device.mac = "11:22:33:44:55:66".to_string();
+
+        assert_eq!(
+            device.to_string(),
+            "p11abcde/112233445566 (11:22:33:44:55:66)"
+        );
+    }
+
+    #[test]
+    fn discovered_devices_can_be_cloned_and_printed() {
+        let device = QuecBLEDevice::decode_data(&advertisement(b"ECig")).unwrap();
+        let mut discovered = HashMap::new();
+        discovered.insert(device.device_key.clone(), device.clone());
+
+        assert_eq!(discovered["112233445566"], device);
+        assert!(format!("{:?}", device).contains("product_key: \"p11abcde\""));
+    }
+}
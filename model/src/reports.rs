@@ -0,0 +1,119 @@
+use alloc::collections::BTreeMap;
+
+use crate::commands::{IotCmd, TtlvCommandModel};
+use crate::ttlv::model::TTLVValue;
+
+/// A `DeviceDataReport` (0x7065): thing-model property values pushed by the device, keyed by
+/// property id. This is what the MQTT bridge publishes
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceDataReport {
+    /// Milliseconds since the Unix epoch, if the device sent one
+    pub timestamp: Option<i64>,
+    pub properties: BTreeMap<i32, TTLVValue>,
+}
+
+impl DeviceDataReport {
+    /// Split a data report into its timestamp and its properties. Which payload id carries
+    /// the timestamp depends on the product's thing model, so it is passed in as
+    /// `timestamp_id`: an integer under that id becomes the timestamp, with `None` every
+    /// field is a property. Returns `None` if the model is not a `DeviceDataReport`
+    ///
+    /// ```
+    /// use unquec_model::commands::{IotCmd, TtlvCommandModel};
+    /// use unquec_model::reports::DeviceDataReport;
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    /// use unquec_model::ttlv::model::TTLVValue;
+    ///
+    /// let sent = TtlvCommandModel::builder(IotCmd::DeviceDataReport, 0)
+    ///     .integer(1, 23)
+    ///     .boolean(2, true)
+    ///     .string(3, "idle")
+    ///     .integer(9, 1_700_000_000_000)
+    ///     .build();
+    /// let frame = EncodeTools::new().start_encode(&sent).unwrap();
+    ///
+    /// let received = match DecodeTools::new().packet_slice(frame.get_cmd_data()).as_slice() {
+    ///     [DecodeResult::Success(model)] => model.clone(),
+    ///     other => panic!("unexpected decode: {:?}", other),
+    /// };
+    /// let report = DeviceDataReport::from_model(&received, Some(9)).unwrap();
+    ///
+    /// assert_eq!(report.timestamp, Some(1_700_000_000_000));
+    /// assert_eq!(report.properties.len(), 3);
+    /// assert!(matches!(report.properties[&1], TTLVValue::Integer(23)));
+    /// assert!(matches!(report.properties[&2], TTLVValue::Boolean(true)));
+    /// // Strings travel as binary (type 3), so they come back as bytes
+    /// assert!(matches!(&report.properties[&3], TTLVValue::Binary(b) if b == b"idle"));
+    ///
+    /// let ack = TtlvCommandModel::builder(IotCmd::DeviceDataReportAck, 0).build();
+    /// assert_eq!(DeviceDataReport::from_model(&ack, Some(9)), None);
+    /// ```
+    pub fn from_model(model: &TtlvCommandModel, timestamp_id: Option<i32>) -> Option<Self> {
+        if IotCmd::from_i32(model.cmd) != Some(IotCmd::DeviceDataReport) {
+            return None;
+        }
+
+        let mut timestamp = None;
+        let mut properties = BTreeMap::new();
+
+        for payload in &model.payloads {
+            match &payload.value {
+                TTLVValue::Integer(ms) if Some(payload.id) == timestamp_id => timestamp = Some(*ms),
+                value => {
+                    properties.insert(payload.id, value.clone());
+                }
+            }
+        }
+
+        Some(Self {
+            timestamp,
+            properties,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn report() -> TtlvCommandModel {
+        TtlvCommandModel::builder(IotCmd::DeviceDataReport, 0)
+            .integer(0, 1_700_000_000_000)
+            .integer(1, 23)
+            .build()
+    }
+
+    #[test]
+    fn without_a_timestamp_id_every_field_is_a_property() {
+        let report = DeviceDataReport::from_model(&report(), None).unwrap();
+
+        assert_eq!(report.timestamp, None);
+        assert_eq!(
+            report.properties.keys().copied().collect::<Vec<_>>(),
+            [0, 1]
+        );
+    }
+
+    #[test]
+    fn timestamp_id_takes_that_field_out_of_the_properties() {
+        let report = DeviceDataReport::from_model(&report(), Some(0)).unwrap();
+
+        assert_eq!(report.timestamp, Some(1_700_000_000_000));
+        assert_eq!(report.properties.keys().copied().collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn non_integer_under_the_timestamp_id_stays_a_property() {
+        let model = TtlvCommandModel::builder(IotCmd::DeviceDataReport, 0)
+            .string(5, "noon")
+            .build();
+
+        let report = DeviceDataReport::from_model(&model, Some(5)).unwrap();
+
+        assert_eq!(report.timestamp, None);
+        assert!(report.properties.contains_key(&5));
+    }
+}
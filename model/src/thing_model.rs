@@ -0,0 +1,114 @@
+use alloc::vec::Vec;
+
+use crate::commands::{Cmd, TtlvCommandModel};
+use crate::ttlv::model::TTLVValue;
+
+/// Helpers for TLS (Thing Model) commands
+pub struct ThingModel;
+
+/// Outcome of a thing-model write, as acknowledged by `TlsWriteRes` (0x7036)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteResult {
+    pub success: bool,
+    pub failed_ids: Vec<i32>,
+}
+
+impl ThingModel {
+    /// Interpret a `TlsWriteRes` (0x7036) response.
+    ///
+    /// Each payload echoes a written property id and carries its status:
+    /// - boolean (type 0/1): `true` means the property was written
+    /// - numeric (type 2): status code, `0` means the property was written
+    ///
+    /// A response without payloads is a global acknowledgement of the whole write.
+    /// Returns `None` if the model is not a `TlsWriteRes`
+    pub fn parse_write_res(model: &TtlvCommandModel) -> Option<WriteResult> {
+        if Cmd::from_i32(model.cmd) != Some(Cmd::TlsWriteRes) {
+            return None;
+        }
+
+        let mut failed_ids = Vec::new();
+
+        for payload in &model.payloads {
+            let ok = match &payload.value {
+                TTLVValue::Boolean(b) => *b,
+                TTLVValue::Integer(code) => *code == 0,
+                _ => continue,
+            };
+
+            if !ok {
+                failed_ids.push(payload.id);
+            }
+        }
+
+        Some(WriteResult {
+            success: failed_ids.is_empty(),
+            failed_ids,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ttlv::decode::{DecodeResult, DecodeTools};
+    use crate::ttlv::encode::EncodeTools;
+    use crate::ttlv::model::TTLVData;
+
+    fn write_res(payloads: Vec<TTLVData>) -> TtlvCommandModel {
+        let mut model = TtlvCommandModel::new(Cmd::TlsWriteRes.as_i32(), 0);
+        for payload in payloads {
+            model.add_payload(payload);
+        }
+        model
+    }
+
+    fn received(model: &TtlvCommandModel) -> TtlvCommandModel {
+        let frame = EncodeTools::new().start_encode(model).unwrap();
+        match DecodeTools::new().packet_slice(frame.get_cmd_data()).pop() {
+            Some(DecodeResult::Success(model)) => model,
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_the_failed_property() {
+        let res = write_res(vec![
+            TTLVData::new(1, 1, true).with_boolean(true),
+            TTLVData::new(2, 2, true).with_integer(3),
+        ]);
+
+        let result = ThingModel::parse_write_res(&received(&res)).unwrap();
+
+        assert_eq!(
+            result,
+            WriteResult {
+                success: false,
+                failed_ids: vec![2],
+            }
+        );
+    }
+
+    #[test]
+    fn zero_status_and_empty_response_are_success() {
+        let per_id = write_res(vec![
+            TTLVData::new(1, 2, true).with_integer(0),
+            TTLVData::new(2, 1, true).with_boolean(true),
+        ]);
+        let global = write_res(Vec::new());
+
+        for res in [per_id, global] {
+            let result = ThingModel::parse_write_res(&received(&res)).unwrap();
+            assert!(result.success);
+            assert!(result.failed_ids.is_empty());
+        }
+    }
+
+    #[test]
+    fn other_commands_are_not_write_results() {
+        let mut write = TtlvCommandModel::new(Cmd::TlsWrite.as_i32(), 0);
+        write.add_payload(TTLVData::new(1, 0, true).with_boolean(false));
+
+        assert_eq!(ThingModel::parse_write_res(&write), None);
+    }
+}
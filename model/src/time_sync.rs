@@ -0,0 +1,21 @@
+use crate::commands::{IotCmd, TtlvCommandModel};
+
+/// `SendDeviceTimeSyncEvent` (0x7062) carrying the current time, sent unsolicited so the
+/// device can set its clock without first asking with a `DeviceTimeSyncReport`.
+///
+/// Field layout (assumed, not confirmed against a capture):
+/// - id 1, numeric: UTC time in milliseconds since the Unix epoch
+///
+/// ```
+/// use unquec_model::commands::IotCmd;
+/// use unquec_model::time_sync::build_time_sync_event;
+///
+/// let model = build_time_sync_event(0, 1_700_000_000_000);
+/// assert_eq!(model.cmd, IotCmd::SendDeviceTimeSyncEvent.as_i32());
+/// assert_eq!(model.integer_payload(1), Some(1_700_000_000_000));
+/// ```
+pub fn build_time_sync_event(packet_id: i32, unix_millis: i64) -> TtlvCommandModel {
+    TtlvCommandModel::builder(IotCmd::SendDeviceTimeSyncEvent, packet_id)
+        .integer(1, unix_millis)
+        .build()
+}
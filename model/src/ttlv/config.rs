@@ -0,0 +1,133 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::ttlv::decode::DEFAULT_MAX_PACKET_LEN;
+
+/// How the checksum byte (frame offset 4) is computed over frame bytes `[5..]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ChecksumKind {
+    /// Wrapping sum, what the modules use
+    #[default]
+    Sum,
+    Xor,
+}
+
+impl ChecksumKind {
+    pub fn compute(self, data: &[u8]) -> u8 {
+        match self {
+            Self::Sum => data.iter().fold(0u8, |acc, &byte| acc.wrapping_add(byte)),
+            Self::Xor => data.iter().fold(0u8, |acc, &byte| acc ^ byte),
+        }
+    }
+}
+
+/// Framing options shared by `EncodeTools` and `DecodeTools`, so both ends agree.
+/// The default matches the modules: additive checksum, `0xAA 0x55` stuffing and
+/// `ReceiveDeviceTransparent` (0x0024) as the only transparent command
+///
+/// ```
+/// use unquec_model::commands::{Cmd, TtlvCommandModel};
+/// use unquec_model::ttlv::config::{ChecksumKind, CodecConfig};
+/// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+/// use unquec_model::ttlv::encode::EncodeTools;
+///
+/// let config = CodecConfig::builder()
+///     .checksum(ChecksumKind::Xor)
+///     .stuffing(false)
+///     .build();
+///
+/// let model = TtlvCommandModel::builder(Cmd::TlsWrite, 7)
+///     .binary(1, vec![0xaa, 0x55, 0x01])
+///     .build();
+/// let frame = EncodeTools::with_config(config.clone())
+///     .start_encode_with_packet_id(&model, true)
+///     .unwrap();
+///
+/// // Nothing stuffed, and the checksum is the XOR of bytes [5..]
+/// assert_eq!(frame.get_cmd_data(), frame.pre_stuffed());
+/// let data = frame.get_cmd_data();
+/// assert_eq!(data[4], data[5..].iter().fold(0, |acc, byte| acc ^ byte));
+///
+/// match DecodeTools::with_config(config).packet_slice(data).first() {
+///     Some(DecodeResult::Success(decoded)) => {
+///         assert_eq!(decoded.packet_id, 7);
+///         assert_eq!(decoded.binary_payload(1), Some(&vec![0xaa, 0x55, 0x01]));
+///     }
+///     other => panic!("unexpected {:?}", other),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecConfig {
+    pub checksum: ChecksumKind,
+    /// Insert a `0x55` after every `0xAA` past the header on encode and strip it on decode
+    pub stuffing: bool,
+    /// Commands whose payload is decoded as a `TtlvTransparentModel` instead of TTLV fields
+    pub transparent_cmds: Vec<u16>,
+    /// Declared payload lengths above this are rejected instead of buffered
+    pub max_packet_len: usize,
+}
+
+impl Default for CodecConfig {
+    fn default() -> Self {
+        Self {
+            checksum: ChecksumKind::Sum,
+            stuffing: true,
+            transparent_cmds: vec![0x0024],
+            max_packet_len: DEFAULT_MAX_PACKET_LEN,
+        }
+    }
+}
+
+impl CodecConfig {
+    /// Start from the defaults and override individual options
+    pub fn builder() -> CodecConfigBuilder {
+        CodecConfigBuilder {
+            config: Self::default(),
+        }
+    }
+
+    pub fn is_transparent(&self, cmd: u16) -> bool {
+        self.transparent_cmds.contains(&cmd)
+    }
+}
+
+/// Chains option overrides onto a default `CodecConfig`
+#[derive(Debug, Clone)]
+pub struct CodecConfigBuilder {
+    config: CodecConfig,
+}
+
+impl CodecConfigBuilder {
+    pub fn checksum(mut self, checksum: ChecksumKind) -> Self {
+        self.config.checksum = checksum;
+        self
+    }
+
+    pub fn stuffing(mut self, stuffing: bool) -> Self {
+        self.config.stuffing = stuffing;
+        self
+    }
+
+    /// Replace the transparent command list
+    pub fn transparent_cmds(mut self, cmds: Vec<u16>) -> Self {
+        self.config.transparent_cmds = cmds;
+        self
+    }
+
+    /// Add one command to the transparent command list
+    pub fn transparent_cmd(mut self, cmd: u16) -> Self {
+        if !self.config.transparent_cmds.contains(&cmd) {
+            self.config.transparent_cmds.push(cmd);
+        }
+        self
+    }
+
+    pub fn max_packet_len(mut self, max_packet_len: usize) -> Self {
+        self.config.max_packet_len = max_packet_len;
+        self
+    }
+
+    pub fn build(self) -> CodecConfig {
+        self.config
+    }
+}
@@ -1,5 +1,14 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
 use crate::commands::TtlvCommandModel;
-use crate::ttlv::model::{TTLVData, TTLVValue, TtlvTransparentModel};
+use crate::ttlv::cipher::PayloadCipher;
+use crate::ttlv::config::CodecConfig;
+use crate::ttlv::encode::EncodeTools;
+use crate::ttlv::model::{TTLVData, TTLVValue, TtlvTransparentModel, shift_decimal};
 
 /// Result of decoding TTLV data
 #[derive(Debug)]
@@ -7,6 +16,13 @@ pub enum DecodeResult {
     Success(TtlvCommandModel),
     Transparent(TtlvTransparentModel),
     Incomplete, // Need more data
+    /// Frame whose checksum byte does not match its contents, kept whole (de-stuffed)
+    /// for inspection. `expected` is computed from the bytes, `actual` is what the frame carries
+    ChecksumMismatch {
+        expected: u8,
+        actual: u8,
+        frame: Vec<u8>,
+    },
     Error(String),
 }
 
@@ -15,127 +31,328 @@ pub enum DecodeResult {
 pub struct DecodeTools {
     stbuf: Vec<u8>,
     receive_data: Vec<u8>,
+    /// Bytes at the front of `receive_data` already decoded or skipped. Dropped in one go
+    /// on the next `feed` instead of shifting the buffer after every frame
+    consumed: usize,
+    /// The trailing `0xAA` of `receive_data` already had its stuffing `0x55` dropped, so
+    /// a `0x55` arriving next is data
+    destuffed_aa: bool,
+    config: CodecConfig,
+    cipher: Option<Box<dyn PayloadCipher>>,
+    debug_hex_limit: usize,
 }
 
+/// Default limit on the declared payload length, well above any observed frame
+pub const DEFAULT_MAX_PACKET_LEN: usize = 4096;
+
+/// Header, length, checksum, packet id and cmd: the shortest frame that carries a command
+pub const MIN_FRAME_LEN: usize = 9;
+
 impl DecodeTools {
     pub fn new() -> Self {
+        Self::with_config(CodecConfig::default())
+    }
+
+    /// Reject frames whose declared payload length exceeds `max_packet_len`, instead of
+    /// buffering until that many bytes arrive
+    pub fn with_limits(max_packet_len: usize) -> Self {
+        Self::with_config(CodecConfig {
+            max_packet_len,
+            ..CodecConfig::default()
+        })
+    }
+
+    /// Expect frames as produced by an `EncodeTools` with the same `config`
+    pub fn with_config(config: CodecConfig) -> Self {
         Self {
             stbuf: vec![0xaa, 0xaa],
             receive_data: Vec::new(),
+            consumed: 0,
+            destuffed_aa: false,
+            config,
+            cipher: None,
+            debug_hex_limit: 64,
+        }
+    }
+
+    pub fn config(&self) -> &CodecConfig {
+        &self.config
+    }
+
+    /// Decode a frame pasted from a log in the `\xNN` escaped format
+    pub fn decode_escaped(&mut self, text: &str) -> Vec<DecodeResult> {
+        self.packet_slice(&parse_escaped_hex(text))
+    }
+
+    /// Cap how many buffered bytes the debug output formats, the rest is summarized
+    pub fn set_debug_hex_limit(&mut self, limit: usize) {
+        self.debug_hex_limit = limit;
+    }
+
+    /// Hex dump of at most `debug_hex_limit` bytes, followed by `... (N more)` if cut
+    pub fn format_debug_hex(&self, data: &[u8]) -> String {
+        let shown = data.len().min(self.debug_hex_limit);
+        let mut hex = data[..shown]
+            .iter()
+            .map(|b| format!("0x{:02x}", b))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        if data.len() > shown {
+            if shown > 0 {
+                hex.push_str(", ");
+            }
+            hex.push_str(&format!("... ({} more)", data.len() - shown));
         }
+        hex
+    }
+
+    /// Decrypt payloads with `cipher` from now on, `None` expects them in the clear
+    pub fn set_cipher(&mut self, cipher: Option<Box<dyn PayloadCipher>>) {
+        self.cipher = cipher;
     }
 
-    /// Process incoming data packets and return results
+    /// Drop any partially received frame.
+    /// Partial frames are not resumable: after a reconnect the device restarts the frame
+    /// from the header, so buffered bytes must be discarded on every (re)subscription.
+    /// Configuration and cipher are kept
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    ///
+    /// let random = TtlvCommandModel::new(Cmd::Random.as_i32(), 0);
+    /// let frame = EncodeTools::new().encode_command(&random, 1000).unwrap();
+    /// let mut decode_tools = DecodeTools::new();
+    ///
+    /// // The old session drops mid-frame
+    /// decode_tools.packet_slice(&frame[..6]);
+    /// assert!(!decode_tools.pending().is_empty());
+    ///
+    /// // Without the reset the stale header would claim the new frame's bytes
+    /// decode_tools.reset();
+    /// assert!(decode_tools.pending().is_empty());
+    /// assert!(matches!(
+    ///     decode_tools.packet_slice(&frame).as_slice(),
+    ///     [DecodeResult::Success(model)] if model.cmd == Cmd::Random.as_i32()
+    /// ));
+    /// ```
+    pub fn reset(&mut self) {
+        self.receive_data.clear();
+        self.consumed = 0;
+        self.destuffed_aa = false;
+    }
+
+    /// Bytes buffered from previous calls, already de-stuffed. Between calls this is
+    /// either empty, a lone trailing `0xAA` that may begin the next header, or the start of
+    /// an incomplete frame beginning with its `0xAA 0xAA` header
+    pub fn pending(&self) -> &[u8] {
+        &self.receive_data[self.consumed..]
+    }
+
+    /// Process incoming data packets and return results, collected from `feed`.
+    /// Bytes that cannot belong to a frame are dropped, see `pending` for what is kept
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    ///
+    /// let random = TtlvCommandModel::new(Cmd::Random.as_i32(), 0);
+    /// let frame = EncodeTools::new().encode_command(&random, 1000).unwrap();
+    ///
+    /// // A frame split anywhere within its first 9 bytes still decodes once complete
+    /// for split in 0..=8 {
+    ///     let mut decode_tools = DecodeTools::new();
+    ///     let first = decode_tools.packet_slice(&frame[..split]);
+    ///     assert!(first.iter().all(|result| matches!(result, DecodeResult::Incomplete)));
+    ///     assert_eq!(decode_tools.pending(), &frame[..split]);
+    ///
+    ///     let rest = decode_tools.packet_slice(&frame[split..]);
+    ///     assert!(matches!(rest.as_slice(), [DecodeResult::Success(_)]));
+    ///     assert!(decode_tools.pending().is_empty());
+    /// }
+    ///
+    /// // Short buffers without a header keep at most a trailing 0xAA
+    /// let mut decode_tools = DecodeTools::new();
+    /// decode_tools.packet_slice(&[0x01, 0x02, 0xaa]);
+    /// assert_eq!(decode_tools.pending(), &[0xaa]);
+    /// decode_tools.packet_slice(&[0x01, 0x02]);
+    /// assert!(decode_tools.pending().is_empty());
+    ///
+    /// // Junk before a partial header is dropped
+    /// decode_tools.packet_slice(&[0x01, 0xaa, 0xaa, 0x00]);
+    /// assert_eq!(decode_tools.pending(), &[0xaa, 0xaa, 0x00]);
+    ///
+    /// // A bad frame is reported with its bytes and decoding carries on with the next one
+    /// let mut bad = frame.clone();
+    /// bad[4] ^= 0xff;
+    /// let stream = [frame.clone(), bad.clone(), frame.clone()].concat();
+    /// match DecodeTools::new().packet_slice(&stream).as_slice() {
+    ///     [
+    ///         DecodeResult::Success(_),
+    ///         DecodeResult::ChecksumMismatch { expected, actual, frame: kept },
+    ///         DecodeResult::Success(_),
+    ///     ] => {
+    ///         assert_eq!((*expected, *actual), (frame[4], bad[4]));
+    ///         assert_eq!(kept, &bad);
+    ///     }
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// ```
     pub fn packet_slice(&mut self, data: &[u8]) -> Vec<DecodeResult> {
-        let bytes = data.to_vec();
-        self.receive_data.extend_from_slice(&bytes);
-        self.receive_data = self.splice_buffer(&self.receive_data);
+        self.feed(data).collect()
+    }
 
-        let mut results = Vec::new();
+    /// Buffer `data` and yield the results it completes one at a time, without collecting
+    /// them. Frames left unread when the iterator is dropped stay buffered for the next call
+    pub fn feed(&mut self, data: &[u8]) -> impl Iterator<Item = DecodeResult> + '_ {
+        self.receive_data.drain(..self.consumed);
+        self.consumed = 0;
 
-        while !self.receive_data.is_empty() {
-            if self.receive_data.len() < 9 {
-                println!("Received data is too short");
-                results.push(DecodeResult::Incomplete);
-                return results;
-            }
-
-            if let Some(start_index) = self.find_subsequence(&self.receive_data, &self.stbuf) {
-                if start_index < self.receive_data.len() {
-                    // Find data field length (checksum to data field length)
-                    let payload_len = if start_index + 3 < self.receive_data.len() {
-                        let use_byte = [
-                            self.receive_data[start_index + 2],
-                            self.receive_data[start_index + 3],
-                        ];
-                        self.read_byte_array_short(&use_byte) as usize
-                    } else {
-                        0
-                    };
+        if self.config.stuffing {
+            self.extend_destuffed(data);
+        } else {
+            self.receive_data.extend_from_slice(data);
+        }
 
-                    println!(
-                        "receive_data=[{:?}], start_index={}, payload_len={}",
-                        self.receive_data.iter().map(|b| format!("0x{:02x}", b)).collect::<Vec<String>>().join(", "), start_index, payload_len
-                    );
+        // Errors resync past the bad bytes and carry on, only `Incomplete` waits for more data
+        let mut waiting = false;
+        core::iter::from_fn(move || {
+            if waiting {
+                return None;
+            }
+            let result = self.next_result()?;
+            waiting = matches!(result, DecodeResult::Incomplete);
+            Some(result)
+        })
+    }
 
-                    if self.receive_data.len() < start_index + payload_len + 4 {
-                        println!(
-                            "The data length is insufficient, continue to receive data, receiveData-len={}",
-                            self.receive_data.len()
-                        );
-                        self.receive_data = self.receive_data[start_index..].to_vec();
-                        results.push(DecodeResult::Incomplete);
-                        return results;
-                    }
+    /// Append `data` with the stuffing removed, same as `splice_buffer` over the whole
+    /// buffer. `destuffed_aa` carries over whether a trailing `0xAA` still expects its
+    /// `0x55`, so only the new bytes need a pass
+    fn extend_destuffed(&mut self, data: &[u8]) {
+        self.receive_data.reserve(data.len());
+        for &byte in data {
+            if byte == 0x55 && self.receive_data.last() == Some(&0xaa) && !self.destuffed_aa {
+                log::trace!("remove 55");
+                self.destuffed_aa = true;
+                continue;
+            }
+            self.receive_data.push(byte);
+            self.destuffed_aa = false;
+        }
+    }
 
-                    let n_buf_copy =
-                        self.receive_data[start_index..start_index + payload_len + 4].to_vec();
-                    self.receive_data = self.receive_data[start_index + payload_len + 4..].to_vec();
+    /// Decode the next frame from the buffer, `None` if the buffer is empty
+    fn next_result(&mut self) -> Option<DecodeResult> {
+        let pending = &self.receive_data[self.consumed..];
+        if pending.is_empty() {
+            return None;
+        }
 
-                    match self.crc_security(&n_buf_copy) {
-                        Ok(result) => results.push(result),
-                        Err(e) => results.push(DecodeResult::Error(e)),
-                    }
-                }
-            } else {
-                // Didn't find packet header, check if last byte is 0xAA
-                if !self.receive_data.is_empty() {
-                    if self.receive_data.last() == Some(&0xaa) {
-                        // Last byte might be first byte of packet header, clear previous data
-                        self.receive_data = vec![0xaa];
-                        results.push(DecodeResult::Incomplete);
-                        return results;
-                    } else {
-                        // Invalid data
-                        self.receive_data.clear();
-                        results.push(DecodeResult::Error(
-                            "Invalid data - no packet header found".to_string(),
-                        ));
-                        return results;
-                    }
-                } else {
-                    // Empty data
-                    self.receive_data.clear();
-                    results.push(DecodeResult::Incomplete);
-                    return results;
-                }
+        let Some(start_index) = self.find_subsequence(pending, &self.stbuf) else {
+            // Without a header only a trailing 0xAA can still begin one
+            let keep_aa = pending.last() == Some(&0xaa);
+            let destuffed_aa = self.destuffed_aa;
+            self.reset();
+            if keep_aa {
+                self.receive_data.push(0xaa);
+                self.destuffed_aa = destuffed_aa;
+                return Some(DecodeResult::Incomplete);
             }
+            return Some(DecodeResult::Error(
+                "Invalid data - no packet header found".to_string(),
+            ));
+        };
+
+        // Bytes before the header can never be part of a frame
+        self.consumed += start_index;
+        let pending = &self.receive_data[self.consumed..];
+
+        if pending.len() < MIN_FRAME_LEN {
+            log::trace!("Received data is too short");
+            return Some(DecodeResult::Incomplete);
+        }
+
+        // Data field length, from the checksum to the end of the payload
+        let payload_len = self.read_byte_array_short(&[pending[2], pending[3]]) as usize;
+
+        log::trace!(
+            "receive_data=[{:?}], payload_len={}",
+            self.format_debug_hex(pending),
+            payload_len
+        );
+
+        // De-stuffing runs over the length bytes too, so a length field of
+        // `0xAA 0x55` comes out as an implausible 0xAAxx and ends up here
+        if payload_len > self.config.max_packet_len {
+            // Drop the header so the search resumes past it
+            self.consumed += 2;
+            let err = format!(
+                "Declared payload length {} exceeds limit {}",
+                payload_len, self.config.max_packet_len
+            );
+            return Some(DecodeResult::Error(err));
         }
 
-        results
+        if pending.len() < payload_len + 4 {
+            log::trace!(
+                "The data length is insufficient, continue to receive data, receiveData-len={}",
+                pending.len()
+            );
+            return Some(DecodeResult::Incomplete);
+        }
+
+        // A frame failing its checksum is skipped whole, by its declared length
+        let frame = self.consumed..self.consumed + payload_len + 4;
+        self.consumed = frame.end;
+
+        Some(match self.crc_security(&self.receive_data[frame]) {
+            Ok(result) => result,
+            Err(e) => DecodeResult::Error(e),
+        })
     }
 
     /// Check CRC and parse data, returning Result instead of using callbacks
     fn crc_security(&self, data: &[u8]) -> Result<DecodeResult, String> {
-        if data.len() < 5 {
-            return Err("Data too short".to_string());
+        // A declared length below 5 yields a frame with no room for packet id and cmd;
+        // reject it here rather than reading them as 0 and reporting an illegal cmd
+        if data.len() < MIN_FRAME_LEN {
+            return Err(format!(
+                "Frame too short: {} bytes, need {} for packet id and cmd",
+                data.len(),
+                MIN_FRAME_LEN
+            ));
         }
 
-        let crc_buf = &data[5..];
-        let n_xor = self.sum_calculation(crc_buf);
-        let old_xor = data[4];
+        let checked_bytes = &data[5..];
+        let computed = self.config.checksum.compute(checked_bytes);
+        let received = data[4];
 
-        if n_xor == old_xor {
-            let packet_id = if data.len() >= 7 {
-                let use_byte = [data[5], data[6]];
-                self.read_byte_array_short(&use_byte)
-            } else {
-                0
-            };
-
-            let cmd = if data.len() >= 9 {
-                let use_byte = [data[7], data[8]];
-                self.read_byte_array_short(&use_byte)
-            } else {
-                0
-            };
+        if computed == received {
+            let cmd = self.read_byte_array_short(&[data[7], data[8]]);
 
             if cmd == 0 || cmd == 0xffff {
-                println!("=cmd 非法=");
+                log::warn!("cmd 非法: 0x{:04X}", cmd);
                 return Err("cmd 非法".to_string());
             }
 
-            if cmd == 0x0024 {
+            // The checksum covers the encrypted bytes, so decrypt only once it matched
+            let decrypted;
+            let data = match &self.cipher {
+                Some(cipher) if data.len() > 9 => {
+                    let payload = cipher.decrypt(&data[9..]).ok_or_else(|| {
+                        "Payload does not decrypt with the session key".to_string()
+                    })?;
+                    decrypted = [&data[..9], payload.as_slice()].concat();
+                    decrypted.as_slice()
+                }
+                _ => data,
+            };
+
+            if self.config.is_transparent(cmd as u16) {
                 Ok(DecodeResult::Transparent(
                     self.parse_transparent_payload(data),
                 ))
@@ -143,8 +360,16 @@ impl DecodeTools {
                 Ok(DecodeResult::Success(self.parse_payload(data)))
             }
         } else {
-            println!("crc error=");
-            Err("crc error".to_string())
+            log::warn!(
+                "crc error: computed 0x{:02x}, frame 0x{:02x}",
+                computed,
+                received
+            );
+            Ok(DecodeResult::ChecksumMismatch {
+                expected: computed,
+                actual: received,
+                frame: data.to_vec(),
+            })
         }
     }
 
@@ -169,70 +394,76 @@ impl DecodeTools {
         obj.packet_id = packet_id;
         obj.cmd = cmd;
 
-        // Payload container
-        let mut payload_data = Vec::new();
-        let payload_raw = if data.len() > 9 { &data[9..] } else { &[] };
+        // Payload container. A minimal ack frame (`payload_len == 5`) is exactly 9 bytes
+        // long and carries no payload, which yields an empty slice here
+        let payload_raw = data.get(9..).unwrap_or(&[]);
+        obj.payloads = self.parse_ttlv_fields(payload_raw);
 
-        let payload = payload_raw.to_vec();
+        obj
+    }
 
-        if !payload.is_empty() {
-            let mut offset = 0;
-            while offset < payload.len() {
-                if offset + 1 >= payload.len() {
-                    break;
-                }
+    /// Parse a sequence of TTLV fields, skipping the ones that can't be interpreted
+    pub fn parse_ttlv_fields(&self, payload: &[u8]) -> Vec<TTLVData> {
+        let mut payload_data = Vec::new();
 
-                let use_short = [payload[offset], payload[offset + 1]];
-                let ttlv_head = self.read_byte_array_short(&use_short);
-                offset += 2;
+        let mut offset = 0;
+        while offset < payload.len() {
+            if offset + 1 >= payload.len() {
+                break;
+            }
 
-                let ttlv_id = (ttlv_head >> 3) & 0x1fff;
-                let ttlv_type = ttlv_head & 0x07;
+            let use_short = [payload[offset], payload[offset + 1]];
+            let ttlv_head = self.read_byte_array_short(&use_short);
+            offset += 2;
 
-                let mut ttlv_data = None;
+            let ttlv_id = (ttlv_head >> 3) & 0x1fff;
+            let ttlv_type = ttlv_head & 0x07;
 
-                if ttlv_type == 3 || ttlv_type == 5 {
-                    // Binary data
-                    if let Some(p_obj) = self.parse_binary(&payload, offset) {
-                        offset = p_obj.offset;
-                        let mut data = TTLVData::new(ttlv_id, ttlv_type as i32, true);
-                        data.value = TTLVValue::Binary(p_obj.data);
-                        ttlv_data = Some(data);
-                    } else {
-                        offset += 2;
-                        continue;
-                    }
-                } else if ttlv_type == 0 || ttlv_type == 1 {
-                    // Boolean
-                    let mut data = TTLVData::new(ttlv_id, ttlv_type as i32, true);
-                    data.value = TTLVValue::Boolean(ttlv_type == 1);
+            let mut ttlv_data = None;
+
+            if ttlv_type == 3 || ttlv_type == 5 {
+                // Binary data
+                if let Some(p_obj) = self.parse_binary(payload, offset) {
+                    offset = p_obj.offset;
+                    let mut data = TTLVData::new(ttlv_id, ttlv_type, true);
+                    data.value = TTLVValue::Binary(p_obj.data);
+                    ttlv_data = Some(data);
+                }
+            } else if ttlv_type == 0 || ttlv_type == 1 {
+                // Boolean
+                let mut data = TTLVData::new(ttlv_id, ttlv_type, true);
+                data.value = TTLVValue::Boolean(ttlv_type == 1);
+                ttlv_data = Some(data);
+            } else if ttlv_type == 2 {
+                // Enum and numeric
+                if let Some(parse_num_data) = self.parse_enum_value(payload, offset) {
+                    offset = parse_num_data.offset;
+                    let mut data = TTLVData::new(ttlv_id, ttlv_type, true);
+                    data.decimal = parse_num_data.decimal();
+                    data.value = parse_num_data.value;
+                    ttlv_data = Some(data);
+                }
+            } else if ttlv_type == 4 {
+                // Struct
+                if let Some(parse_struct_data) = self.parse_struct(payload, offset) {
+                    offset = parse_struct_data.offset;
+                    let mut data = TTLVData::new(ttlv_id, ttlv_type, true);
+                    data.value = TTLVValue::Struct(parse_struct_data.data);
                     ttlv_data = Some(data);
-                } else if ttlv_type == 2 {
-                    // Enum and numeric
-                    if let Some(parse_num_data) = self.parse_enum_value(&payload, offset) {
-                        offset = parse_num_data.offset;
-                        let mut data = TTLVData::new(ttlv_id, ttlv_type as i32, true);
-                        data.value = parse_num_data.value;
-                        ttlv_data = Some(data);
-                    }
-                } else if ttlv_type == 4 {
-                    // Struct
-                    if let Some(parse_struct_data) = self.parse_struct(&payload, offset) {
-                        offset = parse_struct_data.offset;
-                        let mut data = TTLVData::new(ttlv_id, ttlv_type as i32, true);
-                        data.value = TTLVValue::Struct(parse_struct_data.data);
-                        ttlv_data = Some(data);
-                    }
                 }
+            }
 
-                if let Some(data) = ttlv_data {
-                    payload_data.push(data);
+            match ttlv_data {
+                Some(data) => payload_data.push(data),
+                None => {
+                    // Without a parsed length the field's end is unknown, keep the rest as is
+                    payload_data.push(raw_field(ttlv_id, ttlv_type, &payload[offset..]));
+                    break;
                 }
             }
-            obj.payloads = payload_data;
         }
 
-        obj
+        payload_data
     }
 
     /// Parse transparent payload
@@ -257,13 +488,46 @@ impl DecodeTools {
         model.cmd = cmd as u16;
 
         // Payload container
-        let payload_raw = if data.len() > 9 { &data[9..] } else { &[] };
+        let payload_raw = data.get(9..).unwrap_or(&[]);
 
         model.payloads = payload_raw.to_vec();
         model
     }
 
-    /// Parse struct data
+    /// Parse struct data: a 2-byte element count, then that many TTLV fields, recursing
+    /// into nested structs
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    /// use unquec_model::ttlv::model::TTLVData;
+    ///
+    /// let innermost = TTLVData::new(3, 4, true).with_struct(vec![
+    ///     TTLVData::new(1, 3, true).with_binary_str("leaf"),
+    ///     TTLVData::new(2, 1, true).with_boolean(true),
+    /// ]);
+    /// let middle = TTLVData::new(2, 4, true).with_struct(vec![
+    ///     innermost,
+    ///     TTLVData::new(4, 0, true).with_boolean(false),
+    /// ]);
+    /// let outer = TTLVData::new(1, 4, true).with_struct(vec![
+    ///     TTLVData::new(5, 3, true).with_binary_str("before"),
+    ///     middle,
+    /// ]);
+    /// let model = TtlvCommandModel::builder(Cmd::TlsWrite, 7)
+    ///     .payload(outer)
+    ///     .string(6, "after")
+    ///     .build();
+    ///
+    /// let frame = EncodeTools::new().start_encode(&model).unwrap();
+    /// let Some(DecodeResult::Success(decoded)) =
+    ///     DecodeTools::new().packet_slice(frame.get_cmd_data()).pop()
+    /// else {
+    ///     panic!("frame did not decode");
+    /// };
+    /// assert_eq!(decoded.payloads, model.payloads);
+    /// ```
     pub fn parse_struct(&self, payload: &[u8], offset: usize) -> Option<ParseStructData> {
         if offset + 1 >= payload.len() {
             return None;
@@ -289,30 +553,29 @@ impl DecodeTools {
                 let ttlv_id = (ttlv_head >> 3) & 0x1fff;
                 let ttlv_type = ttlv_head & 0x07;
 
+                let mut ttlv_data = None;
+
                 if ttlv_type == 3 || ttlv_type == 5 {
                     // Binary data
                     if let Some(p_obj) = self.parse_binary(payload, offset) {
                         offset = p_obj.offset;
                         let mut data = TTLVData::new(ttlv_id, ttlv_type as i32, true);
                         data.value = TTLVValue::Binary(p_obj.data);
-                        stc_elements.push(data);
-                    } else {
-                        offset += 2;
-                        remaining -= 1;
-                        continue;
+                        ttlv_data = Some(data);
                     }
                 } else if ttlv_type == 0 || ttlv_type == 1 {
                     // Boolean
                     let mut data = TTLVData::new(ttlv_id, ttlv_type as i32, true);
                     data.value = TTLVValue::Boolean(ttlv_type == 1);
-                    stc_elements.push(data);
+                    ttlv_data = Some(data);
                 } else if ttlv_type == 2 {
                     // Enum and numeric
                     if let Some(parse_num_data) = self.parse_enum_value(payload, offset) {
                         offset = parse_num_data.offset;
                         let mut data = TTLVData::new(ttlv_id, ttlv_type as i32, true);
+                        data.decimal = parse_num_data.decimal();
                         data.value = parse_num_data.value;
-                        stc_elements.push(data);
+                        ttlv_data = Some(data);
                     }
                 } else if ttlv_type == 4 {
                     // Struct
@@ -320,7 +583,16 @@ impl DecodeTools {
                         offset = parse_struct_data.offset;
                         let mut data = TTLVData::new(ttlv_id, ttlv_type as i32, true);
                         data.value = TTLVValue::Struct(parse_struct_data.data);
-                        stc_elements.push(data);
+                        ttlv_data = Some(data);
+                    }
+                }
+
+                match ttlv_data {
+                    Some(data) => stc_elements.push(data),
+                    None => {
+                        stc_elements.push(raw_field(ttlv_id, ttlv_type, &payload[offset..]));
+                        offset = payload.len();
+                        break;
                     }
                 }
 
@@ -344,7 +616,8 @@ impl DecodeTools {
         let ttlv_len = self.read_byte_array_short(&use_short) as usize;
         let mut offset = offset + 2;
 
-        if ttlv_len > 0 && offset + ttlv_len <= payload.len() {
+        // Zero length is valid, e.g. hidden networks in a wifi scan response
+        if offset + ttlv_len <= payload.len() {
             let bytes = payload[offset..offset + ttlv_len].to_vec();
             offset += ttlv_len;
 
@@ -357,7 +630,62 @@ impl DecodeTools {
         }
     }
 
-    /// Parse enum value
+    /// Parse a type-2 (enum/numeric) value.
+    ///
+    /// On the wire it is one header byte followed by a big-endian magnitude:
+    /// - bit 7: sign, 1 is negative
+    /// - bits 6..3: decimal count, the value is magnitude / 10^count
+    /// - bits 2..0: magnitude length minus 1, so 1 to 8 bytes
+    ///
+    /// There is no separate string form. A signed magnitude outside the i64 range is not
+    /// a valid number, so rather than wrapping or clamping it the magnitude bytes are
+    /// returned as `TTLVValue::String` via lossy UTF-8, and the fields after it still
+    /// decode. `-2^63` is in range and decodes to `i64::MIN`.
+    ///
+    /// With a decimal count the value is also given as an f64, which is only exact while
+    /// the magnitude stays below 2^53; `ParseNumData::decimal()` keeps the exact mantissa
+    ///
+    /// ```
+    /// use unquec_model::ttlv::decode::DecodeTools;
+    /// use unquec_model::ttlv::model::TTLVValue;
+    ///
+    /// let tools = DecodeTools::new();
+    /// let parse = |header: u8, magnitude: [u8; 8]| {
+    ///     let mut payload = vec![header];
+    ///     payload.extend(magnitude);
+    ///     tools.parse_enum_value(&payload, 0).unwrap()
+    /// };
+    ///
+    /// // Every width from 1 to 8 bytes reads exactly that many bytes
+    /// for width in 1..=8u8 {
+    ///     let mut payload = vec![width - 1, 0x01];
+    ///     payload.extend(vec![0x00; width as usize - 1]);
+    ///     payload.push(0xff); // next field, must not be read
+    ///     let parsed = tools.parse_enum_value(&payload, 0).unwrap();
+    ///     assert_eq!(parsed.value, TTLVValue::Integer(1 << (8 * (width - 1))));
+    ///     assert_eq!(parsed.offset, 1 + width as usize);
+    /// }
+    ///
+    /// // 8-byte magnitudes at both ends of the range
+    /// let max = parse(0x07, i64::MAX.to_be_bytes());
+    /// assert_eq!(max.value, TTLVValue::Integer(i64::MAX));
+    /// assert_eq!(max.offset, 9);
+    /// let min = parse(0x87, (1u64 << 63).to_be_bytes());
+    /// assert_eq!(min.value, TTLVValue::Integer(i64::MIN));
+    /// let near_min = parse(0x87, i64::MAX.to_be_bytes());
+    /// assert_eq!(near_min.value, TTLVValue::Integer(-i64::MAX));
+    ///
+    /// // Out of range either way
+    /// let too_low = parse(0x87, ((1u64 << 63) + 1).to_be_bytes());
+    /// assert!(matches!(too_low.value, TTLVValue::String(_)));
+    /// assert_eq!(too_low.decimal(), None);
+    /// assert!(matches!(parse(0x07, u64::MAX.to_be_bytes()).value, TTLVValue::String(_)));
+    ///
+    /// // 15 decimals on a 19-digit mantissa: the float is rounded, the mantissa is not
+    /// let fine = parse(0xff, i64::MAX.to_be_bytes());
+    /// assert_eq!(fine.decimal(), Some((-i64::MAX, 15)));
+    /// assert_eq!(fine.value, TTLVValue::Float(-9223.372036854776));
+    /// ```
     pub fn parse_enum_value(&self, payload: &[u8], offset: usize) -> Option<ParseNumData> {
         if offset >= payload.len() {
             return None;
@@ -368,50 +696,66 @@ impl DecodeTools {
 
         let negative = (lenbuf & 0xff) >> 7;
         let amp = (lenbuf >> 3) & 0x0f;
-        let tmp_len = (lenbuf & 0x07) + 1;
+        // Bits 2..0 hold the width minus one, so a width of 0 cannot be expressed and the
+        // widest is 8 bytes
+        let width = usize::from(lenbuf & 0x07) + 1;
 
-        // println!("lenbuf={}, negative={}, amp={}, tmp_len={}", lenbuf, negative, amp, tmp_len);
+        // log::trace!("lenbuf={}, negative={}, amp={}, width={}", lenbuf, negative, amp, width);
 
-        if offset + tmp_len as usize > payload.len() {
-            return None;
-        }
-
-        let buf = payload[offset..offset + tmp_len as usize].to_vec();
-        offset += tmp_len as usize;
+        let buf = payload.get(offset..offset + width)?;
+        offset += width;
 
-        let enum_value = self.read_byte_array_long(&buf);
+        // Right-aligned in 8 bytes: the magnitude always fits a u64, and a width past 8
+        // would panic here rather than silently read the wrong bytes
+        let mut magnitude = [0u8; 8];
+        magnitude[8 - width..].copy_from_slice(buf);
+        let magnitude = u64::from_be_bytes(magnitude);
 
-        let final_value = if negative > 0 {
-            -(enum_value as i64)
+        let checked_value = if negative > 0 {
+            0_i64.checked_sub_unsigned(magnitude)
         } else {
-            enum_value as i64
+            i64::try_from(magnitude).ok()
+        };
+
+        let Some(final_value) = checked_value else {
+            return Some(ParseNumData {
+                value: TTLVValue::String(String::from_utf8_lossy(&buf).to_string()),
+                mantissa: 0,
+                scale: 0,
+                offset,
+            });
         };
 
         if amp > 0 {
             // Double value
-            let calc1 = final_value as f64;
-            let calc2 = 10.0_f64.powi(amp as i32);
-            let result = calc1 / calc2;
-
             Some(ParseNumData {
-                value: TTLVValue::Float(result),
+                value: TTLVValue::Float(shift_decimal(final_value, amp)),
+                mantissa: final_value,
+                scale: amp,
                 offset,
             })
         } else {
             // Long value
             Some(ParseNumData {
                 value: TTLVValue::Integer(final_value),
+                mantissa: final_value,
+                scale: 0,
                 offset,
             })
         }
     }
 
-    /// Remove 0x55 after 0xAA from received data, then split packets
+    /// Undo the encoder's byte stuffing: the first `0x55` after each `0xAA` is stripped.
+    /// The encoder stuffs data `0xAA 0x55` as `0xAA 0x55 0x55`, so the second one is kept
     pub fn splice_buffer(&self, bytes: &[u8]) -> Vec<u8> {
         const B_55: u8 = 0x55;
         const B_AA: u8 = 0xAA;
 
         let mut arr = bytes.to_vec();
+        if arr.len() < 2 {
+            return arr;
+        }
+
         let mut i = 0;
 
         while i < arr.len() - 1 {
@@ -419,27 +763,86 @@ impl DecodeTools {
             let next = arr[i + 1];
 
             if current == B_AA && next == B_55 {
-                println!("remove 55");
+                log::trace!("remove 55");
                 arr.remove(i + 1);
-            } else {
-                i += 1;
             }
+            i += 1;
         }
 
         arr
     }
 
-    /// Calculate checksum
+    /// Wrapping sum of the bytes, the default frame checksum (not an XOR)
     pub fn sum_calculation(&self, data: &[u8]) -> u8 {
-        let mut xor = 0u8;
+        let mut sum = 0u8;
         for (i, &byte) in data.iter().enumerate() {
             if i == 0 {
-                xor = byte;
+                sum = byte;
             } else {
-                xor = xor.wrapping_add(byte);
+                sum = sum.wrapping_add(byte);
             }
         }
-        xor
+        sum
+    }
+
+    /// Whether a de-stuffed frame (`AA AA`, length, checksum, ...) carries the checksum
+    /// `EncodeTools::compute_checksum` gives for it. Only for the default additive checksum;
+    /// frames shorter than `MIN_FRAME_LEN` never verify
+    ///
+    /// ```
+    /// use unquec_model::ttlv::decode::DecodeTools;
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    ///
+    /// // Captured frames from the samples in `example_decode_usage`
+    /// let frames: [&[u8]; 3] = [
+    ///     &[0xaa, 0xaa, 0x00, 0x07, 0xb5, 0x00, 0x00, 0x70, 0x14, 0x00, 0x31],
+    ///     &[0xaa, 0xaa, 0x00, 0x05, 0x6c, 0x03, 0xe8, 0x70, 0x11],
+    ///     &[0xaa, 0xaa, 0x00, 0x09, 0x7d, 0x03, 0xe8, 0x70, 0x17, 0x00, 0x0a, 0x00, 0x01],
+    /// ];
+    /// for frame in frames {
+    ///     assert!(DecodeTools::verify_checksum(frame));
+    ///     assert_eq!(EncodeTools::compute_checksum(frame), frame[4]);
+    ///
+    ///     let mut corrupted = frame.to_vec();
+    ///     *corrupted.last_mut().unwrap() ^= 0x01;
+    ///     assert!(!DecodeTools::verify_checksum(&corrupted));
+    /// }
+    ///
+    /// assert!(!DecodeTools::verify_checksum(&[0xaa, 0xaa, 0x00, 0x00, 0x00]));
+    /// ```
+    pub fn verify_checksum(frame: &[u8]) -> bool {
+        frame.len() >= MIN_FRAME_LEN && EncodeTools::compute_checksum(frame) == frame[4]
+    }
+
+    /// Cheap gate for captured traffic: whether `frame` is exactly one de-stuffed frame with
+    /// the `AA AA` header, a length field matching its size, and a valid checksum (see
+    /// `verify_checksum`). Does not allocate or parse the payload
+    ///
+    /// ```
+    /// use unquec_model::ttlv::decode::DecodeTools;
+    ///
+    /// let frame = [0xaa, 0xaa, 0x00, 0x09, 0x7d, 0x03, 0xe8, 0x70, 0x17, 0x00, 0x0a, 0x00, 0x01];
+    /// assert!(DecodeTools::is_valid_frame(&frame));
+    ///
+    /// let mut corrupted = frame;
+    /// corrupted[12] = 0x02;
+    /// assert!(!DecodeTools::is_valid_frame(&corrupted));
+    ///
+    /// // Checksum still matches, but the length field claims one byte more than is there
+    /// let mut inconsistent = frame;
+    /// inconsistent[3] = 0x0a;
+    /// assert!(DecodeTools::verify_checksum(&inconsistent));
+    /// assert!(!DecodeTools::is_valid_frame(&inconsistent));
+    ///
+    /// assert!(!DecodeTools::is_valid_frame(&frame[2..]));
+    /// ```
+    pub fn is_valid_frame(frame: &[u8]) -> bool {
+        if frame.len() < MIN_FRAME_LEN || frame[..2] != [0xaa, 0xaa] {
+            return false;
+        }
+
+        let declared = u16::from_be_bytes([frame[2], frame[3]]) as usize;
+        declared + 4 == frame.len() && Self::verify_checksum(frame)
     }
 
     /// Find subsequence in data
@@ -466,8 +869,21 @@ impl DecodeTools {
         result
     }
 
-    /// Read long from byte array
-    fn read_byte_array_long(&self, data: &[u8]) -> i64 {
+    /// Read a big-endian i64 from up to 8 bytes. Longer input keeps only its last
+    /// (least significant) 8 bytes instead of panicking
+    ///
+    /// ```
+    /// use unquec_model::ttlv::decode::DecodeTools;
+    ///
+    /// let tools = DecodeTools::new();
+    /// assert_eq!(tools.read_byte_array_long(&[0x01, 0x02]), 0x0102);
+    /// assert_eq!(
+    ///     tools.read_byte_array_long(&[0xff, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]),
+    ///     0x0102_0304_0506_0708
+    /// );
+    /// ```
+    pub fn read_byte_array_long(&self, data: &[u8]) -> i64 {
+        let data = &data[data.len().saturating_sub(8)..];
         let mut padded_data = vec![0u8; 8];
         let start = 8 - data.len();
         for (i, &byte) in data.iter().enumerate() {
@@ -504,16 +920,90 @@ pub struct ParseBinaryData {
 /// Parse numeric data result
 pub struct ParseNumData {
     pub value: TTLVValue,
+    /// Signed raw value before the decimal shift
+    pub mantissa: i64,
+    /// Decimal shift (`amp` nibble)
+    pub scale: u8,
     pub offset: usize,
 }
 
+impl ParseNumData {
+    /// `(mantissa, scale)` to keep on the field, `None` for the text fallback
+    pub fn decimal(&self) -> Option<(i64, u8)> {
+        match self.value {
+            TTLVValue::String(_) => None,
+            _ => Some((self.mantissa, self.scale)),
+        }
+    }
+}
+
 /// Parse struct data result
 pub struct ParseStructData {
     pub data: Vec<TTLVData>,
     pub offset: usize,
 }
 
+/// Field kept verbatim because its type is unknown or its body could not be parsed
+fn raw_field(ttlv_id: i32, ttlv_type: i32, bytes: &[u8]) -> TTLVData {
+    let mut data = TTLVData::new(ttlv_id, ttlv_type, true);
+    data.value = TTLVValue::Raw {
+        type_id: ttlv_type,
+        bytes: bytes.to_vec(),
+    };
+    data
+}
+
+/// Parse `0xNN, 0xNN, ...` formatted frames out of a text block, one frame per
+/// blank-line separated group. `#` lines are skipped and a leading `//` is ignored,
+/// so the commented samples in this file can be pasted as-is
+pub fn parse_hex_frames(text: &str) -> Vec<Vec<u8>> {
+    let mut frames = Vec::new();
+    let mut current = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim().trim_start_matches("//").trim();
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        if line.is_empty() {
+            if !current.is_empty() {
+                frames.push(core::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        for token in line.split(|c: char| c == ',' || c.is_whitespace()) {
+            let hex = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"));
+
+            if let Some(Ok(byte)) = hex.map(|h| u8::from_str_radix(h, 16)) {
+                current.push(byte);
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        frames.push(current);
+    }
+
+    frames
+}
+
+/// Parse a `\xaa\xaa\x00...` escaped frame, as printed by the encode example.
+/// Anything between the escapes (quotes, whitespace, line breaks) is ignored
+pub fn parse_escaped_hex(text: &str) -> Vec<u8> {
+    text.split("\\x")
+        .skip(1)
+        .filter_map(|chunk| chunk.get(..2))
+        .filter_map(|hex| u8::from_str_radix(hex, 16).ok())
+        .collect()
+}
+
 /// Example usage of DecodeTools with new functional approach
+#[cfg(feature = "std")]
 pub fn example_decode_usage(example_data: Vec<u8>) {
     // Create a decode tools instance
     let mut decode_tools = DecodeTools::new();
@@ -547,29 +1037,29 @@ pub fn example_decode_usage(example_data: Vec<u8>) {
     // ];
 
     // let example_data = vec![
-    //     0xaa, 0xaa, 0x00, 0xca, 0x71, 0x03, 0xe8, 0x70, 0x13, 0x01, 0x9c, 0x00, 0x10, 0x00, 0x03, 0x00, 0x09, 0x58, 0x61, 
-    //     0x74, 0x61, 0x32, 0x39, 0x30, 0x2e, 0x32, 0x00, 0x03, 0x00, 0x07, 0x63, 0x61, 0x6c, 0x79, 0x6e, 0x6b, 0x61, 0x00, 
-    //     0x03, 0x00, 0x0c, 0x54, 0x50, 0x2d, 0x4c, 0x69, 0x6e, 0x6b, 0x5f, 0x41, 0x34, 0x33, 0x34, 0x00, 0x03, 0x00, 0x0c, 
-    //     0x54, 0x50, 0x2d, 0x4c, 0x69, 0x6e, 0x6b, 0x5f, 0x32, 0x37, 0x34, 0x36, 0x00, 0x03, 0x00, 0x0d, 0x4d, 0x65, 0x67, 
-    //     0x61, 0x57, 0x49, 0x46, 0x49, 0x5f, 0x32, 0x47, 0x48, 0x7a, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x08, 0x4e, 
-    //     0x45, 0x57, 0x5f, 0x57, 0x49, 0x46, 0x49, 0x00, 0x03, 0x00, 0x0b, 0x58, 0x69, 0x61, 0x6f, 0x6d, 0x69, 0x5f, 0x39, 
-    //     0x41, 0x34, 0x31, 0x00, 0x03, 0x00, 0x0e, 0x58, 0x69, 0x61, 0x6f, 0x6d, 0x69, 0x20, 0x31, 0x31, 0x20, 0x4c, 0x69, 
-    //     0x74, 0x65, 0x00, 0x03, 0x00, 0x03, 0x4b, 0x4e, 0x53, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x0c, 0x54, 0x50, 
-    //     0x2d, 0x4c, 0x49, 0x4e, 0x4b, 0x5f, 0x41, 0x38, 0x32, 0x38, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x08, 0x54, 
-    //     0x65, 0x6e, 0x64, 0x61, 0x5f, 0x35, 0x37, 0x00, 0x03, 0x00, 0x08, 0x56, 0x65, 0x72, 0x4e, 0x65, 0x74, 0x5f, 0x32, 
+    //     0xaa, 0xaa, 0x00, 0xca, 0x71, 0x03, 0xe8, 0x70, 0x13, 0x01, 0x9c, 0x00, 0x10, 0x00, 0x03, 0x00, 0x09, 0x58, 0x61,
+    //     0x74, 0x61, 0x32, 0x39, 0x30, 0x2e, 0x32, 0x00, 0x03, 0x00, 0x07, 0x63, 0x61, 0x6c, 0x79, 0x6e, 0x6b, 0x61, 0x00,
+    //     0x03, 0x00, 0x0c, 0x54, 0x50, 0x2d, 0x4c, 0x69, 0x6e, 0x6b, 0x5f, 0x41, 0x34, 0x33, 0x34, 0x00, 0x03, 0x00, 0x0c,
+    //     0x54, 0x50, 0x2d, 0x4c, 0x69, 0x6e, 0x6b, 0x5f, 0x32, 0x37, 0x34, 0x36, 0x00, 0x03, 0x00, 0x0d, 0x4d, 0x65, 0x67,
+    //     0x61, 0x57, 0x49, 0x46, 0x49, 0x5f, 0x32, 0x47, 0x48, 0x7a, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x08, 0x4e,
+    //     0x45, 0x57, 0x5f, 0x57, 0x49, 0x46, 0x49, 0x00, 0x03, 0x00, 0x0b, 0x58, 0x69, 0x61, 0x6f, 0x6d, 0x69, 0x5f, 0x39,
+    //     0x41, 0x34, 0x31, 0x00, 0x03, 0x00, 0x0e, 0x58, 0x69, 0x61, 0x6f, 0x6d, 0x69, 0x20, 0x31, 0x31, 0x20, 0x4c, 0x69,
+    //     0x74, 0x65, 0x00, 0x03, 0x00, 0x03, 0x4b, 0x4e, 0x53, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x0c, 0x54, 0x50,
+    //     0x2d, 0x4c, 0x49, 0x4e, 0x4b, 0x5f, 0x41, 0x38, 0x32, 0x38, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x08, 0x54,
+    //     0x65, 0x6e, 0x64, 0x61, 0x5f, 0x35, 0x37, 0x00, 0x03, 0x00, 0x08, 0x56, 0x65, 0x72, 0x4e, 0x65, 0x74, 0x5f, 0x32,
     //     0x00, 0x03, 0x00, 0x0c, 0x54, 0x50, 0x2d, 0x4c, 0x49, 0x4e, 0x4b, 0x5f, 0x43, 0x41, 0x34, 0x38
     // ];
 
     // let example_data = vec![
     //     0xaa, 0xaa, 0x00, 0x09, 0x7d, 0x03, 0xe8, 0x70, 0x17, 0x00, 0x0a, 0x00, 0x01
     // ];
-    
+
     // let example_data = vec![
-    //     0xaa, 0xaa, 0x00, 0x49, 0x0f, 0x00, 0x01, 0x00, 0xb4, 0x00, 0x63, 0x00, 0x40, 
-    //     0x54, 0x65, 0x61, 0x33, 0x63, 0x71, 0x2b, 0x53, 0x2b, 0x71, 0x48, 0x41, 0x53, 
-    //     0x63, 0x45, 0x4a, 0x69, 0x49, 0x5a, 0x36, 0x66, 0x74, 0x39, 0x72, 0x32, 0x55, 
-    //     0x65, 0x47, 0x64, 0x37, 0x34, 0x57, 0x79, 0x2f, 0x4b, 0x65, 0x58, 0x32, 0x4c, 
-    //     0x6b, 0x66, 0x67, 0x33, 0x2f, 0x46, 0x51, 0x50, 0x75, 0x72, 0x53, 0x63, 0x42, 
+    //     0xaa, 0xaa, 0x00, 0x49, 0x0f, 0x00, 0x01, 0x00, 0xb4, 0x00, 0x63, 0x00, 0x40,
+    //     0x54, 0x65, 0x61, 0x33, 0x63, 0x71, 0x2b, 0x53, 0x2b, 0x71, 0x48, 0x41, 0x53,
+    //     0x63, 0x45, 0x4a, 0x69, 0x49, 0x5a, 0x36, 0x66, 0x74, 0x39, 0x72, 0x32, 0x55,
+    //     0x65, 0x47, 0x64, 0x37, 0x34, 0x57, 0x79, 0x2f, 0x4b, 0x65, 0x58, 0x32, 0x4c,
+    //     0x6b, 0x66, 0x67, 0x33, 0x2f, 0x46, 0x51, 0x50, 0x75, 0x72, 0x53, 0x63, 0x42,
     //     0x54, 0x75, 0x4f, 0x53, 0x46, 0x4a, 0x4d, 0x4a, 0x62, 0x59, 0x43, 0x70,
     // ];
 
@@ -580,10 +1070,7 @@ pub fn example_decode_usage(example_data: Vec<u8>) {
         let indent_str = "  ".repeat(indent);
         println!(
             "{}Payload: ID=0x{:04X}, Type={}, TTLV={}",
-            indent_str,
-            payload.id,
-            payload.type_id,
-            payload.ttlv,
+            indent_str, payload.id, payload.type_id, payload.ttlv,
         );
 
         // Pattern match on the value
@@ -592,14 +1079,25 @@ pub fn example_decode_usage(example_data: Vec<u8>) {
             TTLVValue::String(s) => println!("{}  String: {}", indent_str, s),
             TTLVValue::Integer(i) => println!("{}  Integer: {}", indent_str, i),
             TTLVValue::Float(f) => println!("{}  Float: {}", indent_str, f),
-            TTLVValue::Binary(b) => println!("{}  Binary: {:?}, as string: {}", indent_str, b, String::from_utf8_lossy(b)),
+            TTLVValue::Binary(b) => println!(
+                "{}  Binary: {:?}, as string: {}",
+                indent_str,
+                b,
+                String::from_utf8_lossy(b)
+            ),
             TTLVValue::Struct(s) => {
                 println!("{}  Struct with {} items", indent_str, s.len());
                 for (j, item) in s.iter().enumerate() {
-                    println!("{}  Item {}: ID=0x{:04X}, Type={}, TTLV={}", indent_str, j, item.id, item.type_id, item.ttlv);
+                    println!(
+                        "{}  Item {}: ID=0x{:04X}, Type={}, TTLV={}",
+                        indent_str, j, item.id, item.type_id, item.ttlv
+                    );
                     traverse_payload(item, indent + 1);
                 }
             }
+            TTLVValue::Raw { type_id, bytes } => {
+                println!("{}  Raw type {}: {:?}", indent_str, type_id, bytes)
+            }
             TTLVValue::None => println!("    None"),
         }
     }
@@ -623,6 +1121,18 @@ pub fn example_decode_usage(example_data: Vec<u8>) {
             DecodeResult::Incomplete => {
                 println!("Need more data to complete packet");
             }
+            DecodeResult::ChecksumMismatch {
+                expected,
+                actual,
+                frame,
+            } => {
+                eprintln!(
+                    "Checksum mismatch: expected 0x{:02X}, got 0x{:02X} in {}",
+                    expected,
+                    actual,
+                    decode_tools.format_debug_hex(&frame)
+                );
+            }
             DecodeResult::Error(e) => {
                 eprintln!("Decode error: {}", e);
             }
@@ -631,6 +1141,7 @@ pub fn example_decode_usage(example_data: Vec<u8>) {
 }
 
 /// Example of processing multiple packets
+#[cfg(feature = "std")]
 pub fn example_multiple_packets() {
     let mut decoder = DecodeTools::new();
 
@@ -660,3 +1171,544 @@ pub fn example_multiple_packets() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Cmd;
+    use crate::ttlv::encode::EncodeTools;
+    use crate::ttlv::model::EncodeError;
+
+    fn received(model: &TtlvCommandModel) -> TtlvCommandModel {
+        let frame = EncodeTools::new().start_encode(model).unwrap();
+        match DecodeTools::new().packet_slice(frame.get_cmd_data()).pop() {
+            Some(DecodeResult::Success(model)) => model,
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn eight_byte_numbers_round_trip() {
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .integer(1, i64::MAX)
+            .integer(2, -i64::MAX)
+            .integer(3, i64::MIN + 1)
+            .build();
+
+        assert_eq!(received(&sent).payloads, sent.payloads);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)] // -3.14 is a reading, not π
+    fn negative_floats_keep_sign_and_scale() {
+        let cases = [
+            (-3.14, (-314, 2)),
+            (-0.001, (-1, 3)),
+            (-123456.789, (-123456789, 3)),
+        ];
+        let mut sent = TtlvCommandModel::builder(Cmd::WifiPair, 0);
+        for (id, (value, _)) in cases.iter().enumerate() {
+            sent = sent.float(id as i32 + 1, *value);
+        }
+
+        let decoded = received(&sent.build());
+
+        for (field, (value, decimal)) in decoded.payloads.iter().zip(cases) {
+            let TTLVValue::Float(got) = field.value else {
+                panic!("not a float: {:?}", field);
+            };
+            assert!((got - value).abs() < 1e-9, "{} != {}", got, value);
+            assert_eq!(field.as_decimal(), Some(decimal));
+        }
+    }
+
+    /// Type-2 field with a hand-built header byte and magnitude
+    fn raw_number(id: i32, header: u8, magnitude: [u8; 8]) -> TTLVData {
+        let mut bytes = vec![header];
+        bytes.extend(magnitude);
+        let mut data = TTLVData::new(id, 2, true);
+        data.value = TTLVValue::Raw { type_id: 2, bytes };
+        data
+    }
+
+    #[test]
+    fn minus_two_to_the_63_decodes_to_i64_min() {
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .payload(raw_number(1, 0x87, (1u64 << 63).to_be_bytes()))
+            .build();
+
+        assert_eq!(
+            received(&sent).payloads[0].value,
+            TTLVValue::Integer(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn out_of_range_numbers_fall_back_to_text_and_later_fields_decode() {
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .payload(raw_number(1, 0x87, ((1u64 << 63) + 1).to_be_bytes()))
+            .payload(raw_number(2, 0x07, u64::MAX.to_be_bytes()))
+            .integer(3, 7)
+            .build();
+
+        let decoded = received(&sent);
+
+        assert!(matches!(decoded.payloads[0].value, TTLVValue::String(_)));
+        assert!(matches!(decoded.payloads[1].value, TTLVValue::String(_)));
+        assert_eq!(decoded.integer_payload(3), Some(7));
+    }
+
+    #[test]
+    fn out_of_range_number_with_decimals_has_no_decimal() {
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .payload(raw_number(1, 0xff, u64::MAX.to_be_bytes()))
+            .build();
+
+        let decoded = received(&sent);
+
+        assert!(matches!(decoded.payloads[0].value, TTLVValue::String(_)));
+        assert_eq!(decoded.payloads[0].as_decimal(), None);
+    }
+
+    #[test]
+    fn text_in_a_type_2_field_decodes_as_a_string() {
+        // 8 bytes with the top bit set, past i64::MAX as a magnitude
+        let text = "€uro!!";
+        let magnitude = *text.as_bytes().first_chunk().unwrap();
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .payload(raw_number(1, 0x07, magnitude))
+            .integer(2, 7)
+            .build();
+
+        let decoded = received(&sent);
+
+        let expected = TTLVData::new(1, 2, true).with_string(text.into());
+        assert_eq!(decoded.payloads[0], expected);
+        assert_eq!(decoded.payloads[0].as_decimal(), None);
+        assert_eq!(decoded.integer_payload(2), Some(7));
+        // Text is not a number, so it cannot be sent back as one
+        assert_eq!(
+            EncodeTools::new().start_encode(&decoded).map(|_| ()),
+            Err(EncodeError::InvalidNumber(text.into()))
+        );
+    }
+
+    #[test]
+    fn unknown_field_type_is_kept_raw_and_forwarded_verbatim() {
+        let mut unknown = TTLVData::new(2, 6, true);
+        unknown.value = TTLVValue::Raw {
+            type_id: 6,
+            bytes: vec![0x01, 0x02, 0x03],
+        };
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .integer(1, 5)
+            .payload(unknown)
+            .build();
+
+        let decoded = received(&sent);
+
+        assert_eq!(decoded.payloads, sent.payloads);
+        let forwarded = EncodeTools::new()
+            .start_encode_with_packet_id(&decoded, true)
+            .unwrap();
+        let original = EncodeTools::new()
+            .start_encode_with_packet_id(&sent, true)
+            .unwrap();
+        assert_eq!(forwarded.pre_stuffed()[9..], original.pre_stuffed()[9..]);
+    }
+
+    #[test]
+    fn frames_left_unread_by_feed_stay_buffered() {
+        let mut encode_tools = EncodeTools::new();
+        let mut data = Vec::new();
+        for cmd in [Cmd::RandomResp, Cmd::LoginResp] {
+            let model = TtlvCommandModel::new(cmd.as_i32(), 0);
+            data.extend(encode_tools.start_encode(&model).unwrap().get_cmd_data());
+        }
+
+        let mut decode_tools = DecodeTools::new();
+        let first = decode_tools.feed(&data).next();
+        assert!(
+            matches!(first, Some(DecodeResult::Success(model)) if model.cmd == Cmd::RandomResp.as_i32())
+        );
+
+        match decode_tools.feed(&[]).collect::<Vec<_>>().as_slice() {
+            [DecodeResult::Success(model)] => assert_eq!(model.cmd, Cmd::LoginResp.as_i32()),
+            other => panic!("unexpected decode: {:?}", other),
+        }
+        assert!(decode_tools.pending().is_empty());
+    }
+
+    #[test]
+    fn stuffed_aa_55_data_survives_destuffing() {
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .binary(1, vec![0xaa, 0x55, 0x55, 0xaa, 0xaa, 0x55])
+            .build();
+        let frame = EncodeTools::new().start_encode(&sent).unwrap();
+        let data = frame.get_cmd_data();
+
+        assert_eq!(received(&sent).payloads, sent.payloads);
+        assert_eq!(DecodeTools::new().splice_buffer(data), *frame.pre_stuffed());
+
+        // Split between an 0xAA and its 0x55s, the stuffing state carries over
+        let mut decode_tools = DecodeTools::new();
+        let mut results = Vec::new();
+        for byte in data {
+            results.extend(decode_tools.packet_slice(core::slice::from_ref(byte)));
+        }
+        match results.last() {
+            Some(DecodeResult::Success(model)) => assert_eq!(model.payloads, sent.payloads),
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn frame_without_room_for_packet_id_and_cmd_is_rejected() {
+        // Declared length 1: checksum only, the zero padding belongs to no frame
+        let short = [0xaa, 0xaa, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+        match DecodeTools::new().packet_slice(&short).first() {
+            Some(DecodeResult::Error(err)) => {
+                assert!(err.contains("Frame too short: 5 bytes"), "{}", err)
+            }
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn minimal_ack_frame_has_no_payloads() {
+        let frame = [0xaa, 0xaa, 0x00, 0x05, 0x6c, 0x03, 0xe8, 0x70, 0x11];
+
+        match DecodeTools::new().packet_slice(&frame).as_slice() {
+            [DecodeResult::Success(model)] => {
+                assert_eq!(model.packet_id, 1000);
+                assert_eq!(model.cmd, Cmd::WifiPairResp.as_i32());
+                assert!(model.payloads.is_empty());
+            }
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn frame_one_byte_short_of_a_cmd_is_rejected() {
+        // Declared length 4 leaves room for the packet id but only half the cmd
+        let short = [0xaa, 0xaa, 0x00, 0x04, 0x5b, 0x03, 0xe8, 0x70];
+        let ack = [0xaa, 0xaa, 0x00, 0x05, 0x6c, 0x03, 0xe8, 0x70, 0x11];
+
+        let results = DecodeTools::new().packet_slice(&[&short[..], &ack].concat());
+
+        match results.as_slice() {
+            [DecodeResult::Error(_), DecodeResult::Success(model)] => {
+                assert_eq!(model.cmd, Cmd::WifiPairResp.as_i32());
+            }
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn length_over_the_limit_is_an_error_and_resyncs() {
+        let oversized = [0xaa, 0xaa, 0x01, 0x00, 0x00, 0x03, 0xe8, 0x70, 0x11];
+        let ack = [0xaa, 0xaa, 0x00, 0x05, 0x6c, 0x03, 0xe8, 0x70, 0x11];
+        let mut decode_tools = DecodeTools::with_limits(0xff);
+
+        let results = decode_tools.packet_slice(&[&oversized[..], &ack].concat());
+
+        match results.as_slice() {
+            [DecodeResult::Error(err), DecodeResult::Success(model)] => {
+                assert!(err.contains("256 exceeds limit 255"), "{}", err);
+                assert_eq!(model.cmd, Cmd::WifiPairResp.as_i32());
+            }
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn length_within_the_limit_waits_for_the_rest() {
+        let header = [0xaa, 0xaa, 0x00, 0xff, 0x00, 0x03, 0xe8, 0x70, 0x11];
+
+        let results = DecodeTools::with_limits(0xff).packet_slice(&header);
+
+        assert!(matches!(results.as_slice(), [DecodeResult::Incomplete]));
+        assert_eq!(DecodeTools::new().config().max_packet_len, 4096);
+    }
+
+    #[test]
+    fn aa_55_length_field_is_rejected_without_buffering() {
+        let bogus = [0xaa, 0xaa, 0xaa, 0x55, 0x01, 0x02, 0x03, 0x04, 0x05];
+        let ack = [0xaa, 0xaa, 0x00, 0x05, 0x6c, 0x03, 0xe8, 0x70, 0x11];
+
+        // Destuffed, the length reads 0xaa01, well over the limit
+        let results = DecodeTools::new().packet_slice(&[&bogus[..], &ack].concat());
+
+        match results.as_slice() {
+            [DecodeResult::Error(err), DecodeResult::Success(model)] => {
+                assert!(err.contains("43521 exceeds limit"), "{}", err);
+                assert_eq!(model.cmd, Cmd::WifiPairResp.as_i32());
+            }
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn debug_hex_is_cut_at_the_limit() {
+        let data = vec![0xab; 64 * 1024];
+
+        let hex = DecodeTools::new().format_debug_hex(&data);
+
+        assert!(hex.starts_with("0xab, 0xab, "));
+        assert!(hex.ends_with(", 0xab, ... (65472 more)"));
+        assert_eq!(hex.matches("0xab").count(), 64);
+    }
+
+    #[test]
+    fn debug_hex_limit_is_configurable() {
+        let mut decode_tools = DecodeTools::new();
+        decode_tools.set_debug_hex_limit(2);
+        assert_eq!(
+            decode_tools.format_debug_hex(&[1, 2, 3]),
+            "0x01, 0x02, ... (1 more)"
+        );
+        assert_eq!(decode_tools.format_debug_hex(&[1, 2]), "0x01, 0x02");
+
+        decode_tools.set_debug_hex_limit(0);
+        assert_eq!(decode_tools.format_debug_hex(&[1, 2]), "... (2 more)");
+    }
+
+    #[test]
+    fn decodes_the_escaped_output_of_the_encode_example() {
+        // Same model and formatting as examples/encode.rs
+        let mut something = TtlvCommandModel::new(0x00B4, 1);
+        something.add_payload(TTLVData::new(0x000C, 3, true).with_binary(b"YWFh".repeat(16)));
+        let frame = EncodeTools::new().start_encode(&something).unwrap();
+        let printed: String = frame
+            .get_cmd_data()
+            .iter()
+            .map(|b| format!("\\x{:02x}", b))
+            .collect();
+
+        match DecodeTools::new().decode_escaped(&printed).as_slice() {
+            [DecodeResult::Success(model)] => {
+                assert_eq!(model.cmd, 0x00B4);
+                assert_eq!(model.payloads, something.payloads);
+            }
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn escaped_hex_skips_quotes_and_line_breaks() {
+        let pasted = "b'\\xaa\\xAA\\x00\\x05'\n    '\\x6c\\x03\\xe8\\x70\\x11'";
+
+        assert_eq!(
+            parse_escaped_hex(pasted),
+            [0xaa, 0xaa, 0x00, 0x05, 0x6c, 0x03, 0xe8, 0x70, 0x11]
+        );
+        assert!(parse_escaped_hex("\\xzz\\x1").is_empty());
+    }
+
+    #[test]
+    fn splice_buffer_handles_empty_and_single_byte_input() {
+        let decode_tools = DecodeTools::new();
+
+        assert!(decode_tools.splice_buffer(&[]).is_empty());
+        assert_eq!(decode_tools.splice_buffer(&[0xaa]), [0xaa]);
+        assert_eq!(decode_tools.splice_buffer(&[0x55]), [0x55]);
+    }
+
+    #[test]
+    fn splice_buffer_strips_the_55_after_each_aa() {
+        let stuffed = [0xaa, 0x55, 0x01, 0x55, 0xaa, 0x55, 0xaa, 0x55, 0xaa];
+
+        assert_eq!(
+            DecodeTools::new().splice_buffer(&stuffed),
+            [0xaa, 0x01, 0x55, 0xaa, 0xaa, 0xaa]
+        );
+    }
+
+    #[test]
+    fn parse_hex_frames_reads_commented_blocks() {
+        let text = "# two frames\n\
+                    // 0xaa, 0xAA,\n\
+                    // 0x00 0X05\n\
+                    \n\
+                    0x01, junk, 0xzz, 0x2\n";
+
+        assert_eq!(
+            parse_hex_frames(text),
+            [vec![0xaa, 0xaa, 0x00, 0x05], vec![0x01, 0x02]]
+        );
+    }
+
+    #[test]
+    fn every_sample_frame_decodes_without_panicking() {
+        let samples = parse_hex_frames(include_str!("../../examples/samples.txt"));
+        assert!(samples.len() >= 8);
+
+        for frame in &samples {
+            let results = DecodeTools::new().packet_slice(frame);
+            assert!(!results.is_empty());
+            // Byte by byte exercises every partial-frame path
+            let mut decode_tools = DecodeTools::new();
+            for byte in frame {
+                decode_tools.packet_slice(core::slice::from_ref(byte));
+            }
+        }
+
+        let stream = samples.concat();
+        DecodeTools::new().packet_slice(&stream);
+    }
+
+    /// The receive loop before the `consumed` offset: the whole buffer is re-spliced on
+    /// every call and frames are drained off its front one by one
+    struct CopyingDecoder {
+        tools: DecodeTools,
+        receive_data: Vec<u8>,
+    }
+
+    impl CopyingDecoder {
+        fn packet_slice(&mut self, data: &[u8]) -> Vec<DecodeResult> {
+            self.receive_data.extend_from_slice(data);
+            self.receive_data = self.tools.splice_buffer(&self.receive_data);
+
+            let mut results = Vec::new();
+            while let Some(result) = self.next_result() {
+                let waiting = matches!(result, DecodeResult::Incomplete);
+                results.push(result);
+                if waiting {
+                    break;
+                }
+            }
+            results
+        }
+
+        fn next_result(&mut self) -> Option<DecodeResult> {
+            if self.receive_data.is_empty() {
+                return None;
+            }
+
+            let tools = &self.tools;
+            let Some(start_index) = tools.find_subsequence(&self.receive_data, &tools.stbuf) else {
+                if self.receive_data.last() == Some(&0xaa) {
+                    self.receive_data = vec![0xaa];
+                    return Some(DecodeResult::Incomplete);
+                }
+                self.receive_data.clear();
+                return Some(DecodeResult::Error(
+                    "Invalid data - no packet header found".to_string(),
+                ));
+            };
+            self.receive_data.drain(..start_index);
+
+            if self.receive_data.len() < MIN_FRAME_LEN {
+                return Some(DecodeResult::Incomplete);
+            }
+
+            let length = [self.receive_data[2], self.receive_data[3]];
+            let payload_len = self.tools.read_byte_array_short(&length) as usize;
+            if payload_len > self.tools.config.max_packet_len {
+                self.receive_data.drain(..2);
+                return Some(DecodeResult::Error(format!(
+                    "Declared payload length {} exceeds limit {}",
+                    payload_len, self.tools.config.max_packet_len
+                )));
+            }
+
+            if self.receive_data.len() < payload_len + 4 {
+                return Some(DecodeResult::Incomplete);
+            }
+
+            let frame: Vec<u8> = self.receive_data.drain(..payload_len + 4).collect();
+            Some(match self.tools.crc_security(&frame) {
+                Ok(result) => result,
+                Err(e) => DecodeResult::Error(e),
+            })
+        }
+    }
+
+    #[test]
+    fn offset_buffer_matches_the_copying_decoder() {
+        // xorshift, fixed seed so a failure reproduces
+        let mut state = 0x2545_f491_u32;
+        let mut next = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as usize % bound
+        };
+
+        let mut encode_tools = EncodeTools::new();
+        let mut compared = 0;
+        for _ in 0..200 {
+            // Frames whose stuffing splits anywhere, corrupted frames and junk. Re-splicing
+            // the whole buffer is only idempotent while no de-stuffed `0xAA 0x55` is
+            // retained, so neither carries data `0xAA 0x55`
+            let mut stream = Vec::new();
+            for _ in 0..next(6) {
+                let model = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+                    .binary(1, (0..next(5)).map(|_| [0xaa, 0x01][next(2)]).collect())
+                    .integer(2, next(0x10000) as i64)
+                    .build();
+                let encoded = encode_tools.start_encode(&model).unwrap();
+                let mut frame = encoded.get_cmd_data().to_vec();
+                if next(4) == 0 {
+                    let at = 4 + next(frame.len() - 4);
+                    frame[at] ^= 0x0f;
+                }
+                stream.extend(frame);
+                stream.extend((0..next(3)).map(|_| [0x00, 0x13, 0xaa][next(3)]));
+            }
+            let destuffed = DecodeTools::new().splice_buffer(&stream);
+            if destuffed.windows(2).any(|pair| pair == [0xaa, 0x55]) {
+                continue;
+            }
+            compared += 1;
+
+            let mut decode_tools = DecodeTools::new();
+            let mut copying = CopyingDecoder {
+                tools: DecodeTools::new(),
+                receive_data: Vec::new(),
+            };
+            let mut rest = stream.as_slice();
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at(1 + next(rest.len().min(24)));
+                rest = tail;
+
+                assert_eq!(
+                    format!("{:?}", decode_tools.packet_slice(chunk)),
+                    format!("{:?}", copying.packet_slice(chunk)),
+                    "{:02x?}",
+                    stream
+                );
+                assert_eq!(decode_tools.pending(), copying.receive_data.as_slice());
+            }
+        }
+        assert!(compared > 150, "{}", compared);
+    }
+
+    #[test]
+    fn every_width_reads_exactly_its_bytes() {
+        let tools = DecodeTools::new();
+
+        for width in 1..=8usize {
+            let mut payload = vec![(width - 1) as u8];
+            payload.extend(vec![0x11; width]);
+            payload.push(0xee); // next field, must not be read
+
+            let parsed = tools.parse_enum_value(&payload, 0).unwrap();
+            let expected = u64::from_be_bytes([0x11; 8]) >> (8 * (8 - width));
+            assert_eq!(parsed.value, TTLVValue::Integer(expected as i64));
+            assert_eq!(parsed.offset, 1 + width);
+        }
+    }
+
+    #[test]
+    fn truncated_magnitude_is_rejected() {
+        let tools = DecodeTools::new();
+
+        // Eight bytes announced, three present
+        let short = [0x07, 0x01, 0x02, 0x03];
+        assert!(tools.parse_enum_value(&short, 0).is_none());
+        assert!(tools.parse_enum_value(&[0x00], 0).is_none());
+        assert!(tools.parse_enum_value(&[], 0).is_none());
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::ttlv::decode::DecodeResult;
+
+/// Suppresses frames the device retransmitted because our ack was late.
+/// A result with the same `(cmd, packet_id)` as one forwarded within `window` is dropped;
+/// incomplete, checksum-failed, error and transparent results without a packet id always pass
+///
+/// ```
+/// use std::time::{Duration, Instant};
+/// use unquec_model::commands::TtlvCommandModel;
+/// use unquec_model::ttlv::decode::DecodeResult;
+/// use unquec_model::ttlv::dedup::DedupFilter;
+///
+/// let frame = || DecodeResult::Success(TtlvCommandModel::new(0x7065, 42));
+/// let start = Instant::now();
+///
+/// let mut dedup = DedupFilter::new(Duration::from_millis(500));
+/// assert!(dedup.accept_at(&frame(), start));
+/// assert!(!dedup.accept_at(&frame(), start + Duration::from_millis(200)));
+///
+/// let mut dedup = DedupFilter::new(Duration::from_millis(500));
+/// assert!(dedup.accept_at(&frame(), start));
+/// assert!(dedup.accept_at(&frame(), start + Duration::from_secs(1)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DedupFilter {
+    window: Duration,
+    seen: VecDeque<(i32, i32, Instant)>,
+}
+
+impl DedupFilter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: VecDeque::new(),
+        }
+    }
+
+    /// Keep the results of one `packet_slice` call that are not repeats
+    pub fn filter(&mut self, results: Vec<DecodeResult>) -> Vec<DecodeResult> {
+        self.filter_at(results, Instant::now())
+    }
+
+    pub fn filter_at(&mut self, results: Vec<DecodeResult>, now: Instant) -> Vec<DecodeResult> {
+        results
+            .into_iter()
+            .filter(|result| self.accept_at(result, now))
+            .collect()
+    }
+
+    /// Whether `result` should be forwarded, remembering it if so
+    pub fn accept(&mut self, result: &DecodeResult) -> bool {
+        self.accept_at(result, Instant::now())
+    }
+
+    /// Whether `result` should be forwarded at `now`, remembering it if so
+    pub fn accept_at(&mut self, result: &DecodeResult, now: Instant) -> bool {
+        let key = match result {
+            DecodeResult::Success(model) => (model.cmd, model.packet_id),
+            DecodeResult::Transparent(model) => match model.packet_id {
+                Some(packet_id) => (model.cmd as i32, packet_id),
+                None => return true,
+            },
+            DecodeResult::Incomplete
+            | DecodeResult::ChecksumMismatch { .. }
+            | DecodeResult::Error(_) => return true,
+        };
+
+        while let Some(&(_, _, seen_at)) = self.seen.front() {
+            if now.saturating_duration_since(seen_at) <= self.window {
+                break;
+            }
+            self.seen.pop_front();
+        }
+
+        if self
+            .seen
+            .iter()
+            .any(|&(cmd, packet_id, _)| (cmd, packet_id) == key)
+        {
+            return false;
+        }
+
+        self.seen.push_back((key.0, key.1, now));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::TtlvCommandModel;
+
+    const WINDOW: Duration = Duration::from_millis(500);
+
+    fn frame(cmd: i32, packet_id: i32) -> DecodeResult {
+        DecodeResult::Success(TtlvCommandModel::new(cmd, packet_id))
+    }
+
+    #[test]
+    fn repeat_at_exactly_the_window_is_dropped() {
+        let start = Instant::now();
+        let mut dedup = DedupFilter::new(WINDOW);
+
+        assert!(dedup.accept_at(&frame(0x7065, 42), start));
+        assert!(!dedup.accept_at(&frame(0x7065, 42), start + WINDOW));
+    }
+
+    #[test]
+    fn repeat_just_past_the_window_passes() {
+        let start = Instant::now();
+        let mut dedup = DedupFilter::new(WINDOW);
+        let later = start + WINDOW + Duration::from_nanos(1);
+
+        assert!(dedup.accept_at(&frame(0x7065, 42), start));
+        assert!(dedup.accept_at(&frame(0x7065, 42), later));
+    }
+
+    #[test]
+    fn dropped_repeats_do_not_extend_the_window() {
+        let start = Instant::now();
+        let mut dedup = DedupFilter::new(WINDOW);
+
+        assert!(dedup.accept_at(&frame(0x7065, 42), start));
+        assert!(!dedup.accept_at(&frame(0x7065, 42), start + WINDOW / 2));
+        assert!(dedup.accept_at(&frame(0x7065, 42), start + WINDOW * 2));
+    }
+
+    #[test]
+    fn only_cmd_and_packet_id_together_are_a_repeat() {
+        let now = Instant::now();
+        let mut dedup = DedupFilter::new(WINDOW);
+
+        assert!(dedup.accept_at(&frame(0x7065, 42), now));
+        assert!(dedup.accept_at(&frame(0x7065, 43), now));
+        assert!(dedup.accept_at(&frame(0x7066, 42), now));
+    }
+
+    #[test]
+    fn errors_always_pass() {
+        let now = Instant::now();
+        let mut dedup = DedupFilter::new(WINDOW);
+        let results = vec![
+            DecodeResult::Error("bad".into()),
+            DecodeResult::Error("bad".into()),
+            DecodeResult::Incomplete,
+            frame(0x7065, 42),
+            frame(0x7065, 42),
+        ];
+
+        let forwarded = dedup.filter_at(results, now);
+
+        assert_eq!(forwarded.len(), 4);
+        assert!(matches!(forwarded[3], DecodeResult::Success(_)));
+    }
+}
@@ -1,91 +1,250 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
 use crate::commands::TtlvCommandModel;
+use crate::ttlv::cipher::PayloadCipher;
+use crate::ttlv::config::{ChecksumKind, CodecConfig};
 use crate::ttlv::model::{
-    DoubleNeedValue, EncodeResult, TTLVData, TTLVValue, TtlvTransparentModel,
+    DoubleNeedValue, EncodeError, EncodeResult, TTLVData, TTLVValue, TtlvTransparentModel,
 };
 
 /// TTLV encoding utility class
 pub struct EncodeTools {
     packet_id: u16,
+    cipher: Option<Box<dyn PayloadCipher>>,
+    config: CodecConfig,
 }
 
 impl EncodeTools {
     pub fn new() -> Self {
-        Self { packet_id: 0 }
+        Self::with_config(CodecConfig::default())
+    }
+
+    /// Frame with the checksum and stuffing from `config`, pass the same config to the
+    /// `DecodeTools` on the other end
+    pub fn with_config(config: CodecConfig) -> Self {
+        Self {
+            packet_id: 0,
+            cipher: None,
+            config,
+        }
+    }
+
+    /// Start the packet id sequence at a seed-derived point within `[1000, 0xffff)`,
+    /// so encoders in different processes (seeded e.g. with a PID) are unlikely to collide
+    pub fn seeded(seed: u64) -> Self {
+        let span = (0xffff - 1000) as u64;
+        // `packet_id` holds the last issued id, the first `get_serial_num` adds one
+        Self {
+            packet_id: 999 + (seed % span) as u16,
+            ..Self::new()
+        }
+    }
+
+    /// Encrypt payloads with `cipher` from now on, `None` sends them in the clear
+    pub fn set_cipher(&mut self, cipher: Option<Box<dyn PayloadCipher>>) {
+        self.cipher = cipher;
     }
 
     pub fn get_packet_id(&self) -> u16 {
         self.packet_id
     }
 
+    pub fn config(&self) -> &CodecConfig {
+        &self.config
+    }
+
     /// Start encoding TTLV command model
-    pub fn start_encode(&mut self, model: &TtlvCommandModel) -> EncodeResult {
+    pub fn start_encode(&mut self, model: &TtlvCommandModel) -> Result<EncodeResult, EncodeError> {
         self.start_encode_with_packet_id(model, false)
     }
 
     /// Encode to TTLV format
     /// @param model QuecTtlvCommandModel
     /// @param is_use_packet_id Whether to use QuecTtlvCommandModel's PacketId
-    /// @return EncodeResult
+    /// @return EncodeResult, or the first field that cannot be framed
     pub fn start_encode_with_packet_id(
         &mut self,
         model: &TtlvCommandModel,
         is_use_packet_id: bool,
-    ) -> EncodeResult {
-        let payloads = &model.payloads;
+    ) -> Result<EncodeResult, EncodeError> {
         let cmd = model.cmd as u16;
-        let mut result = EncodeResult::new();
-        let payload_raw;
-
-        if cmd == 0x0011 {
-            payload_raw = self.encode_read_payload_to_buffer(payloads);
-        } else {
-            payload_raw = self.encode_payload_to_buffer(payloads);
-        }
+        let payload = self.encrypt_payload(self.encode_model_payload(model)?);
 
-        let payload = payload_raw; // No encryption in Rust version
-        let length = 9 + payload.len();
-        let mut cmd_data = vec![0u8; length];
-        cmd_data[0] = 0xaa;
-        cmd_data[1] = 0xaa;
-        let length2 = 5 + payload.len();
-        // Data field length
-        cmd_data[2] = ((length2 >> 8) & 0xff) as u8;
-        cmd_data[3] = (length2 & 0xff) as u8;
         let packet_id = if is_use_packet_id {
             (model.packet_id & 0xFFFF) as u16
         } else {
             self.get_serial_num()
         };
-        cmd_data[5] = ((packet_id >> 8) & 0xff) as u8;
-        cmd_data[6] = (packet_id & 0xff) as u8;
-        cmd_data[7] = ((cmd >> 8) & 0xff) as u8;
-        cmd_data[8] = (cmd & 0xff) as u8;
 
-        if payload.len() > 0 {
-            for (i, &byte) in payload.iter().enumerate() {
-                cmd_data[9 + i] = byte;
-            }
-        }
+        let cmd_data = self.build_frame(cmd, packet_id, &payload);
+        Ok(self.finish_frame(cmd, packet_id, cmd_data))
+    }
 
-        let valid_array = &cmd_data[5..];
-        cmd_data[4] = self.sum_calculation(valid_array);
-        let data = self.garble_buffer(&cmd_data);
-        let ckey = (cmd as u32) << 16 | packet_id as u32;
+    /// Encode `model` as `start_encode` does, but put `checksum` in place of the computed
+    /// checksum, to produce a frame the decoder must reject. A testing and fuzzing aid:
+    /// `frame_raw` with an override checksum, plus the cipher, serial number and stuffing
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    ///
+    /// let model = TtlvCommandModel::builder(Cmd::Random, 0).build();
+    /// let mut encode_tools = EncodeTools::new();
+    /// let expected = EncodeTools::new().start_encode(&model).unwrap().pre_stuffed()[4];
+    ///
+    /// let bad = encode_tools.frame_with_checksum(&model, expected ^ 0xff).unwrap();
+    /// match DecodeTools::new().packet_slice(bad.get_cmd_data()).as_slice() {
+    ///     [DecodeResult::ChecksumMismatch { expected: computed, actual, .. }] => {
+    ///         assert_eq!(*computed, expected);
+    ///         assert_eq!(*actual, expected ^ 0xff);
+    ///     }
+    ///     other => panic!("unexpected decode: {:?}", other),
+    /// }
+    /// ```
+    pub fn frame_with_checksum(
+        &mut self,
+        model: &TtlvCommandModel,
+        checksum: u8,
+    ) -> Result<EncodeResult, EncodeError> {
+        let cmd = model.cmd as u16;
+        let payload = self.encrypt_payload(self.encode_model_payload(model)?);
+        let packet_id = self.get_serial_num();
+
+        let cmd_data = self.frame_raw(packet_id, cmd, &payload, Some(checksum));
+        Ok(self.finish_frame(cmd, packet_id, cmd_data))
+    }
+
+    pub fn start_encode_transparent(
+        &mut self,
+        model: &TtlvTransparentModel,
+    ) -> Result<EncodeResult, EncodeError> {
+        let payload = self.encrypt_payload(model.payloads.clone());
 
-        result.set_cmd_key(ckey);
+        let package_id = model.packet_id;
+        let cmd = model.cmd;
+
+        let packet_id = if let Some(id) = package_id {
+            (id & 0xFFFF) as u16
+        } else {
+            self.get_serial_num()
+        };
+
+        let cmd_data = self.build_frame(cmd, packet_id, &payload);
+        Ok(self.finish_frame(cmd, packet_id, cmd_data))
+    }
+
+    /// Stuff a built frame and wrap it with its command key
+    fn finish_frame(&self, cmd: u16, packet_id: u16, cmd_data: Vec<u8>) -> EncodeResult {
+        let data = self.stuff_frame(&cmd_data);
+        let c_key = (cmd as u32) << 16 | packet_id as u32;
+
+        let mut result = EncodeResult::new();
+        result.set_cmd_key(c_key);
         result.set_cmd_data(data);
+        result.set_pre_stuffed(cmd_data);
         result.set_cmd(cmd);
         result.set_packet_id(packet_id);
         result
     }
 
-    pub fn start_encode_transparent(&mut self, model: &TtlvTransparentModel) -> EncodeResult {
-        let payload_raw = &model.payloads;
-        let payload = payload_raw.clone(); // No encryption in Rust version
+    /// Frame `model` with an explicit packet id, ready to write to the characteristic,
+    /// enciphered with this encoder's cipher. Unlike `start_encode` it has no serial number
+    /// side effects, which suits one-off commands and fixed test vectors
+    pub fn encode_command(
+        &self,
+        model: &TtlvCommandModel,
+        packet_id: u16,
+    ) -> Result<Vec<u8>, EncodeError> {
+        let payload = self.encrypt_payload(self.encode_model_payload(model)?);
+        let cmd_data = self.build_frame(model.cmd as u16, packet_id, &payload);
+        Ok(self.stuff_frame(&cmd_data))
+    }
 
-        let package_id = model.packet_id;
-        let cmd = model.cmd;
+    /// Frame raw bytes as transparent command `cmd`, shorthand for
+    /// `start_encode_transparent(&TtlvTransparentModel::from_bytes(..))`
+    ///
+    /// ```
+    /// use unquec_model::commands::IotCmd;
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    ///
+    /// let payload = vec![0x01, 0xaa, 0xaa, 0x02, 0xff, 0x00];
+    /// let result = EncodeTools::new()
+    ///     .encode_transparent(IotCmd::ReceiveDeviceTransparent as u16, Some(7), payload.clone())
+    ///     .unwrap();
+    ///
+    /// match DecodeTools::new().packet_slice(result.get_cmd_data()).as_slice() {
+    ///     [DecodeResult::Transparent(model)] => {
+    ///         assert_eq!(model.cmd, 0x0024);
+    ///         assert_eq!(model.packet_id, Some(7));
+    ///         assert_eq!(model.payloads, payload);
+    ///     }
+    ///     other => panic!("unexpected decode: {:?}", other),
+    /// }
+    /// ```
+    pub fn encode_transparent(
+        &mut self,
+        cmd: u16,
+        packet_id: Option<i32>,
+        payload: Vec<u8>,
+    ) -> Result<EncodeResult, EncodeError> {
+        self.start_encode_transparent(&TtlvTransparentModel::from_bytes(cmd, packet_id, payload))
+    }
+
+    fn encrypt_payload(&self, payload: Vec<u8>) -> Vec<u8> {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(&payload),
+            None => payload,
+        }
+    }
+
+    /// Unstuffed frame around an already encoded `payload`, for building test vectors.
+    /// `override_checksum` replaces the computed checksum byte, e.g. to exercise the
+    /// decoder's checksum error path. No cipher or stuffing is applied, so payloads
+    /// containing `AA AA` or `AA 55` will not decode as-is; `frame_with_checksum` does the
+    /// same for a whole model, ready to send
+    ///
+    /// ```
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    ///
+    /// let encode_tools = EncodeTools::new();
+    /// let payload = [0x00, 0x0a, 0x00, 0x01];
+    ///
+    /// let good = encode_tools.frame_raw(1000, 0x7017, &payload, None);
+    /// assert_eq!(good, [0xaa, 0xaa, 0x00, 0x09, 0x7d, 0x03, 0xe8, 0x70, 0x17, 0x00, 0x0a, 0x00, 0x01]);
+    /// assert!(matches!(
+    ///     DecodeTools::new().packet_slice(&good).as_slice(),
+    ///     [DecodeResult::Success(model)] if model.packet_id == 1000
+    /// ));
+    ///
+    /// let bad = encode_tools.frame_raw(1000, 0x7017, &payload, Some(0x00));
+    /// assert!(matches!(
+    ///     DecodeTools::new().packet_slice(&bad).as_slice(),
+    ///     [DecodeResult::ChecksumMismatch { expected: 0x7d, actual: 0x00, .. }]
+    /// ));
+    /// ```
+    pub fn frame_raw(
+        &self,
+        packet_id: u16,
+        cmd: u16,
+        payload: &[u8],
+        override_checksum: Option<u8>,
+    ) -> Vec<u8> {
+        let mut frame = self.build_frame(cmd, packet_id, payload);
+        if let Some(checksum) = override_checksum {
+            frame[4] = checksum;
+        }
+        frame
+    }
 
+    /// Lay out header, length, checksum, packet id and cmd around `payload`, before stuffing
+    fn build_frame(&self, cmd: u16, packet_id: u16, payload: &[u8]) -> Vec<u8> {
         let length = 9 + payload.len();
         let mut cmd_data = vec![0u8; length];
         cmd_data[0] = 0xaa;
@@ -94,31 +253,26 @@ impl EncodeTools {
         // Data field length
         cmd_data[2] = ((length2 >> 8) & 0xff) as u8;
         cmd_data[3] = (length2 & 0xff) as u8;
-        let packet_id = if let Some(id) = package_id {
-            (id & 0xFFFF) as u16
-        } else {
-            self.get_serial_num()
-        };
         cmd_data[5] = ((packet_id >> 8) & 0xff) as u8;
         cmd_data[6] = (packet_id & 0xff) as u8;
         cmd_data[7] = ((cmd >> 8) & 0xff) as u8;
         cmd_data[8] = (cmd & 0xff) as u8;
 
         if payload.len() > 0 {
-            cmd_data[9..9 + payload.len()].copy_from_slice(&payload);
+            cmd_data[9..9 + payload.len()].copy_from_slice(payload);
         }
 
         let valid_array = &cmd_data[5..];
-        cmd_data[4] = self.sum_calculation(valid_array);
-        let data = self.garble_buffer(&cmd_data);
-        let c_key = (cmd as u32) << 16 | packet_id as u32;
+        cmd_data[4] = self.config.checksum.compute(valid_array);
+        cmd_data
+    }
 
-        let mut result = EncodeResult::new();
-        result.set_cmd_key(c_key);
-        result.set_cmd_data(data);
-        result.set_cmd(cmd);
-        result.set_packet_id(packet_id);
-        result
+    fn stuff_frame(&self, frame: &[u8]) -> Vec<u8> {
+        if self.config.stuffing {
+            self.garble_buffer(frame)
+        } else {
+            frame.to_vec()
+        }
     }
 
     /// Prevent conflicts with packet header, this method checks the encapsulated instruction
@@ -135,7 +289,7 @@ impl EncodeTools {
             let next = arr[count + 1];
 
             if (current == B_AA && next == B_55) || (current == B_AA && next == B_AA) {
-                println!("add 55");
+                log::trace!("add 55");
                 arr.insert(count + 1, B_55);
                 count += 1;
             }
@@ -144,16 +298,23 @@ impl EncodeTools {
         arr
     }
 
+    /// Wrapping sum of the bytes, the default frame checksum (not an XOR)
     pub fn sum_calculation(&self, data: &[u8]) -> u8 {
-        let mut xor = 0u8;
+        let mut sum = 0u8;
         for (i, &byte) in data.iter().enumerate() {
             if i == 0 {
-                xor = byte;
+                sum = byte;
             } else {
-                xor = xor.wrapping_add(byte);
+                sum = sum.wrapping_add(byte);
             }
         }
-        xor
+        sum
+    }
+
+    /// Checksum byte for a whole de-stuffed frame: the wrapping sum of bytes `[5..]`
+    /// (packet id, cmd and payload), as `ChecksumKind::Sum` computes it
+    pub fn compute_checksum(frame: &[u8]) -> u8 {
+        ChecksumKind::Sum.compute(frame.get(5..).unwrap_or(&[]))
     }
 
     pub fn get_serial_num(&mut self) -> u16 {
@@ -164,6 +325,15 @@ impl EncodeTools {
         self.packet_id
     }
 
+    /// Encode the payload section of a command model, picking the read layout for 0x0011
+    pub fn encode_model_payload(&self, model: &TtlvCommandModel) -> Result<Vec<u8>, EncodeError> {
+        if model.cmd as u16 == 0x0011 {
+            Ok(self.encode_read_payload_to_buffer(&model.payloads))
+        } else {
+            self.encode_payload_to_buffer(&model.payloads)
+        }
+    }
+
     pub fn encode_read_payload_to_buffer(&self, payloads: &[TTLVData]) -> Vec<u8> {
         let mut buf = Vec::new();
         for obj in payloads {
@@ -173,7 +343,7 @@ impl EncodeTools {
         buf
     }
 
-    pub fn encode_payload_to_buffer(&self, payloads: &[TTLVData]) -> Vec<u8> {
+    pub fn encode_payload_to_buffer(&self, payloads: &[TTLVData]) -> Result<Vec<u8>, EncodeError> {
         let mut buf = Vec::new();
         for obj in payloads {
             if !obj.ttlv {
@@ -190,7 +360,7 @@ impl EncodeTools {
                 match &obj.value {
                     TTLVValue::Binary(_) | TTLVValue::None => {
                         // Binary data or None - encode as binary
-                        let bytes = self.encode_binary(obj);
+                        let bytes = self.encode_binary(obj)?;
                         buf.extend_from_slice(&bytes);
                     }
                     TTLVValue::Boolean(_) => {
@@ -198,32 +368,32 @@ impl EncodeTools {
                     }
                     TTLVValue::String(_) | TTLVValue::Integer(_) | TTLVValue::Float(_) => {
                         // Enum values
-                        let bytes = self.encode_enum_value(obj);
+                        let bytes = self.encode_enum_value(obj)?;
                         buf.extend_from_slice(&bytes);
                     }
                     TTLVValue::Struct(_) => {
                         // Struct
-                        buf.extend(self.encode_struct_payload(obj));
+                        buf.extend(self.encode_struct_payload(obj)?);
+                    }
+                    TTLVValue::Raw { bytes, .. } => {
+                        // Undecoded field, forwarded as received
+                        buf.extend_from_slice(bytes);
                     }
                 }
             }
         }
-        buf
+        Ok(buf)
     }
 
-    fn encode_struct_payload(&self, obj: &TTLVData) -> Vec<u8> {
+    /// Element count and elements of a struct field, the caller has already written the
+    /// field header
+    fn encode_struct_payload(&self, obj: &TTLVData) -> Result<Vec<u8>, EncodeError> {
         let mut buf = Vec::new();
         let payloads = match &obj.value {
             TTLVValue::Struct(list) => list,
             _ => &Vec::new(),
         };
 
-        let ttlv_id = obj.id as u16;
-        let ttlv_type = obj.type_id as u16;
-        let ttlv_header = ((ttlv_id << 3) & 0xFFF8) + (ttlv_type & 0x07);
-        let header_buf_sec = self.get_byte_by_short(ttlv_header as i32);
-        buf.extend_from_slice(&header_buf_sec);
-
         // Add struct protocol Length element count 2B
         let byte_by_short = self.get_byte_by_short(payloads.len() as i32);
         buf.extend_from_slice(&byte_by_short);
@@ -242,7 +412,7 @@ impl EncodeTools {
                 match &obj_sec.value {
                     TTLVValue::Binary(_) => {
                         // Binary
-                        let bytes = self.encode_binary(obj_sec);
+                        let bytes = self.encode_binary(obj_sec)?;
                         buf.extend_from_slice(&bytes);
                     }
                     TTLVValue::Boolean(_) => {
@@ -250,11 +420,14 @@ impl EncodeTools {
                     }
                     TTLVValue::String(_) | TTLVValue::Integer(_) | TTLVValue::Float(_) => {
                         // Encapsulated enum
-                        let bytes = self.encode_enum_value(obj_sec);
+                        let bytes = self.encode_enum_value(obj_sec)?;
                         buf.extend_from_slice(&bytes);
                     }
                     TTLVValue::Struct(_) => {
-                        buf.extend(self.encode_struct_payload(obj_sec));
+                        buf.extend(self.encode_struct_payload(obj_sec)?);
+                    }
+                    TTLVValue::Raw { bytes, .. } => {
+                        buf.extend_from_slice(bytes);
                     }
                     TTLVValue::None => {
                         // Skip None values
@@ -262,7 +435,7 @@ impl EncodeTools {
                 }
             }
         }
-        buf
+        Ok(buf)
     }
 
     fn get_byte_by_short(&self, value: i32) -> Vec<u8> {
@@ -281,23 +454,32 @@ impl EncodeTools {
         result
     }
 
-    fn encode_binary(&self, obj: &TTLVData) -> Vec<u8> {
+    fn encode_binary(&self, obj: &TTLVData) -> Result<Vec<u8>, EncodeError> {
         match &obj.value {
             TTLVValue::Binary(bytes) => {
+                // Length prefix is 2 bytes, a longer field would be silently truncated
+                if bytes.len() > 0xFFFF {
+                    return Err(EncodeError::BinaryFieldTooLarge {
+                        id: obj.id,
+                        len: bytes.len(),
+                    });
+                }
+
                 let byte_by_short = self.get_byte_by_short(bytes.len() as i32);
                 let mut result = Vec::new();
                 result.extend_from_slice(&byte_by_short);
                 result.extend_from_slice(bytes);
-                result
+                Ok(result)
             }
             _ => {
                 // For None or other types, return empty
-                Vec::new()
+                Ok(Vec::new())
             }
         }
     }
 
-    fn encode_enum_value(&self, obj: &TTLVData) -> Vec<u8> {
+    /// Type-2 value: strings must hold a number, anything else is `InvalidNumber`
+    fn encode_enum_value(&self, obj: &TTLVData) -> Result<Vec<u8>, EncodeError> {
         match &obj.value {
             TTLVValue::String(s) => {
                 if s.contains('.') {
@@ -308,17 +490,23 @@ impl EncodeTools {
             }
             TTLVValue::Integer(i) => self.get_long_result(&i.to_string()),
             TTLVValue::Float(f) => self.get_double_result(&f.to_string()),
-            _ => Vec::new(),
+            _ => Ok(Vec::new()),
         }
     }
 
-    fn get_long_result(&self, data: &str) -> Vec<u8> {
-        let value = data.parse::<i64>().unwrap();
+    fn get_long_result(&self, data: &str) -> Result<Vec<u8>, EncodeError> {
+        let value = data
+            .parse::<i64>()
+            .map_err(|_| EncodeError::InvalidNumber(data.to_string()))?;
         let mut one_byte = vec![0u8; 1];
 
         let (sign, abs_value) = if value < 0 {
             one_byte[0] = 0x01 << 7;
-            (true, -value)
+            // i64::MIN has no positive counterpart
+            let abs_value = value
+                .checked_neg()
+                .ok_or_else(|| EncodeError::NumberOutOfRange(data.to_string()))?;
+            (true, abs_value)
         } else {
             one_byte[0] = 0;
             (false, value)
@@ -334,11 +522,15 @@ impl EncodeTools {
         let mut result = Vec::new();
         result.extend_from_slice(&one_byte);
         result.extend_from_slice(&parm_buf);
-        result
+        Ok(result)
     }
 
-    fn get_double_result(&self, data: &str) -> Vec<u8> {
-        let value = data.parse::<f64>().unwrap();
+    fn get_double_result(&self, data: &str) -> Result<Vec<u8>, EncodeError> {
+        let value = data
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+            .ok_or_else(|| EncodeError::InvalidNumber(data.to_string()))?;
         let mut one_byte = vec![0u8; 1];
 
         let (sign, abs_value) = if value < 0.0 {
@@ -349,7 +541,9 @@ impl EncodeTools {
             (false, value)
         };
 
-        let double_need_value = self.extract_double(abs_value);
+        let double_need_value = self
+            .extract_double(abs_value)
+            .ok_or_else(|| EncodeError::NumberOutOfRange(data.to_string()))?;
         let mut parm_buf = self.long_to_byte_array_big_endian(double_need_value.value);
         let count = double_need_value.count;
         one_byte[0] |= (count << 3) as u8;
@@ -358,7 +552,7 @@ impl EncodeTools {
         let mut result = Vec::new();
         result.extend_from_slice(&one_byte);
         result.extend_from_slice(&parm_buf);
-        result
+        Ok(result)
     }
 
     fn u64_buffer(&self, value: i64) -> Vec<u8> {
@@ -394,35 +588,35 @@ impl EncodeTools {
         valid_byte_list
     }
 
-    fn extract_double(&self, value: f64) -> DoubleNeedValue {
+    /// Split a non-negative finite value into mantissa and decimal count,
+    /// `None` if the mantissa does not fit an i64
+    fn extract_double(&self, value: f64) -> Option<DoubleNeedValue> {
         let mut result = DoubleNeedValue::new();
 
-        let str = format!("{:.15}", value);
+        // Shortest representation, a fixed 15 decimals would drag binary rounding noise
+        // into the mantissa (123456.789 -> 123456.789000000004307) and overflow it
+        let mut str = value.to_string();
+        if str.split('.').nth(1).map_or(0, |fraction| fraction.len()) > 15 {
+            // The decimal count has 4 bits on the wire
+            str = format!("{:.15}", value);
+        }
         let str = str.replace(",", ".");
         let parts: Vec<&str> = str.split('.').collect();
 
-        if parts.len() == 1 {
-            let last = parts[0];
-            let need_value = last.parse::<i64>().unwrap();
-            result.set_value(need_value);
-            result.set_count(0);
-        } else {
-            let value2 = parts[1];
-            let num_value = value2.parse::<i64>().unwrap();
-            if num_value <= 0 {
-                panic!("PARAMS_ERROR");
-            }
-            let t = value2.trim_end_matches('0');
-            let last = format!("{}{}", parts[0], t);
-            let need_value = last.parse::<i64>().unwrap();
-            result.set_value(need_value);
-            result.set_count(t.len());
-        }
-        result
+        // Without a fractional part the value is sent as a plain integer
+        let t = parts
+            .get(1)
+            .map_or("", |value2| value2.trim_end_matches('0'));
+        let last = format!("{}{}", parts[0], t);
+        let need_value = last.parse::<i64>().ok()?;
+        result.set_value(need_value);
+        result.set_count(t.len());
+        Some(result)
     }
 }
 
 /// Example usage of TTLV encoding utility
+#[cfg(feature = "std")]
 pub fn example_usage() {
     // Create a command model (equivalent to Java: QuecTtlvCommandModel commandModel = new QuecTtlvCommandModel();)
     let mut command_model = TtlvCommandModel::new(0x7032, 0);
@@ -472,7 +666,13 @@ pub fn example_usage() {
 
     // Create encoder and encode the command
     let mut encoder = EncodeTools::new();
-    let result = encoder.start_encode(&command_model);
+    let result = match encoder.start_encode(&command_model) {
+        Ok(result) => result,
+        Err(err) => {
+            println!("Encode failed: {:?}", err);
+            return;
+        }
+    };
 
     // Print the results
     println!("Command Key: 0x{:08X}", result.get_cmd_key());
@@ -482,6 +682,7 @@ pub fn example_usage() {
 }
 
 /// Example of transparent model usage
+#[cfg(feature = "std")]
 pub fn example_transparent_usage() {
     // Create a transparent model
     let mut transparent_model = TtlvTransparentModel::new(0x8001);
@@ -489,7 +690,13 @@ pub fn example_transparent_usage() {
 
     // Create encoder and encode the transparent model
     let mut encoder = EncodeTools::new();
-    let result = encoder.start_encode_transparent(&transparent_model);
+    let result = match encoder.start_encode_transparent(&transparent_model) {
+        Ok(result) => result,
+        Err(err) => {
+            println!("Transparent encode failed: {:?}", err);
+            return;
+        }
+    };
 
     // Print the results
     println!("Transparent Command Key: 0x{:08X}", result.get_cmd_key());
@@ -499,6 +706,7 @@ pub fn example_transparent_usage() {
 }
 
 /// Example of read command (cmd = 0x0011)
+#[cfg(feature = "std")]
 pub fn example_read_command() {
     // Create a read command model
     let mut read_model = TtlvCommandModel::new(0x0011, 0);
@@ -513,7 +721,13 @@ pub fn example_read_command() {
 
     // Create encoder and encode the read command
     let mut encoder = EncodeTools::new();
-    let result = encoder.start_encode(&read_model);
+    let result = match encoder.start_encode(&read_model) {
+        Ok(result) => result,
+        Err(err) => {
+            println!("Read encode failed: {:?}", err);
+            return;
+        }
+    };
 
     // Print the results
     println!("Read Command Key: 0x{:08X}", result.get_cmd_key());
@@ -521,3 +735,218 @@ pub fn example_read_command() {
     println!("Read Packet ID: {}", result.get_packet_id());
     println!("Read Encoded Data: {:?}", result.get_cmd_data());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Cmd;
+    use crate::ttlv::config::ChecksumKind;
+    use crate::ttlv::decode::{DecodeResult, DecodeTools};
+
+    /// Flips every payload bit, enough to tell an enciphered payload from a clear one
+    struct Invert;
+
+    impl PayloadCipher for Invert {
+        fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+            data.iter().map(|byte| !byte).collect()
+        }
+
+        fn decrypt(&self, data: &[u8]) -> Option<Vec<u8>> {
+            Some(self.encrypt(data))
+        }
+    }
+
+    #[test]
+    fn pre_stuffed_is_the_checksummed_frame_before_stuffing() {
+        let mut model = TtlvCommandModel::new(Cmd::WifiPair.as_i32(), 1234);
+        model.add_payload(TTLVData::new(1, 3, true).with_binary(vec![0xaa, 0xaa, 0x01]));
+
+        let mut encode_tools = EncodeTools::new();
+        let frame = encode_tools
+            .start_encode_with_packet_id(&model, true)
+            .unwrap();
+        let pre_stuffed = frame.pre_stuffed();
+        let stuffed = encode_tools.garble_buffer(pre_stuffed);
+
+        assert_eq!(&stuffed, frame.get_cmd_data());
+        assert_eq!(pre_stuffed[4], EncodeTools::compute_checksum(pre_stuffed));
+        // The AA AA in the payload gains a 55 on the wire
+        assert_eq!(frame.get_cmd_data().len(), pre_stuffed.len() + 1);
+    }
+
+    fn xor_unstuffed() -> CodecConfig {
+        CodecConfig::builder()
+            .checksum(ChecksumKind::Xor)
+            .stuffing(false)
+            .build()
+    }
+
+    fn wifi_pair(packet_id: i32) -> TtlvCommandModel {
+        TtlvCommandModel::builder(Cmd::WifiPair, packet_id)
+            .string(1, "home")
+            .binary(2, vec![0xaa, 0xaa, 0x01])
+            .integer(11, 30)
+            .build()
+    }
+
+    #[test]
+    fn encode_command_matches_start_encode_with_the_same_packet_id() {
+        let frame = EncodeTools::new()
+            .encode_command(&wifi_pair(0), 1234)
+            .unwrap();
+
+        let expected = EncodeTools::new()
+            .start_encode_with_packet_id(&wifi_pair(1234), true)
+            .unwrap();
+        assert_eq!(&frame, expected.get_cmd_data());
+    }
+
+    #[test]
+    fn encode_command_follows_the_config() {
+        let encode_tools = EncodeTools::with_config(xor_unstuffed());
+
+        let frame = encode_tools.encode_command(&wifi_pair(0), 1234).unwrap();
+
+        let expected = EncodeTools::with_config(xor_unstuffed())
+            .start_encode_with_packet_id(&wifi_pair(1234), true)
+            .unwrap();
+        assert_eq!(&frame, expected.get_cmd_data());
+        assert_eq!(frame[4], ChecksumKind::Xor.compute(&frame[5..]));
+        // Not stuffed, the AA AA in the payload goes out as-is
+        assert_eq!(frame.len(), expected.pre_stuffed().len());
+    }
+
+    #[test]
+    fn encode_command_applies_the_cipher() {
+        let mut encode_tools = EncodeTools::with_config(xor_unstuffed());
+        encode_tools.set_cipher(Some(Box::new(Invert)));
+
+        let frame = encode_tools.encode_command(&wifi_pair(0), 1234).unwrap();
+
+        let clear = EncodeTools::with_config(xor_unstuffed())
+            .encode_command(&wifi_pair(0), 1234)
+            .unwrap();
+        assert_ne!(frame[9..], clear[9..]);
+
+        let mut decode_tools = DecodeTools::with_config(xor_unstuffed());
+        decode_tools.set_cipher(Some(Box::new(Invert)));
+        match decode_tools.packet_slice(&frame).as_slice() {
+            [DecodeResult::Success(decoded)] => {
+                assert_eq!(decoded.packet_id, 1234);
+                assert_eq!(decoded.payloads, wifi_pair(0).payloads);
+            }
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stuffed_xor_frames_round_trip_with_the_cipher() {
+        let config = CodecConfig::builder().checksum(ChecksumKind::Xor).build();
+        let mut encode_tools = EncodeTools::with_config(config.clone());
+        encode_tools.set_cipher(Some(Box::new(Invert)));
+        // Inverted, 0x55 0x55 0xaa comes out as 0xaa 0xaa 0x55 and has to be stuffed
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .binary(1, vec![0x55, 0x55, 0xaa])
+            .build();
+
+        let frame = encode_tools.start_encode(&sent).unwrap();
+        assert!(frame.get_cmd_data().len() > frame.pre_stuffed().len());
+
+        let mut decode_tools = DecodeTools::with_config(config);
+        decode_tools.set_cipher(Some(Box::new(Invert)));
+        match decode_tools.packet_slice(frame.get_cmd_data()).as_slice() {
+            [DecodeResult::Success(decoded)] => assert_eq!(decoded.payloads, sent.payloads),
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encode_command_leaves_the_serial_number_alone() {
+        let encode_tools = EncodeTools::seeded(2000);
+        let before = encode_tools.get_packet_id();
+
+        encode_tools.encode_command(&wifi_pair(0), 7).unwrap();
+
+        assert_eq!(encode_tools.get_packet_id(), before);
+    }
+
+    #[test]
+    fn frame_with_checksum_only_changes_the_checksum() {
+        let mut encode_tools = EncodeTools::with_config(xor_unstuffed());
+        encode_tools.set_cipher(Some(Box::new(Invert)));
+        let mut reference = EncodeTools::with_config(xor_unstuffed());
+        reference.set_cipher(Some(Box::new(Invert)));
+
+        let good = reference.start_encode(&wifi_pair(0)).unwrap();
+        let bad = encode_tools
+            .frame_with_checksum(&wifi_pair(0), good.get_cmd_data()[4] ^ 0xff)
+            .unwrap();
+
+        let (good, bad) = (good.get_cmd_data(), bad.get_cmd_data());
+        assert_eq!(bad[4], good[4] ^ 0xff);
+        assert_eq!(bad[..4], good[..4]);
+        assert_eq!(bad[5..], good[5..]);
+    }
+
+    #[test]
+    fn oversized_binary_field_is_named_in_the_error() {
+        let model = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .binary(1, vec![0; 0xFFFF])
+            .binary(6, vec![0; 0x10000])
+            .build();
+
+        let error = EncodeTools::new().start_encode(&model).unwrap_err();
+
+        assert_eq!(
+            error,
+            EncodeError::BinaryFieldTooLarge {
+                id: 6,
+                len: 0x10000
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_numbers_are_errors_not_panics() {
+        let encode = |value: &str| {
+            let model = TtlvCommandModel::builder(Cmd::TlsWrite, 0)
+                .payload(TTLVData::new(1, 2, true).with_string(value.into()))
+                .build();
+            EncodeTools::new().start_encode(&model).map(|_| ())
+        };
+        let invalid = |value: &str| Err(EncodeError::InvalidNumber(value.into()));
+
+        assert_eq!(encode("not a number"), invalid("not a number"));
+        assert_eq!(encode("N/A"), invalid("N/A"));
+        assert_eq!(encode(""), invalid(""));
+        // Overflows to infinity
+        assert_eq!(encode("1e999"), invalid("1e999"));
+        assert_eq!(encode("1.0e999"), invalid("1.0e999"));
+        assert_eq!(
+            encode("-9223372036854775808"),
+            Err(EncodeError::NumberOutOfRange("-9223372036854775808".into()))
+        );
+    }
+
+    #[test]
+    fn seeds_start_at_different_ids_within_range() {
+        let first_ids: Vec<u16> = [0, 1, 12345, 0xffff - 1001, u64::MAX]
+            .into_iter()
+            .map(|seed| EncodeTools::seeded(seed).get_serial_num())
+            .collect();
+
+        assert_eq!(first_ids[..4], [1000, 1001, 13345, 0xfffe]);
+        for (i, id) in first_ids.iter().enumerate() {
+            assert!((1000..0xffff).contains(id), "{}", id);
+            assert!(!first_ids[..i].contains(id), "{}", id);
+        }
+    }
+
+    #[test]
+    fn seeded_sequence_wraps_back_to_1000() {
+        let mut encode_tools = EncodeTools::seeded(0xffff - 1001);
+
+        assert_eq!(encode_tools.get_serial_num(), 0xfffe);
+        assert_eq!(encode_tools.get_serial_num(), 1000);
+    }
+}
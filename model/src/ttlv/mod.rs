@@ -1,3 +1,7 @@
+pub mod cipher;
+pub mod config;
 pub mod decode;
+#[cfg(feature = "std")]
+pub mod dedup;
 pub mod encode;
 pub mod model;
@@ -1,18 +1,61 @@
-use std::any::Any;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::OnceCell;
+
+#[cfg(feature = "std")]
+use crate::quec_ble_device::QuecBLEDevice;
+use crate::ttlv::decode::DecodeTools;
 
 // Re-export QuecTtlvCommandModel from commands module for backward compatibility
 pub use crate::commands::TtlvCommandModel;
 
 /// Type-safe TTLV value representation
-#[derive(Debug, Clone)]
+///
+/// Type 2 is numeric on the wire: a sign/amplitude/length byte followed by up to 8
+/// big-endian bytes. Decoding yields `Integer` (no amplitude) or `Float`, and `String`
+/// (the magnitude bytes as lossy UTF-8) when the magnitude is not a number that fits an
+/// i64; on encode a `String` must hold a number. See `TTLVData::as_enum_string` for
+/// integers that spell short text
+///
+/// With the `serde` feature the variant is tagged as `{"type": .., "value": ..}` and
+/// binary data is written as a base64 string
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum TTLVValue {
     None,
     Boolean(bool),
     String(String),
     Integer(i64),
     Float(f64),
-    Binary(Vec<u8>),
+    Binary(#[cfg_attr(feature = "serde", serde(with = "base64_bytes"))] Vec<u8>),
     Struct(Vec<TTLVData>),
+    /// Field the decoder could not interpret (unknown type or truncated), holding every
+    /// byte after its header so it can be forwarded unchanged
+    Raw {
+        type_id: i32,
+        #[cfg_attr(feature = "serde", serde(with = "base64_bytes"))]
+        bytes: Vec<u8>,
+    },
+}
+
+#[cfg(feature = "serde")]
+mod base64_bytes {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        STANDARD.decode(text).map_err(serde::de::Error::custom)
+    }
 }
 
 impl TTLVValue {
@@ -61,6 +104,66 @@ impl TTLVValue {
         }
     }
 
+    /// Bytes held by binary and string data, including nested struct members
+    pub fn data_len(&self) -> usize {
+        match self {
+            Self::String(s) => s.len(),
+            Self::Binary(b) => b.len(),
+            Self::Raw { bytes, .. } => bytes.len(),
+            Self::Struct(list) => list.iter().map(|item| item.value.data_len()).sum(),
+            _ => 0,
+        }
+    }
+
+    /// Equality with floats compared within `epsilon`, since the decimal-shift encoding
+    /// does not always give back the exact f64. Other variants compare exactly; struct
+    /// members must match in order by id, type and (approximately) value
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    /// use unquec_model::ttlv::model::{TTLVData, TTLVValue};
+    ///
+    /// let value = TTLVValue::Float(3.14);
+    /// assert!(value.approx_eq(&TTLVValue::Float(3.1400000001), 1e-6));
+    /// assert!(!value.approx_eq(&TTLVValue::Float(3.15), 1e-6));
+    /// assert!(!value.approx_eq(&TTLVValue::Integer(3), 1.0));
+    ///
+    /// // After a round trip through the codec
+    /// let model = TtlvCommandModel::builder(Cmd::TlsWrite, 7)
+    ///     .float(1, -21.7)
+    ///     .build();
+    /// let frame = EncodeTools::new().start_encode_with_packet_id(&model, true).unwrap();
+    /// let Some(DecodeResult::Success(decoded)) =
+    ///     DecodeTools::new().packet_slice(frame.get_cmd_data()).pop()
+    /// else {
+    ///     panic!("frame did not decode");
+    /// };
+    /// let received = &decoded.payload(1).unwrap().value;
+    /// assert!(TTLVValue::Float(-21.7).approx_eq(received, 1e-9));
+    ///
+    /// // Struct members recurse
+    /// let member = |value| vec![TTLVData::new(1, 2, true).with_float(value)];
+    /// let sent = TTLVValue::Struct(member(0.3));
+    /// assert!(sent.approx_eq(&TTLVValue::Struct(member(0.1 + 0.2)), 1e-9));
+    /// assert!(!sent.approx_eq(&TTLVValue::Struct(member(0.4)), 1e-9));
+    /// ```
+    pub fn approx_eq(&self, other: &TTLVValue, epsilon: f64) -> bool {
+        match (self, other) {
+            (Self::Float(a), Self::Float(b)) => (a - b).abs() <= epsilon,
+            (Self::Struct(a), Self::Struct(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b).all(|(a, b)| {
+                        a.id == b.id
+                            && a.type_id == b.type_id
+                            && a.value.approx_eq(&b.value, epsilon)
+                    })
+            }
+            _ => self == other,
+        }
+    }
+
     /// Get the type_id that corresponds to this value
     pub fn type_id(&self) -> i32 {
         match self {
@@ -72,17 +175,33 @@ impl TTLVValue {
             Self::Float(_) => 2,
             Self::Binary(_) => 3,
             Self::Struct(_) => 4,
+            Self::Raw { type_id, .. } => *type_id,
         }
     }
 }
 
 /// Data structures for TTLV encoding
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TTLVData {
     pub id: i32,
     pub type_id: i32,
     pub ttlv: bool,
     pub value: TTLVValue,
+    /// Mantissa and decimal shift of a decoded type-2 field as sent, which the f64 value
+    /// cannot hold exactly past 2^53. Only used while it still describes `value`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) decimal: Option<(i64, u8)>,
+}
+
+/// Fields compare by id, type and value; the decoded `decimal` is a detail of the value
+impl PartialEq for TTLVData {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.type_id == other.type_id
+            && self.ttlv == other.ttlv
+            && self.value == other.value
+    }
 }
 
 impl TTLVData {
@@ -92,6 +211,7 @@ impl TTLVData {
             type_id,
             ttlv,
             value: TTLVValue::None,
+            decimal: None,
         }
     }
 
@@ -120,18 +240,113 @@ impl TTLVData {
         self
     }
 
+    /// Binary field, type 3 unless the field was created with the type 5 alias, which is
+    /// kept so it goes out on the wire as type 5
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    /// use unquec_model::ttlv::model::TTLVData;
+    ///
+    /// let alias = TTLVData::new(2, 5, true).with_binary(vec![0x01, 0xaa, 0x03]);
+    /// assert_eq!(alias.type_id, 5);
+    /// assert_eq!(TTLVData::new(2, 2, true).with_binary(vec![]).type_id, 3);
+    ///
+    /// let model = TtlvCommandModel::builder(Cmd::TlsWrite, 3).payload(alias.clone()).build();
+    /// let frame = EncodeTools::new().start_encode_with_packet_id(&model, true).unwrap();
+    /// // Header (2 << 3) | 5, then the 2-byte length
+    /// assert_eq!(&frame.pre_stuffed()[9..13], &[0x00, 0x15, 0x00, 0x03]);
+    ///
+    /// let Some(DecodeResult::Success(decoded)) =
+    ///     DecodeTools::new().packet_slice(frame.get_cmd_data()).pop()
+    /// else {
+    ///     panic!("frame did not decode");
+    /// };
+    /// assert_eq!(decoded.payloads, [alias]);
+    /// ```
     pub fn with_binary(mut self, value: Vec<u8>) -> Self {
         self.value = TTLVValue::Binary(value);
-        self.type_id = 3;
+        if self.type_id != 5 {
+            self.type_id = 3;
+        }
         self
     }
 
+    /// Binary field holding the UTF-8 bytes of `value`, for SSIDs, passwords and URLs
+    ///
+    /// ```
+    /// use unquec_model::ttlv::model::TTLVData;
+    ///
+    /// let ssid = TTLVData::new(1, 3, true).with_binary_str("home");
+    /// let raw = TTLVData::new(1, 3, true).with_binary_slice(b"home");
+    /// let owned = TTLVData::new(1, 3, true).with_binary(b"home".to_vec());
+    /// assert_eq!(ssid, raw);
+    /// assert_eq!(raw, owned);
+    /// ```
+    pub fn with_binary_str(self, value: &str) -> Self {
+        self.with_binary_slice(value.as_bytes())
+    }
+
+    /// Binary field copied from a borrowed slice
+    pub fn with_binary_slice(self, value: &[u8]) -> Self {
+        self.with_binary(value.to_vec())
+    }
+
     pub fn with_struct(mut self, value: Vec<TTLVData>) -> Self {
         self.value = TTLVValue::Struct(value);
         self.type_id = 4;
         self
     }
 
+    /// Check that `type_id` matches the value variant, recursing into structs.
+    /// A mismatch would be framed with the wrong type nibble and misread by the decoder
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if !self.ttlv {
+            return Ok(());
+        }
+
+        let expected: &[i32] = match &self.value {
+            TTLVValue::None => return Ok(()),
+            TTLVValue::Boolean(true) => &[1],
+            TTLVValue::Boolean(false) => &[0],
+            TTLVValue::String(_) | TTLVValue::Integer(_) | TTLVValue::Float(_) => &[2],
+            TTLVValue::Binary(_) => &[3, 5],
+            TTLVValue::Struct(_) => &[4],
+            TTLVValue::Raw { type_id, .. } => core::slice::from_ref(type_id),
+        };
+
+        if !expected.contains(&self.type_id) {
+            return Err(ValidationError::TypeMismatch {
+                id: self.id,
+                type_id: self.type_id,
+                expected: expected[0],
+            });
+        }
+
+        if let TTLVValue::Struct(children) = &self.value {
+            for child in children {
+                child.validate()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Human-readable name of the value variant, independent of the raw `type_id`
+    pub fn type_name(&self) -> &'static str {
+        match &self.value {
+            TTLVValue::None => "none",
+            TTLVValue::Boolean(_) => "boolean",
+            TTLVValue::String(_) => "string",
+            TTLVValue::Integer(_) => "integer",
+            TTLVValue::Float(_) => "float",
+            TTLVValue::Binary(_) => "binary",
+            TTLVValue::Raw { .. } => "raw",
+            TTLVValue::Struct(_) => "struct",
+        }
+    }
+
     /// Legacy method for backward compatibility
     pub fn get_data(&self) -> Option<&Box<dyn Any + Send + Sync>> {
         None // No longer needed with type-safe enum
@@ -152,6 +367,26 @@ impl TTLVData {
         }
     }
 
+    /// Type-2 field read as text: a `String` value as-is, or the big-endian bytes of a
+    /// non-negative integer when they are all printable ASCII (e.g. `0x4f4e` -> `"ON"`)
+    pub fn as_enum_string(&self) -> Option<String> {
+        match &self.value {
+            TTLVValue::String(s) => Some(s.clone()),
+            TTLVValue::Integer(i) if *i > 0 => {
+                let bytes = i.to_be_bytes();
+                let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+                let text = &bytes[start..];
+
+                if text.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+                    Some(String::from_utf8_lossy(text).to_string())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
     pub fn as_integer(&self) -> Option<i64> {
         match &self.value {
             TTLVValue::Integer(i) => Some(*i),
@@ -166,6 +401,22 @@ impl TTLVData {
         }
     }
 
+    /// `(mantissa, scale)` of a numeric value, where value = mantissa / 10^scale, for
+    /// exact decimal math. A decoded float gives the mantissa and scale it was sent with,
+    /// exact at any magnitude; `None` for a float that did not come from the decoder
+    pub fn as_decimal(&self) -> Option<(i64, u8)> {
+        match (&self.value, self.decimal) {
+            (TTLVValue::Integer(i), _) => Some((*i, 0)),
+            // Kept only while the value has not been replaced since decoding
+            (TTLVValue::Float(f), Some((mantissa, scale)))
+                if shift_decimal(mantissa, scale).to_bits() == f.to_bits() =>
+            {
+                Some((mantissa, scale))
+            }
+            _ => None,
+        }
+    }
+
     pub fn as_binary(&self) -> Option<&Vec<u8>> {
         match &self.value {
             TTLVValue::Binary(b) => Some(b),
@@ -179,6 +430,61 @@ impl TTLVData {
             _ => None,
         }
     }
+
+    /// Device descriptor embedded in a binary field, in the advertisement format
+    /// `QuecBLEDevice::decode_data` parses, e.g. a sub-device reported by a gateway.
+    /// `None` for other types or bytes that do not decode
+    ///
+    /// ```
+    /// use unquec_model::quec_ble_device::QuecBLEDevice;
+    /// use unquec_model::ttlv::model::TTLVData;
+    ///
+    /// let mut descriptor = b"ECig".to_vec();
+    /// descriptor.extend([0x00, 0x02]); // version
+    /// descriptor.push(6);
+    /// descriptor.extend(b"p11xyz"); // product key
+    /// descriptor.push(4);
+    /// descriptor.extend([0x12, 0x34, 0x56, 0x78]); // device key
+    /// descriptor.push(0x03); // status
+    /// descriptor.extend([0x00, 0x2c]); // flags
+    /// let device = QuecBLEDevice::decode_data(&descriptor).unwrap();
+    ///
+    /// let field = TTLVData::new(4, 3, true).with_binary(descriptor);
+    /// assert_eq!(field.as_quec_device(), Some(device));
+    ///
+    /// let junk = TTLVData::new(4, 3, true).with_binary(b"not a device".to_vec());
+    /// assert_eq!(junk.as_quec_device(), None);
+    /// assert_eq!(TTLVData::new(4, 2, true).with_integer(1).as_quec_device(), None);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn as_quec_device(&self) -> Option<QuecBLEDevice> {
+        self.as_binary()
+            .and_then(|data| QuecBLEDevice::decode_data(data).ok())
+    }
+}
+
+/// Reasons a payload is rejected by `validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// Field `id` carries `type_id` but its value requires `expected`
+    TypeMismatch {
+        id: i32,
+        type_id: i32,
+        expected: i32,
+    },
+    /// Field `id` appears more than once at the top level, with `ValidateOptions::unique_ids`
+    DuplicateId(i32),
+}
+
+/// Reasons a model cannot be framed by `EncodeTools`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// Binary field `id` is `len` bytes, more than its 2-byte length prefix can hold
+    BinaryFieldTooLarge { id: i32, len: usize },
+    /// Numeric string that does not parse, or a non-finite float
+    InvalidNumber(String),
+    /// Number whose magnitude or mantissa does not fit an i64
+    NumberOutOfRange(String),
 }
 
 // QuecTtlvCommandModel moved to commands module
@@ -187,7 +493,9 @@ impl TTLVData {
 pub struct TtlvTransparentModel {
     pub cmd: u16,
     pub packet_id: Option<i32>,
+    /// Raw transparent bytes, kept as received
     pub payloads: Vec<u8>,
+    inner: OnceCell<TtlvCommandModel>,
 }
 
 impl TtlvTransparentModel {
@@ -196,14 +504,41 @@ impl TtlvTransparentModel {
             cmd,
             packet_id: None,
             payloads: Vec::new(),
+            inner: OnceCell::new(),
         }
     }
+
+    /// Transparent command carrying `payload` as-is. A `packet_id` of `None` lets the
+    /// encoder assign its next serial number
+    pub fn from_bytes(cmd: u16, packet_id: Option<i32>, payload: Vec<u8>) -> Self {
+        Self {
+            packet_id,
+            payloads: payload,
+            ..Self::new(cmd)
+        }
+    }
+
+    /// TTLV fields carried inside the transparent payload, parsed best-effort on first
+    /// call and cached afterwards. `payloads` stays untouched, so undecodable bytes remain
+    /// available; changing `payloads` after the first call does not refresh the cache.
+    ///
+    /// Parsing uses a default `DecodeTools`, not the frame decoder's `CodecConfig` or
+    /// cipher. Neither applies here: `payloads` was decrypted with the rest of the frame,
+    /// and field parsing does not depend on the checksum, stuffing or length settings
+    pub fn decode_inner(&self) -> &TtlvCommandModel {
+        self.inner.get_or_init(|| {
+            let mut model = TtlvCommandModel::new(self.cmd as i32, self.packet_id.unwrap_or(0));
+            model.payloads = DecodeTools::new().parse_ttlv_fields(&self.payloads);
+            model
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct EncodeResult {
     cmd_key: u32,
     cmd_data: Vec<u8>,
+    pre_stuffed: Vec<u8>,
     cmd: u16,
     packet_id: u16,
 }
@@ -213,11 +548,21 @@ impl EncodeResult {
         Self {
             cmd_key: 0,
             cmd_data: Vec::new(),
+            pre_stuffed: Vec::new(),
             cmd: 0,
             packet_id: 0,
         }
     }
 
+    pub fn set_pre_stuffed(&mut self, pre_stuffed: Vec<u8>) {
+        self.pre_stuffed = pre_stuffed;
+    }
+
+    /// Frame before 0x55 stuffing, i.e. the bytes the checksum at `[4]` was computed over
+    pub fn pre_stuffed(&self) -> &Vec<u8> {
+        &self.pre_stuffed
+    }
+
     pub fn set_cmd_key(&mut self, cmd_key: u32) {
         self.cmd_key = cmd_key;
     }
@@ -249,6 +594,47 @@ impl EncodeResult {
     pub fn get_packet_id(&self) -> u16 {
         self.packet_id
     }
+
+    /// Identifying fields and frame length without the frame bytes, for logs and metrics
+    ///
+    /// ```
+    /// use unquec_model::commands::{Cmd, TtlvCommandModel};
+    /// use unquec_model::ttlv::encode::EncodeTools;
+    ///
+    /// let model = TtlvCommandModel::builder(Cmd::Login, 1001).string(2, "digest").build();
+    /// let frame = EncodeTools::new().start_encode_with_packet_id(&model, true).unwrap();
+    ///
+    /// let summary = frame.summary();
+    /// assert_eq!((summary.cmd, summary.packet_id), (0x7034, 1001));
+    /// assert_eq!(summary.len, frame.get_cmd_data().len());
+    /// assert_eq!(summary.cmd_key, 0x7034_03e9);
+    /// ```
+    pub fn summary(&self) -> EncodeSummary {
+        EncodeSummary {
+            cmd: self.cmd,
+            packet_id: self.packet_id,
+            len: self.cmd_data.len(),
+            cmd_key: self.cmd_key,
+        }
+    }
+}
+
+/// Copyable digest of an `EncodeResult`, see `EncodeResult::summary`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncodeSummary {
+    pub cmd: u16,
+    pub packet_id: u16,
+    /// Length of the frame as sent, stuffing included
+    pub len: usize,
+    /// `cmd << 16 | packet_id`
+    pub cmd_key: u32,
+}
+
+/// `mantissa / 10^scale` as the decoder computes it. `scale` is at most 15, so the power
+/// is exact; `powi` needs std
+pub(crate) fn shift_decimal(mantissa: i64, scale: u8) -> f64 {
+    mantissa as f64 / 10_i64.pow(u32::from(scale)) as f64
 }
 
 #[derive(Clone, Debug)]
@@ -293,3 +679,246 @@ pub mod error_config {
     pub const PARAMS_DATA_ERROR: &str = "PARAMS_DATA_ERROR";
     pub const PARAMS_ERROR: &str = "PARAMS_ERROR";
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Cmd;
+    use crate::ttlv::decode::{DecodeResult, DecodeTools};
+    use crate::ttlv::encode::EncodeTools;
+
+    fn received(model: &TtlvCommandModel) -> TtlvCommandModel {
+        let frame = EncodeTools::new().start_encode(model).unwrap();
+        match DecodeTools::new().packet_slice(frame.get_cmd_data()).pop() {
+            Some(DecodeResult::Success(model)) => model,
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    /// A frame with one type-2 field carrying a hand-built header byte and magnitude, for
+    /// values the encoder cannot produce. `parse_payload` does not check the checksum
+    fn sent_number(header: u8, magnitude: u64) -> TTLVData {
+        let mut frame = vec![0xaa, 0xaa, 0x00, 0x00, 0x00, 0x00, 0x00, 0x70, 0x10];
+        frame.extend([0x00, 0x0a, header]);
+        frame.extend(magnitude.to_be_bytes());
+        DecodeTools::new().parse_payload(&frame).payloads.remove(0)
+    }
+
+    #[test]
+    fn decoded_float_keeps_its_mantissa_and_scale() {
+        let mut sent = TtlvCommandModel::new(Cmd::WifiPair.as_i32(), 0);
+        sent.add_payload(TTLVData::new(1, 2, true).with_float(2.5));
+        sent.add_payload(TTLVData::new(2, 2, true).with_float(-0.05));
+        sent.add_payload(TTLVData::new(3, 2, true).with_integer(-42));
+        let decoded = received(&sent);
+
+        assert_eq!(decoded.payloads[0].as_decimal(), Some((25, 1)));
+        assert_eq!(decoded.payloads[1].as_decimal(), Some((-5, 2)));
+        assert_eq!(decoded.payloads[2].as_decimal(), Some((-42, 0)));
+    }
+
+    #[test]
+    fn decoded_decimal_is_exact_past_f64_precision() {
+        assert_eq!(
+            sent_number(0xff, i64::MAX as u64).as_decimal(),
+            Some((-9223372036854775807, 15))
+        );
+        assert_eq!(
+            sent_number(0x0f, (1 << 53) + 1).as_decimal(),
+            Some(((1 << 53) + 1, 1))
+        );
+    }
+
+    #[test]
+    fn replaced_float_does_not_keep_the_decoded_decimal() {
+        let mut field = sent_number(0x0f, (1 << 53) + 1);
+
+        field.value = TTLVValue::Float(0.25);
+        assert_eq!(field.as_decimal(), None);
+    }
+
+    #[test]
+    fn decimal_follows_the_value() {
+        let mut field = sent_number(0x0f, 25);
+        assert_eq!(field.as_decimal(), Some((25, 1)));
+
+        field.value = TTLVValue::Integer(3);
+        assert_eq!(field.as_decimal(), Some((3, 0)));
+    }
+
+    #[test]
+    fn non_numeric_has_no_decimal() {
+        let field = TTLVData::new(1, 2, true);
+
+        assert_eq!(field.clone().with_boolean(true).as_decimal(), None);
+        assert_eq!(field.clone().with_binary(vec![1]).as_decimal(), None);
+        assert_eq!(field.with_float(2.5).as_decimal(), None);
+    }
+
+    #[test]
+    fn struct_with_the_wrong_type_id_fails_validation() {
+        let mut field = TTLVData::new(7, 4, true).with_struct(vec![]);
+        field.type_id = 2;
+
+        assert_eq!(
+            field.validate(),
+            Err(ValidationError::TypeMismatch {
+                id: 7,
+                type_id: 2,
+                expected: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn boolean_type_id_must_match_its_value() {
+        let mut field = TTLVData::new(1, 0, true).with_boolean(true);
+        assert_eq!(field.validate(), Ok(()));
+
+        field.type_id = 0;
+        assert_eq!(
+            field.validate(),
+            Err(ValidationError::TypeMismatch {
+                id: 1,
+                type_id: 0,
+                expected: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn validation_recurses_into_struct_members() {
+        let mut member = TTLVData::new(2, 2, true).with_integer(5);
+        member.type_id = 3;
+        let mut model = TtlvCommandModel::new(Cmd::TlsWrite.as_i32(), 0);
+        model.add_payload(TTLVData::new(1, 3, true).with_binary(vec![0x01]));
+        model.add_payload(TTLVData::new(9, 4, true).with_struct(vec![member]));
+
+        assert_eq!(
+            model.validate(),
+            Err(ValidationError::TypeMismatch {
+                id: 2,
+                type_id: 3,
+                expected: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn binary_alias_and_non_ttlv_fields_pass_validation() {
+        let alias = TTLVData::new(1, 5, true).with_binary(vec![0x01]);
+        let mut read_id = TTLVData::new(2, 0, false);
+        read_id.value = TTLVValue::Integer(1);
+
+        assert_eq!(alias.validate(), Ok(()));
+        assert_eq!(read_id.validate(), Ok(()));
+    }
+
+    #[test]
+    fn text_sent_in_a_type_2_field_reads_back_as_enum_string() {
+        // A device spelling "ON" in the magnitude bytes
+        let mut sent = TtlvCommandModel::new(Cmd::TlsWrite.as_i32(), 0);
+        sent.add_payload(TTLVData::new(1, 2, true).with_integer(0x4f4e));
+        sent.add_payload(TTLVData::new(2, 2, true).with_string("42".into()));
+        let decoded = received(&sent);
+
+        // Text comes back as the integer its bytes spell, numbers as numbers
+        assert_eq!(decoded.payloads[0].as_integer(), Some(0x4f4e));
+        assert_eq!(decoded.payloads[0].as_enum_string().as_deref(), Some("ON"));
+        assert_eq!(decoded.payloads[1].as_integer(), Some(42));
+    }
+
+    #[test]
+    fn enum_string_needs_printable_positive_integers() {
+        let text = |value| {
+            let mut field = TTLVData::new(1, 2, true);
+            field.value = value;
+            field.as_enum_string()
+        };
+
+        let idle = TTLVValue::String("idle".into());
+        assert_eq!(text(idle).as_deref(), Some("idle"));
+        assert_eq!(text(TTLVValue::Integer(0x20)).as_deref(), Some(" "));
+        assert_eq!(text(TTLVValue::Integer(7)), None);
+        // The wire cannot tell them apart: a number whose bytes are printable reads as text
+        assert_eq!(text(TTLVValue::Integer(42)).as_deref(), Some("*"));
+        assert_eq!(text(TTLVValue::Integer(0)), None);
+        assert_eq!(text(TTLVValue::Integer(-0x4f4e)), None);
+        assert_eq!(text(TTLVValue::Float(1.5)), None);
+    }
+
+    #[test]
+    fn type_name_follows_the_value_not_the_type_id() {
+        let field = |value| TTLVData {
+            value,
+            ..TTLVData::new(1, 7, true)
+        };
+        let cases = [
+            (TTLVValue::None, "none"),
+            (TTLVValue::Boolean(false), "boolean"),
+            (TTLVValue::String("on".into()), "string"),
+            (TTLVValue::Integer(1), "integer"),
+            (TTLVValue::Float(1.5), "float"),
+            (TTLVValue::Binary(vec![1]), "binary"),
+            (TTLVValue::Struct(vec![]), "struct"),
+            (
+                TTLVValue::Raw {
+                    type_id: 7,
+                    bytes: vec![],
+                },
+                "raw",
+            ),
+        ];
+
+        for (value, name) in cases {
+            assert_eq!(field(value).type_name(), name);
+        }
+    }
+
+    #[test]
+    fn transparent_inner_decode_is_cached_and_keeps_the_raw_bytes() {
+        let mut encoder = EncodeTools::new();
+        let fields = [TTLVData::new(1, 2, true).with_integer(5)];
+        let inner = encoder.encode_payload_to_buffer(&fields).unwrap();
+        let frame = encoder
+            .encode_transparent(0x0024, Some(7), inner.clone())
+            .unwrap();
+
+        let Some(DecodeResult::Transparent(model)) =
+            DecodeTools::new().packet_slice(frame.get_cmd_data()).pop()
+        else {
+            panic!("not a transparent frame");
+        };
+
+        let first = model.decode_inner();
+        let second = model.decode_inner();
+
+        assert!(core::ptr::eq(first, second));
+        assert_eq!((first.cmd, first.packet_id), (0x0024, 7));
+        assert_eq!(first.payloads.len(), 1);
+        assert!(matches!(first.payloads[0].value, TTLVValue::Integer(5)));
+        assert_eq!(model.payloads, inner);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn decoded_payloads_round_trip_through_json() {
+        let ssid = TTLVData::new(1, 3, true).with_binary(b"home".to_vec());
+        let sent = TtlvCommandModel::builder(Cmd::WifiPair, 0)
+            .boolean(1, true)
+            .integer(2, -5)
+            .float(3, 2.5)
+            .binary(4, vec![0xaa, 0x00, 0xff])
+            .structure(5, vec![ssid])
+            .build();
+        let payloads = received(&sent).payloads;
+
+        let json = serde_json::to_string(&payloads).unwrap();
+        let back: Vec<TTLVData> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(back, payloads);
+        // Binary data travels as base64, the variant as a tag
+        let binary = r#""type":"Binary","value":"qgD/""#;
+        assert!(json.contains(binary), "{}", json);
+    }
+}
@@ -0,0 +1,283 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::commands::{Cmd, IotCmd, TtlvCommandModel};
+use crate::ttlv::model::{TTLVData, TTLVValue};
+
+/// Outcome of switching the device to another access point
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchResult {
+    pub connected: bool,
+    pub ip: Option<String>,
+    pub error: Option<i64>,
+}
+
+/// Interpret a `ReadDeviceSwitchWifiAck` (0x7056) response.
+///
+/// Field layout (assumed, not confirmed against a capture):
+/// - id 1, boolean: whether the device joined the new AP
+/// - id 2, binary: IP address obtained, as text
+/// - id 3, numeric: error code when the switch failed
+///
+/// If id 1 is missing, the presence of an IP address is taken as success.
+/// Returns `None` if the model is not a switch-wifi ack
+pub fn parse_switch_ack(model: &TtlvCommandModel) -> Option<SwitchResult> {
+    if IotCmd::from_i32(model.cmd) != Some(IotCmd::ReadDeviceSwitchWifiAck) {
+        return None;
+    }
+
+    let ip = model
+        .binary_payload(2)
+        .map(|data| String::from_utf8_lossy(data).to_string());
+    let error = model.integer_payload(3);
+    let connected = model
+        .payload(1)
+        .and_then(|payload| payload.as_boolean())
+        .unwrap_or(ip.is_some());
+
+    Some(SwitchResult {
+        connected,
+        ip,
+        error,
+    })
+}
+
+/// Connect timeout `WifiPairConfig` sends unless told otherwise, what this tool always used
+pub const DEFAULT_CONNECT_TIMEOUT_SEC: u32 = 30;
+/// Keepalive `WifiPairConfig` sends unless told otherwise, what this tool always used
+pub const DEFAULT_KEEPALIVE_SEC: u32 = 380;
+
+/// `WifiPair` (0x7010) request: the access point to join and the MQTT broker to use.
+///
+/// Field layout:
+/// - id 1, binary: SSID
+/// - id 2, binary: password
+/// - id 11, numeric: seconds to wait for the AP connection
+/// - id 12, numeric: MQTT keepalive in seconds
+/// - id 13, binary: MQTT broker URL
+///
+/// The meaning of 11 and 12 is inferred from their values: this tool has sent 30 and 380,
+/// a captured app request carried 120 and 255
+///
+/// ```
+/// use unquec_model::wifi::{WifiPairConfig, WifiPairConfigError};
+///
+/// let model = WifiPairConfig::new("home", "secret", "mqtt://10.0.0.2:1883")
+///     .build_command(1001)
+///     .unwrap();
+/// assert_eq!(model.integer_payload(11), Some(30));
+/// assert_eq!(model.integer_payload(12), Some(380));
+/// assert_eq!(model.binary_payload(13).unwrap(), b"mqtt://10.0.0.2:1883");
+///
+/// let mut config = WifiPairConfig::new("home", "secret", "mqtt://10.0.0.2:1883");
+/// config.connect_timeout_sec = 0;
+/// assert_eq!(
+///     config.build_command(1001).unwrap_err(),
+///     WifiPairConfigError::ConnectTimeoutOutOfRange(0)
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiPairConfig {
+    pub ssid: String,
+    pub password: String,
+    pub mqtt_url: String,
+    /// Field 11, `CONNECT_TIMEOUT_RANGE`
+    pub connect_timeout_sec: u32,
+    /// Field 12, `KEEPALIVE_RANGE`
+    pub keepalive_sec: u32,
+}
+
+/// Rejected `WifiPairConfig` values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WifiPairConfigError {
+    ConnectTimeoutOutOfRange(u32),
+    KeepaliveOutOfRange(u32),
+}
+
+impl WifiPairConfig {
+    /// Connect timeouts accepted by `build_command`
+    pub const CONNECT_TIMEOUT_RANGE: core::ops::RangeInclusive<u32> = 1..=600;
+    /// Keepalives accepted by `build_command`, MQTT keepalive is a 16-bit field
+    pub const KEEPALIVE_RANGE: core::ops::RangeInclusive<u32> = 10..=65535;
+
+    /// Default timeouts
+    pub fn new(
+        ssid: impl Into<String>,
+        password: impl Into<String>,
+        mqtt_url: impl Into<String>,
+    ) -> Self {
+        Self {
+            ssid: ssid.into(),
+            password: password.into(),
+            mqtt_url: mqtt_url.into(),
+            connect_timeout_sec: DEFAULT_CONNECT_TIMEOUT_SEC,
+            keepalive_sec: DEFAULT_KEEPALIVE_SEC,
+        }
+    }
+
+    pub fn build_command(&self, packet_id: i32) -> Result<TtlvCommandModel, WifiPairConfigError> {
+        if !Self::CONNECT_TIMEOUT_RANGE.contains(&self.connect_timeout_sec) {
+            return Err(WifiPairConfigError::ConnectTimeoutOutOfRange(
+                self.connect_timeout_sec,
+            ));
+        }
+        if !Self::KEEPALIVE_RANGE.contains(&self.keepalive_sec) {
+            return Err(WifiPairConfigError::KeepaliveOutOfRange(self.keepalive_sec));
+        }
+
+        Ok(TtlvCommandModel::builder(Cmd::WifiPair, packet_id)
+            .string(1, self.ssid.as_str())
+            .string(2, self.password.as_str())
+            .integer(11, self.connect_timeout_sec as i64)
+            .integer(12, self.keepalive_sec as i64)
+            .string(13, self.mqtt_url.as_str())
+            .build())
+    }
+}
+
+/// `WifiScan` (0x7012) request, answered with a `WifiScanResp` listing nearby networks
+pub fn build_wifi_scan_command(packet_id: i32) -> TtlvCommandModel {
+    TtlvCommandModel::new(Cmd::WifiScan.as_i32(), packet_id)
+}
+
+/// SSIDs from a `WifiScanResp` (0x7013) response, in the order the device sent them.
+///
+/// The device answers with a struct (id 51) of binary (type 3) entries, one per network;
+/// binary entries at the top level are accepted as well. Empty entries (hidden networks)
+/// are skipped. Returns an empty list if the model is not a scan response
+///
+/// ```
+/// use unquec_model::ttlv::decode::{DecodeResult, DecodeTools};
+/// use unquec_model::wifi::parse_wifi_scan_resp;
+///
+/// let frame = vec![
+///     0xaa, 0xaa, 0x00, 0xca, 0x71, 0x03, 0xe8, 0x70, 0x13, 0x01, 0x9c, 0x00, 0x10, 0x00, 0x03,
+///     0x00, 0x09, 0x58, 0x61, 0x74, 0x61, 0x32, 0x39, 0x30, 0x2e, 0x32, 0x00, 0x03, 0x00, 0x07,
+///     0x63, 0x61, 0x6c, 0x79, 0x6e, 0x6b, 0x61, 0x00, 0x03, 0x00, 0x0c, 0x54, 0x50, 0x2d, 0x4c,
+///     0x69, 0x6e, 0x6b, 0x5f, 0x41, 0x34, 0x33, 0x34, 0x00, 0x03, 0x00, 0x0c, 0x54, 0x50, 0x2d,
+///     0x4c, 0x69, 0x6e, 0x6b, 0x5f, 0x32, 0x37, 0x34, 0x36, 0x00, 0x03, 0x00, 0x0d, 0x4d, 0x65,
+///     0x67, 0x61, 0x57, 0x49, 0x46, 0x49, 0x5f, 0x32, 0x47, 0x48, 0x7a, 0x00, 0x03, 0x00, 0x00,
+///     0x00, 0x03, 0x00, 0x08, 0x4e, 0x45, 0x57, 0x5f, 0x57, 0x49, 0x46, 0x49, 0x00, 0x03, 0x00,
+///     0x0b, 0x58, 0x69, 0x61, 0x6f, 0x6d, 0x69, 0x5f, 0x39, 0x41, 0x34, 0x31, 0x00, 0x03, 0x00,
+///     0x0e, 0x58, 0x69, 0x61, 0x6f, 0x6d, 0x69, 0x20, 0x31, 0x31, 0x20, 0x4c, 0x69, 0x74, 0x65,
+///     0x00, 0x03, 0x00, 0x03, 0x4b, 0x4e, 0x53, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0x00, 0x0c,
+///     0x54, 0x50, 0x2d, 0x4c, 0x49, 0x4e, 0x4b, 0x5f, 0x41, 0x38, 0x32, 0x38, 0x00, 0x03, 0x00,
+///     0x00, 0x00, 0x03, 0x00, 0x08, 0x54, 0x65, 0x6e, 0x64, 0x61, 0x5f, 0x35, 0x37, 0x00, 0x03,
+///     0x00, 0x08, 0x56, 0x65, 0x72, 0x4e, 0x65, 0x74, 0x5f, 0x32, 0x00, 0x03, 0x00, 0x0c, 0x54,
+///     0x50, 0x2d, 0x4c, 0x49, 0x4e, 0x4b, 0x5f, 0x43, 0x41, 0x34, 0x38,
+/// ];
+///
+/// let Some(DecodeResult::Success(model)) = DecodeTools::new().packet_slice(&frame).pop() else {
+///     panic!("sample did not decode");
+/// };
+///
+/// assert_eq!(
+///     parse_wifi_scan_resp(&model),
+///     [
+///         "Xata290.2", "calynka", "TP-Link_A434", "TP-Link_2746", "MegaWIFI_2GHz",
+///         "NEW_WIFI", "Xiaomi_9A41", "Xiaomi 11 Lite", "KNS", "TP-LINK_A828", "Tenda_57",
+///         "VerNet_2", "TP-LINK_CA48",
+///     ]
+/// );
+/// ```
+pub fn parse_wifi_scan_resp(model: &TtlvCommandModel) -> Vec<String> {
+    if Cmd::from_i32(model.cmd) != Some(Cmd::WifiScanResp) {
+        return Vec::new();
+    }
+
+    let mut ssids = Vec::new();
+    for payload in &model.payloads {
+        match &payload.value {
+            TTLVValue::Struct(entries) => ssids.extend(entries.iter().filter_map(ssid)),
+            _ => ssids.extend(ssid(payload)),
+        }
+    }
+    ssids
+}
+
+fn ssid(entry: &TTLVData) -> Option<String> {
+    entry
+        .as_binary()
+        .filter(|data| !data.is_empty())
+        .map(|data| String::from_utf8_lossy(data).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ttlv::decode::{DecodeResult, DecodeTools};
+    use crate::ttlv::encode::EncodeTools;
+    use crate::ttlv::model::TTLVData;
+
+    fn received(model: &TtlvCommandModel) -> TtlvCommandModel {
+        let frame = EncodeTools::new().start_encode(model).unwrap();
+        match DecodeTools::new().packet_slice(frame.get_cmd_data()).pop() {
+            Some(DecodeResult::Success(model)) => model,
+            other => panic!("unexpected decode: {:?}", other),
+        }
+    }
+
+    fn switch_ack(payloads: Vec<TTLVData>) -> TtlvCommandModel {
+        let mut model = TtlvCommandModel::new(IotCmd::ReadDeviceSwitchWifiAck.as_i32(), 0);
+        for payload in payloads {
+            model.add_payload(payload);
+        }
+        model
+    }
+
+    fn status(connected: bool) -> TTLVData {
+        TTLVData::new(1, connected as i32, true).with_boolean(connected)
+    }
+
+    fn ip(address: &str) -> TTLVData {
+        TTLVData::new(2, 3, true).with_binary(address.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn switch_ack_with_ip_is_connected() {
+        let ack = switch_ack(vec![status(true), ip("192.168.1.23")]);
+
+        assert_eq!(
+            parse_switch_ack(&received(&ack)),
+            Some(SwitchResult {
+                connected: true,
+                ip: Some("192.168.1.23".to_string()),
+                error: None,
+            })
+        );
+    }
+
+    #[test]
+    fn switch_nack_carries_the_error_code() {
+        let nack = switch_ack(vec![
+            status(false),
+            TTLVData::new(3, 2, true).with_integer(202),
+        ]);
+
+        assert_eq!(
+            parse_switch_ack(&received(&nack)),
+            Some(SwitchResult {
+                connected: false,
+                ip: None,
+                error: Some(202),
+            })
+        );
+    }
+
+    #[test]
+    fn switch_ack_without_status_goes_by_the_ip() {
+        let with_ip = switch_ack(vec![ip("10.0.0.5")]);
+        let without = switch_ack(Vec::new());
+
+        assert!(parse_switch_ack(&received(&with_ip)).unwrap().connected);
+        assert!(!parse_switch_ack(&received(&without)).unwrap().connected);
+    }
+
+    #[test]
+    fn other_commands_are_not_switch_acks() {
+        let mut switch = TtlvCommandModel::new(IotCmd::ReadDeviceSwitchWifi.as_i32(), 0);
+        switch.add_payload(status(true));
+
+        assert_eq!(parse_switch_ack(&switch), None);
+    }
+}
@@ -23,4 +23,4 @@ async fn main() {
         .build()
         .run()
         .await;
-}
\ No newline at end of file
+}